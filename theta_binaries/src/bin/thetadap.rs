@@ -0,0 +1,11 @@
+use theta::dap::DebugAdapter;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    env_logger::init();
+
+    // stdout carries the protocol, so logging has to go to stderr (env_logger's default)
+    let mut adapter = DebugAdapter::new(std::io::stdin().lock(), std::io::stdout().lock());
+    adapter.serve()?;
+
+    Ok(())
+}
@@ -0,0 +1,57 @@
+use clap::Parser as ClapParser;
+use theta_types::bytecode::{Assembler, BasicAssembler, BitstreamDisassembler, Disassembler, Linker, LinkOptions};
+use std::fs::File;
+use std::io::Read;
+
+#[derive(ClapParser)]
+#[clap(version = "0.0.1", author = "Evan Merlock")]
+struct ThetaLinkOptions {
+    #[clap(required = true)]
+    in_files: Vec<String>,
+    #[clap(short, long)]
+    out_file: Option<String>,
+    /// Drop functions that cannot be reached from an entry point
+    #[clap(short, long)]
+    strip_unused: bool,
+    /// Functions to keep when stripping (defaults to main)
+    #[clap(short, long)]
+    entry: Vec<String>,
+    #[clap(short, long, parse(from_occurrences))]
+    verbose: i32,
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    env_logger::init();
+    let options = ThetaLinkOptions::parse();
+
+    let mut linker = Linker::new(LinkOptions { strip_unused: options.strip_unused, entry_points: options.entry });
+
+    for in_file in &options.in_files {
+        let mut buffer = Vec::new();
+        File::open(in_file)?.read_to_end(&mut buffer)?;
+
+        let mut disassembler = BitstreamDisassembler::new();
+        linker.add_unit(disassembler.disassemble(&buffer)?);
+    }
+
+    let linked = match linker.link() {
+        Ok(linked) => linked,
+        Err(errors) => {
+            for error in &errors {
+                eprintln!("link error: {}", error);
+            }
+            std::process::exit(1);
+        },
+    };
+
+    let mut out_file: Box<dyn std::io::Write> = match options.out_file {
+        Some(out_file) => Box::new(File::create(out_file)?),
+        None => Box::new(std::io::stdout()),
+    };
+
+    let mut assembler = BasicAssembler::new(&mut out_file);
+    assembler.assemble_bitstream(linked)?;
+    out_file.flush()?;
+
+    Ok(())
+}
@@ -0,0 +1,11 @@
+use theta::lsp::LanguageServer;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    env_logger::init();
+
+    // stdout carries the protocol, so logging has to go to stderr (env_logger's default)
+    let mut server = LanguageServer::new(std::io::stdin().lock(), std::io::stdout().lock());
+    server.serve()?;
+
+    Ok(())
+}
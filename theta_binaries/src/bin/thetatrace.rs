@@ -0,0 +1,59 @@
+use clap::{Parser as ClapParser, Subcommand};
+use theta::{program::Program, trace::TraceSummary};
+use theta_vm::vm::{VM, Tracer, DEFAULT_TRACE_STACK_VALUES};
+use std::{cell::RefCell, fs::File, io::BufReader, rc::Rc};
+
+#[derive(ClapParser)]
+#[clap(version = "0.0.1", author = "Evan Merlock")]
+struct ThetaTraceOptions {
+    #[clap(subcommand)]
+    command: TraceCommand,
+}
+
+#[derive(Subcommand)]
+enum TraceCommand {
+    /// Runs a source file, writing a JSON Lines record of every instruction it executes
+    Record {
+        in_file: String,
+        #[clap(short, long, default_value = "trace.jsonl")]
+        out_file: String,
+        /// How many values from the top of the stack to record with each instruction
+        #[clap(short, long, default_value_t = DEFAULT_TRACE_STACK_VALUES)]
+        stack_values: usize,
+    },
+    /// Summarises a trace written by `record`
+    Summary {
+        in_file: String,
+        /// How many entries of each table to show
+        #[clap(short, long, default_value_t = 10)]
+        top: usize,
+    },
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    env_logger::init();
+    let options = ThetaTraceOptions::parse();
+
+    match options.command {
+        TraceCommand::Record { in_file, out_file, stack_values } => {
+            let program = Program::compile(&std::fs::read_to_string(in_file)?)?;
+
+            let mut machine = VM::new(Box::new(std::io::stdout()));
+            let tracer = Rc::new(RefCell::new(Tracer::new(Box::new(File::create(out_file)?)).with_stack_values(stack_values)));
+            machine.add_observer(Box::new(tracer.clone()));
+            program.load(&mut machine)?;
+            let result = machine.execute_code();
+
+            // the trace leading up to a runtime error is the interesting part, so it is kept either way
+            tracer.borrow_mut().finish()?;
+            result?;
+        },
+        TraceCommand::Summary { in_file, top } => {
+            let mut summary = TraceSummary::from_reader(BufReader::new(File::open(in_file)?))?;
+            summary.top = top;
+            print!("{}", summary);
+        },
+    }
+
+    Ok(())
+}
@@ -0,0 +1,214 @@
+use std::{cell::RefCell, collections::BTreeMap, io::Write, rc::Rc, time::{SystemTime, UNIX_EPOCH}};
+
+use theta_types::bytecode::ThetaValue;
+use theta_vm::vm::{VM, VmObserver, ObserverId, LoadedFunction, Instruction};
+
+use crate::program::SCRIPT_FUNCTION;
+
+/// Coverage of a single function.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionCoverage {
+    pub name: String,
+    /// Every line the function has code on, in order.
+    pub lines: Vec<usize>,
+    pub calls: u64,
+}
+
+/// Which lines of a source file executed, and how often.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Coverage {
+    /// Every line with code on it, with the number of times execution reached it.
+    pub lines: BTreeMap<usize, u64>,
+    /// Every function with debug info, in order of their first line.
+    pub functions: Vec<FunctionCoverage>,
+}
+
+impl Coverage {
+    /// Covers every function loaded into `machine`. Functions without debug info have no lines to cover.
+    pub fn for_machine(machine: &VM) -> Coverage {
+        let mut coverage = Coverage::default();
+
+        for func in machine.functions().iter().flatten() {
+            let mut lines: Vec<usize> = func.lines.iter().map(|(_, line)| *line).collect();
+            if lines.is_empty() {
+                continue;
+            }
+            lines.sort_unstable();
+            lines.dedup();
+
+            for line in &lines {
+                coverage.lines.insert(*line, 0);
+            }
+            coverage.functions.push(FunctionCoverage { name: func.function.name.internal().to_string(), lines, calls: 0 });
+        }
+
+        coverage.functions.sort_by_key(|func| func.lines[0]);
+        coverage
+    }
+
+    pub fn lines_hit(&self) -> usize {
+        self.lines.values().filter(|hits| **hits > 0).count()
+    }
+
+    /// The fraction of lines that executed, from 0 to 1. A file without code is fully covered.
+    pub fn line_rate(&self) -> f64 {
+        rate(self.lines_hit(), self.lines.len())
+    }
+
+    /// Functions written in the source, leaving out the one holding the top level code.
+    fn source_functions(&self) -> impl Iterator<Item = &FunctionCoverage> {
+        self.functions.iter().filter(|func| func.name != SCRIPT_FUNCTION)
+    }
+
+    fn function_line_rate(&self, func: &FunctionCoverage) -> f64 {
+        rate(func.lines.iter().filter(|line| self.lines.get(line).is_some_and(|hits| *hits > 0)).count(), func.lines.len())
+    }
+
+    /// Writes the coverage of `source` as an lcov tracefile.
+    pub fn write_lcov(&self, out: &mut impl Write, source: &str) -> std::io::Result<()> {
+        writeln!(out, "TN:")?;
+        writeln!(out, "SF:{}", source)?;
+
+        // the line declaring a function has no code on it, so functions are located by their first line of code
+        for func in self.source_functions() {
+            writeln!(out, "FN:{},{}", func.lines[0], func.name)?;
+        }
+        for func in self.source_functions() {
+            writeln!(out, "FNDA:{},{}", func.calls, func.name)?;
+        }
+        writeln!(out, "FNF:{}", self.source_functions().count())?;
+        writeln!(out, "FNH:{}", self.source_functions().filter(|func| func.calls > 0).count())?;
+
+        for (line, hits) in &self.lines {
+            writeln!(out, "DA:{},{}", line, hits)?;
+        }
+        writeln!(out, "LF:{}", self.lines.len())?;
+        writeln!(out, "LH:{}", self.lines_hit())?;
+        writeln!(out, "end_of_record")
+    }
+
+    /// Writes the coverage of `source` as Cobertura XML. Branches are not tracked, so every branch rate is 0.
+    pub fn write_cobertura(&self, out: &mut impl Write, source: &str) -> std::io::Result<()> {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs());
+        let path = std::path::Path::new(source);
+        let directory = path.parent().map(|dir| dir.display().to_string()).unwrap_or_default();
+        let class = path.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_else(|| source.to_string());
+
+        writeln!(out, "<?xml version=\"1.0\" ?>")?;
+        writeln!(out, "<!DOCTYPE coverage SYSTEM \"http://cobertura.sourceforge.net/xml/coverage-04.dtd\">")?;
+        writeln!(out, "<coverage line-rate=\"{:.4}\" branch-rate=\"0\" lines-covered=\"{}\" lines-valid=\"{}\" branches-covered=\"0\" branches-valid=\"0\" complexity=\"0\" version=\"{}\" timestamp=\"{}\">",
+            self.line_rate(), self.lines_hit(), self.lines.len(), env!("CARGO_PKG_VERSION"), timestamp)?;
+        writeln!(out, "  <sources>")?;
+        writeln!(out, "    <source>{}</source>", escape_xml(&directory))?;
+        writeln!(out, "  </sources>")?;
+        writeln!(out, "  <packages>")?;
+        writeln!(out, "    <package name=\"{}\" line-rate=\"{:.4}\" branch-rate=\"0\" complexity=\"0\">", escape_xml(&class), self.line_rate())?;
+        writeln!(out, "      <classes>")?;
+        writeln!(out, "        <class name=\"{}\" filename=\"{}\" line-rate=\"{:.4}\" branch-rate=\"0\" complexity=\"0\">", escape_xml(&class), escape_xml(source), self.line_rate())?;
+
+        writeln!(out, "          <methods>")?;
+        for func in self.source_functions() {
+            writeln!(out, "            <method name=\"{}\" signature=\"\" line-rate=\"{:.4}\" branch-rate=\"0\" complexity=\"0\">", escape_xml(&func.name), self.function_line_rate(func))?;
+            writeln!(out, "              <lines>")?;
+            for line in &func.lines {
+                writeln!(out, "                <line number=\"{}\" hits=\"{}\" branch=\"false\"/>", line, self.lines[line])?;
+            }
+            writeln!(out, "              </lines>")?;
+            writeln!(out, "            </method>")?;
+        }
+        writeln!(out, "          </methods>")?;
+
+        writeln!(out, "          <lines>")?;
+        for (line, hits) in &self.lines {
+            writeln!(out, "            <line number=\"{}\" hits=\"{}\" branch=\"false\"/>", line, hits)?;
+        }
+        writeln!(out, "          </lines>")?;
+        writeln!(out, "        </class>")?;
+        writeln!(out, "      </classes>")?;
+        writeln!(out, "    </package>")?;
+        writeln!(out, "  </packages>")?;
+        writeln!(out, "</coverage>")
+    }
+
+    /// Writes `source` with the number of times each line executed in front of it.
+    /// Lines without code are marked `-`, lines that never executed `#####`.
+    pub fn write_annotated(&self, out: &mut impl Write, source: &str) -> std::io::Result<()> {
+        for (idx, text) in source.lines().enumerate() {
+            let line = idx + 1;
+            match self.lines.get(&line) {
+                Some(0) => writeln!(out, "{:>9} | {}", "#####", text)?,
+                Some(hits) => writeln!(out, "{:>9} | {}", hits, text)?,
+                None => writeln!(out, "{:>9} | {}", "-", text)?,
+            }
+        }
+
+        writeln!(out)?;
+        writeln!(out, "lines: {}/{} ({:.2}%)", self.lines_hit(), self.lines.len(), self.line_rate() * 100.0)?;
+        writeln!(out, "functions: {}/{}", self.source_functions().filter(|func| func.calls > 0).count(), self.source_functions().count())
+    }
+}
+
+fn rate(hit: usize, total: usize) -> f64 {
+    if total == 0 {
+        1.0
+    } else {
+        hit as f64 / total as f64
+    }
+}
+
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Collects line coverage from a VM by observing the instructions it executes.
+pub struct CoverageCollector {
+    coverage: Rc<RefCell<Coverage>>,
+    observer: ObserverId,
+}
+
+struct CoverageObserver {
+    coverage: Rc<RefCell<Coverage>>,
+}
+
+impl CoverageCollector {
+    /// Starts collecting coverage of every function loaded into `machine`.
+    /// Load the program first, functions loaded later are not covered.
+    pub fn attach(machine: &mut VM) -> CoverageCollector {
+        let coverage = Rc::new(RefCell::new(Coverage::for_machine(machine)));
+        let observer = machine.add_observer(Box::new(CoverageObserver { coverage: coverage.clone() }));
+        CoverageCollector { coverage, observer }
+    }
+
+    /// Stops collecting and returns the coverage.
+    pub fn finish(self, machine: &mut VM) -> Coverage {
+        // dropping the observer releases its reference to the coverage
+        drop(machine.remove_observer(self.observer));
+        Rc::try_unwrap(self.coverage).map_or_else(|shared| shared.borrow().clone(), RefCell::into_inner)
+    }
+}
+
+impl VmObserver for CoverageObserver {
+    fn on_instruction(&mut self, function: Option<&LoadedFunction>, offset: usize, _instruction: Instruction, _stack: &[ThetaValue]) {
+        let Some(func) = function else {
+            return;
+        };
+        // a line is reached each time the instruction starting it runs. instructions after it, which jumps can land on,
+        // are only attributed to the line because no other line started since, so they are not counted.
+        if func.lines.binary_search_by_key(&offset, |(start, _)| *start).is_err() {
+            return;
+        }
+        let Some(line) = func.line_at(offset) else {
+            return;
+        };
+        if let Some(hits) = self.coverage.borrow_mut().lines.get_mut(&line) {
+            *hits += 1;
+        }
+    }
+
+    fn on_call(&mut self, function: &LoadedFunction, _args: &[ThetaValue], _tail_call: bool) {
+        let name = function.function.name.internal();
+        if let Some(func) = self.coverage.borrow_mut().functions.iter_mut().find(|func| *func.name == **name) {
+            func.calls += 1;
+        }
+    }
+}
@@ -0,0 +1,316 @@
+use std::{cell::RefCell, io::{BufRead, Write}, rc::Rc};
+
+use serde_json::{json, Value};
+use theta_types::bytecode::ThetaValue;
+use theta_vm::vm::{VM, ExecutionStatus, RuntimeError};
+
+use crate::{program::Program, protocol::{read_message, write_message, ProtocolError}};
+
+/// Theta programs run on a single thread, so every thread id the client sees is this one.
+pub const THREAD_ID: i64 = 1;
+
+/// The variables reference of the globals scope. Frame locals use the frame's id plus `FRAME_REFERENCE_BASE`.
+const GLOBALS_REFERENCE: i64 = 1;
+const FRAME_REFERENCE_BASE: i64 = 2;
+
+/// Collects what the debuggee prints so it can be forwarded as `output` events.
+#[derive(Debug, Clone, Default)]
+struct ProgramOutput(Rc<RefCell<Vec<u8>>>);
+
+impl Write for ProgramOutput {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// How execution should proceed after a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    Start,
+    Continue,
+    StepIn,
+    StepOver,
+    StepOut,
+}
+
+/// A Debug Adapter Protocol server debugging a single Theta source file.
+///
+/// Messages are read from `input` and written to `output` with the protocol's `Content-Length` framing.
+/// The program runs on the calling thread, so requests are only handled while it is paused.
+pub struct DebugAdapter<R: BufRead, W: Write> {
+    input: R,
+    output: W,
+    seq: i64,
+    machine: Option<VM>,
+    program_output: ProgramOutput,
+    source_path: Option<String>,
+    breakpoint_lines: Vec<usize>,
+    stop_on_entry: bool,
+}
+
+impl<R: BufRead, W: Write> DebugAdapter<R, W> {
+    pub fn new(input: R, output: W) -> DebugAdapter<R, W> {
+        DebugAdapter {
+            input,
+            output,
+            seq: 1,
+            machine: None,
+            program_output: ProgramOutput::default(),
+            source_path: None,
+            breakpoint_lines: Vec::new(),
+            stop_on_entry: false,
+        }
+    }
+
+    /// Handles requests until the client disconnects or closes the input.
+    pub fn serve(&mut self) -> Result<(), ProtocolError> {
+        while let Some(message) = read_message(&mut self.input)? {
+            if message["type"] != "request" {
+                continue;
+            }
+
+            let command = message["command"].as_str().unwrap_or_default().to_string();
+            let arguments = message.get("arguments").cloned().unwrap_or(Value::Null);
+            let action = match self.handle_request(&command, &arguments) {
+                Ok((body, action)) => {
+                    self.respond(&message, Ok(body))?;
+                    action
+                },
+                Err(error) => {
+                    self.respond(&message, Err(error))?;
+                    None
+                },
+            };
+
+            match command.as_str() {
+                "initialize" => self.event("initialized", Value::Null)?,
+                "disconnect" | "terminate" => return Ok(()),
+                _ => {},
+            }
+
+            if let Some(action) = action {
+                self.run(action)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the response body and, for requests that resume the program, how it should run.
+    fn handle_request(&mut self, command: &str, arguments: &Value) -> Result<(Value, Option<Action>), String> {
+        Ok(match command {
+            "initialize" => (json!({ "supportsConfigurationDoneRequest": true }), None),
+            "launch" => {
+                self.launch(arguments)?;
+                (Value::Null, None)
+            },
+            "setBreakpoints" => (json!({ "breakpoints": self.set_breakpoints(arguments) }), None),
+            "setExceptionBreakpoints" => (json!({ "breakpoints": [] }), None),
+            "configurationDone" => {
+                if self.machine.is_none() {
+                    return Err(String::from("no program has been launched"));
+                }
+                (Value::Null, Some(Action::Start))
+            },
+            "threads" => (json!({ "threads": [{ "id": THREAD_ID, "name": "main" }] }), None),
+            "stackTrace" => {
+                let frames = self.stack_frames()?;
+                (json!({ "totalFrames": frames.len(), "stackFrames": frames }), None)
+            },
+            "scopes" => (json!({ "scopes": self.scopes(arguments)? }), None),
+            "variables" => (json!({ "variables": self.variables(arguments)? }), None),
+            "continue" => (json!({ "allThreadsContinued": true }), Some(Action::Continue)),
+            "next" => (Value::Null, Some(Action::StepOver)),
+            "stepIn" => (Value::Null, Some(Action::StepIn)),
+            "stepOut" => (Value::Null, Some(Action::StepOut)),
+            // the program only runs while a request is being handled, so it is always paused here
+            "pause" => (Value::Null, None),
+            "disconnect" | "terminate" => (Value::Null, None),
+            _ => return Err(format!("unsupported request {}", command)),
+        })
+    }
+
+    fn launch(&mut self, arguments: &Value) -> Result<(), String> {
+        let path = arguments["program"].as_str().ok_or("launch requires a program")?;
+        let source = std::fs::read_to_string(path).map_err(|e| format!("could not read {}: {}", path, e))?;
+        let program = Program::compile(&source).map_err(|e| format!("could not compile {}: {}", path, e))?;
+
+        let mut machine = VM::new(Box::new(self.program_output.clone()));
+        program.load(&mut machine).map_err(|e| format!("could not load {}: {}", path, e))?;
+
+        self.machine = Some(machine);
+        self.source_path = Some(path.to_string());
+        self.stop_on_entry = arguments["stopOnEntry"].as_bool().unwrap_or(false);
+
+        // breakpoints may have been set before the program was loaded
+        self.apply_breakpoints();
+        Ok(())
+    }
+
+    /// Replaces every breakpoint, returning which of them could be placed.
+    fn set_breakpoints(&mut self, arguments: &Value) -> Vec<Value> {
+        self.breakpoint_lines = arguments["breakpoints"].as_array().map(|breakpoints| {
+            breakpoints.iter().filter_map(|bp| bp["line"].as_u64()).map(|line| line as usize).collect()
+        }).unwrap_or_default();
+
+        let verified = self.apply_breakpoints();
+        self.breakpoint_lines.iter().zip(verified).map(|(line, verified)| json!({ "verified": verified, "line": line })).collect()
+    }
+
+    /// Sets the requested line breakpoints in the VM, returning whether each line had code on it.
+    /// Until a program is launched no breakpoint can be verified.
+    fn apply_breakpoints(&mut self) -> Vec<bool> {
+        match self.machine.as_mut() {
+            Some(machine) => {
+                machine.clear_breakpoints();
+                self.breakpoint_lines.iter().map(|line| !machine.set_line_breakpoint(*line).is_empty()).collect()
+            },
+            None => vec![false; self.breakpoint_lines.len()],
+        }
+    }
+
+    fn run(&mut self, action: Action) -> Result<(), ProtocolError> {
+        let machine = match self.machine.as_mut() {
+            Some(machine) => machine,
+            None => return Ok(()),
+        };
+
+        let status = match action {
+            Action::Start if self.stop_on_entry => {
+                // starting without fuel pages the program in without running any of it
+                machine.set_fuel(Some(0));
+                let status = machine.execute_code();
+                machine.set_fuel(None);
+                status.map(|_| None)
+            },
+            Action::Start => machine.execute_code().map(Some),
+            Action::Continue => machine.resume().map(Some),
+            Action::StepIn => machine.step_into().map(Some),
+            Action::StepOver => machine.step_over().map(Some),
+            Action::StepOut => machine.step_out().map(Some),
+        };
+
+        self.forward_output()?;
+        match status {
+            Ok(None) => self.stopped("entry"),
+            Ok(Some(ExecutionStatus::Breakpoint)) => self.stopped("breakpoint"),
+            Ok(Some(ExecutionStatus::Stepped)) => self.stopped("step"),
+            Ok(Some(ExecutionStatus::OutOfFuel | ExecutionStatus::DeadlineExceeded)) => self.stopped("pause"),
+            Ok(Some(ExecutionStatus::Finished)) => self.terminate(0),
+            Err(e) => self.runtime_error(e),
+        }
+    }
+
+    fn runtime_error(&mut self, error: RuntimeError) -> Result<(), ProtocolError> {
+        self.event("output", json!({ "category": "stderr", "output": format!("Runtime error: {}\n", error) }))?;
+        self.terminate(1)
+    }
+
+    fn stopped(&mut self, reason: &str) -> Result<(), ProtocolError> {
+        self.event("stopped", json!({ "reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true }))
+    }
+
+    fn terminate(&mut self, exit_code: i64) -> Result<(), ProtocolError> {
+        self.machine = None;
+        self.event("exited", json!({ "exitCode": exit_code }))?;
+        self.event("terminated", Value::Null)
+    }
+
+    fn forward_output(&mut self) -> Result<(), ProtocolError> {
+        let printed = std::mem::take(&mut *self.program_output.0.borrow_mut());
+        if printed.is_empty() {
+            return Ok(());
+        }
+        self.event("output", json!({ "category": "stdout", "output": String::from_utf8_lossy(&printed) }))
+    }
+
+    fn paused_machine(&self) -> Result<&VM, String> {
+        self.machine.as_ref().ok_or_else(|| String::from("the program is not running"))
+    }
+
+    /// Frames are identified by their position on the call stack, innermost first. Only frames in functions are shown.
+    fn stack_frames(&self) -> Result<Vec<Value>, String> {
+        let machine = self.paused_machine()?;
+        let source = self.source();
+
+        Ok(machine.frames().iter().enumerate().filter_map(|(id, frame)| {
+            let name = frame.function.as_ref()?;
+            Some(json!({ "id": id, "name": name.as_str(), "line": frame.line.unwrap_or(0), "column": 0, "source": source }))
+        }).collect())
+    }
+
+    fn scopes(&self, arguments: &Value) -> Result<Vec<Value>, String> {
+        let frame_id = arguments["frameId"].as_i64().ok_or("scopes requires a frameId")?;
+        Ok(vec![
+            json!({ "name": "Locals", "variablesReference": frame_id + FRAME_REFERENCE_BASE, "expensive": false }),
+            json!({ "name": "Globals", "variablesReference": GLOBALS_REFERENCE, "expensive": false }),
+        ])
+    }
+
+    fn variables(&self, arguments: &Value) -> Result<Vec<Value>, String> {
+        let machine = self.paused_machine()?;
+        let reference = arguments["variablesReference"].as_i64().ok_or("variables requires a variablesReference")?;
+
+        if reference == GLOBALS_REFERENCE {
+            let mut globals: Vec<(&str, &ThetaValue)> = machine.globals().into_iter().collect();
+            globals.sort_by_key(|(name, _)| *name);
+            return Ok(globals.into_iter().map(|(name, value)| variable(name, value)).collect());
+        }
+
+        let frames = machine.frames();
+        let frame = usize::try_from(reference - FRAME_REFERENCE_BASE).ok().and_then(|id| frames.get(id)).ok_or("no such frame")?;
+        // locals have no names in the bytecode, so they are named after their slot
+        let arity = frame.function_index.and_then(|function| machine.functions()[function].as_ref()).map_or(0, |func| func.function.args.len());
+        Ok(frame.locals.iter().enumerate().map(|(slot, value)| match slot < arity {
+            true => variable(&format!("arg{}", slot), value),
+            false => variable(&format!("local{}", slot - arity), value),
+        }).collect())
+    }
+
+    fn source(&self) -> Value {
+        match &self.source_path {
+            Some(path) => {
+                let name = std::path::Path::new(path).file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_else(|| path.clone());
+                json!({ "name": name, "path": path })
+            },
+            None => Value::Null,
+        }
+    }
+
+    fn respond(&mut self, request: &Value, result: Result<Value, String>) -> Result<(), ProtocolError> {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": result.is_ok(),
+        });
+        match result {
+            Ok(Value::Null) => {},
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = Value::String(message),
+        }
+        self.send(response)
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> Result<(), ProtocolError> {
+        let mut message = json!({ "type": "event", "event": event });
+        if !body.is_null() {
+            message["body"] = body;
+        }
+        self.send(message)
+    }
+
+    fn send(&mut self, mut message: Value) -> Result<(), ProtocolError> {
+        message["seq"] = json!(self.seq);
+        self.seq += 1;
+        write_message(&mut self.output, &message)
+    }
+}
+
+fn variable(name: &str, value: &ThetaValue) -> Value {
+    json!({ "name": name, "value": format!("{:#}", value), "variablesReference": 0 })
+}
@@ -1 +1,7 @@
 pub mod repl;
+pub mod program;
+pub mod dap;
+pub mod protocol;
+pub mod lsp;
+pub mod trace;
+pub mod coverage;
@@ -0,0 +1,365 @@
+use theta_compiler::{ast::{symbol::{ExtSymbolTable, SymbolData, SymbolTable}, transformers::{typeck::{TypeCk, TypeCkError}, ASTTransformer, TransformError}, AbstractTree, InnerAbstractTree, Expression, Statement, Item}, lexer::{BasicLexer, Lexer, LexerError}, parser::{BasicParser, Parser}};
+use theta_types::{bytecode::{Symbol, Token, TokenType}, errors::parse::ParseError, types::{LocationData, TypeInformation}};
+use theta_compiler::ast::transformers::typeck::TypeCkOutput;
+
+use crate::repl::parser::{ReplParser, ReplItem};
+
+/// A problem found while compiling a document. Locations are character offsets into the source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub location: LocationData,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefinitionKind {
+    Function,
+    Parameter,
+    Variable,
+}
+
+/// A name introduced by a `fun`, a function parameter or a `let`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Definition {
+    pub name: String,
+    pub kind: DefinitionKind,
+    /// The name itself.
+    pub location: LocationData,
+    /// The whole definition. For functions this spans the body.
+    pub extent: LocationData,
+    /// Where the name can be referred to.
+    pub scope: LocationData,
+    pub ty: TypeInformation,
+}
+
+/// Everything the language server knows about one version of a document.
+///
+/// Names are resolved from the tokens, following block scopes, so definitions are available
+/// even when the document does not type check. Types of expressions come from `TypeCk`.
+#[derive(Debug, Default)]
+pub struct Analysis {
+    pub diagnostics: Vec<Diagnostic>,
+    pub definitions: Vec<Definition>,
+    /// Identifiers that refer to a definition, with the index of the definition.
+    pub references: Vec<(LocationData, usize)>,
+    /// The type of every expression that type checked.
+    pub types: Vec<(LocationData, TypeInformation)>,
+}
+
+impl Analysis {
+    pub fn new(source: &str) -> Analysis {
+        let mut analysis = Analysis::default();
+        let end = source.chars().count();
+
+        let mut chars = source.chars();
+        let tokens = match BasicLexer::new(&mut chars).lex() {
+            Ok(tokens) => tokens,
+            Err(e) => {
+                let location = match e {
+                    LexerError::UnterminatedString(_, begin) => LocationData::new(begin.saturating_sub(1), end),
+                    _ => LocationData::new(0, 0),
+                };
+                analysis.diagnostics.push(Diagnostic { location, message: e.to_string() });
+                return analysis;
+            },
+        };
+
+        analysis.resolve_names(tokens.output(), end);
+        analysis.type_check(tokens.output());
+        analysis
+    }
+
+    /// The definition of the name at `offset`, whether `offset` is on the definition itself or on a reference to it.
+    pub fn definition_at(&self, offset: usize) -> Option<&Definition> {
+        let contains = |location: &LocationData| location.begin() <= offset && offset < location.end();
+
+        self.definitions.iter().find(|def| contains(&def.location))
+            .or_else(|| self.references.iter().find(|(location, _)| contains(location)).map(|(_, def)| &self.definitions[*def]))
+    }
+
+    /// The type of the innermost expression at `offset`.
+    pub fn type_at(&self, offset: usize) -> Option<&TypeInformation> {
+        self.types.iter()
+            .filter(|(location, _)| location.begin() <= offset && offset < location.end())
+            .min_by_key(|(location, _)| location.end() - location.begin())
+            .map(|(_, ty)| ty)
+    }
+
+    /// Every definition that can be referred to at `offset`. Shadowed definitions are left out.
+    pub fn visible_at(&self, offset: usize) -> Vec<&Definition> {
+        let mut visible: Vec<&Definition> = Vec::new();
+        for def in self.definitions.iter().filter(|def| def.scope.begin() <= offset && offset <= def.scope.end()) {
+            // definitions are in source order, so later ones shadow earlier ones
+            visible.retain(|other| other.name != def.name);
+            visible.push(def);
+        }
+        visible
+    }
+
+    pub fn functions(&self) -> impl Iterator<Item = &Definition> {
+        self.definitions.iter().filter(|def| def.kind == DefinitionKind::Function)
+    }
+
+    /// Finds every definition and resolves each identifier to the definition in scope where it is used.
+    fn resolve_names(&mut self, tokens: &[Token], end: usize) {
+        // each scope is the index of the token opening it and the definitions made in it
+        let mut scopes: Vec<(usize, Vec<usize>)> = vec![(0, Vec::new())];
+        // parameters are declared before the block they belong to opens
+        let mut parameters: Vec<usize> = Vec::new();
+        // the function whose body is opened by the next brace
+        let mut function: Option<usize> = None;
+        // functions whose body is open, with the depth of their body's scope
+        let mut bodies: Vec<(usize, usize)> = Vec::new();
+
+        let ident = |idx: usize| tokens.get(idx).and_then(|token| match token.ty() {
+            TokenType::Identifier(name) => Some(name),
+            _ => None,
+        });
+
+        let mut idx = 0;
+        while idx < tokens.len() {
+            let token = &tokens[idx];
+            match token.ty() {
+                TokenType::Fun if ident(idx + 1).is_some() => {
+                    let name_token = &tokens[idx + 1];
+                    let (args, return_ty, params_end) = function_signature(tokens, idx + 2);
+
+                    self.definitions.push(Definition {
+                        name: ident(idx + 1).expect("checked above"),
+                        kind: DefinitionKind::Function,
+                        location: name_token.location(),
+                        extent: token.location(),
+                        // functions can be called from anywhere in the file
+                        scope: LocationData::new(0, end),
+                        ty: TypeInformation::Function(Box::new(return_ty), args.iter().map(|(_, ty)| ty.clone()).collect()),
+                    });
+                    let def = self.definitions.len() - 1;
+                    scopes[0].1.push(def);
+                    function = Some(def);
+
+                    for (param, ty) in args {
+                        let location = tokens[param].location();
+                        self.definitions.push(Definition { name: ident(param).expect("parameters are identifiers"), kind: DefinitionKind::Parameter, location: location.clone(), extent: location.clone(), scope: location, ty });
+                        parameters.push(self.definitions.len() - 1);
+                    }
+
+                    idx = params_end;
+                    continue;
+                },
+                TokenType::Let if ident(idx + 1).is_some() => {
+                    let name_token = &tokens[idx + 1];
+                    let ty = match (tokens.get(idx + 2).map(Token::ty), ident(idx + 3)) {
+                        (Some(TokenType::Colon), Some(ty)) => resolve_type(ty),
+                        _ => TypeInformation::None,
+                    };
+                    let statement_end = tokens[idx..].iter().find(|t| t.ty() == TokenType::Semicolon).unwrap_or(name_token).location();
+
+                    self.definitions.push(Definition {
+                        name: ident(idx + 1).expect("checked above"),
+                        kind: DefinitionKind::Variable,
+                        location: name_token.location(),
+                        extent: token.location().merge(statement_end),
+                        scope: LocationData::new(name_token.location().end(), end),
+                        ty,
+                    });
+                    scopes.last_mut().expect("the top level scope is never closed").1.push(self.definitions.len() - 1);
+                    idx += 2;
+                    continue;
+                },
+                TokenType::LeftBrace => {
+                    scopes.push((idx, std::mem::take(&mut parameters)));
+                    for param in &scopes.last().expect("just pushed").1 {
+                        self.definitions[*param].scope = LocationData::new(token.location().begin(), end);
+                    }
+                    if let Some(def) = function.take() {
+                        bodies.push((def, scopes.len()));
+                    }
+                },
+                TokenType::RightBrace if scopes.len() > 1 => {
+                    let (_, defs) = scopes.pop().expect("checked above");
+                    for def in defs {
+                        let scope = self.definitions[def].scope.clone();
+                        self.definitions[def].scope = LocationData::new(scope.begin(), token.location().end());
+                    }
+                    if bodies.last().is_some_and(|(_, depth)| *depth == scopes.len() + 1) {
+                        let (def, _) = bodies.pop().expect("checked above");
+                        let extent = self.definitions[def].extent.clone();
+                        self.definitions[def].extent = extent.merge(token.location());
+                    }
+                },
+                TokenType::Identifier(name) => {
+                    let resolved = scopes.iter().rev().flat_map(|(_, defs)| defs.iter().rev()).find(|def| self.definitions[**def].name == name);
+                    if let Some(def) = resolved {
+                        self.references.push((token.location(), *def));
+                    }
+                },
+                _ => {},
+            }
+            idx += 1;
+        }
+    }
+
+    /// Type checks every item the way the compiler does, recording the type of each expression and any errors.
+    fn type_check(&mut self, tokens: &[Token]) {
+        let tbl = ExtSymbolTable::default();
+        let parser = ReplParser::new(BasicParser::new_sym(tokens, tbl.clone()));
+        let trees = match parser.parse() {
+            Ok(trees) => trees,
+            Err(e) => {
+                let location = match &e {
+                    ParseError::TokenError { token, msg: _ } => token.location(),
+                    ParseError::Other { msg: _ } => tokens.last().map(Token::location).unwrap_or(LocationData::new(0, 0)),
+                };
+                self.diagnostics.push(Diagnostic { location, message: e.to_string() });
+                return;
+            },
+        };
+
+        for item in trees {
+            match item {
+                ReplItem::ParserItem(pi) => {
+                    let location = pi.information().location_data.clone();
+                    // the parser put the function in the table, so other items can call it even if its body does not type check
+                    match TypeCk::new(pi.information().current_symbol_table.clone()).transform_item(&pi) {
+                        Ok(Item::Function(func)) => self.visit_tree(&func.chunk),
+                        Err(e) => self.type_error(e, location),
+                    }
+                },
+                ReplItem::Declaration(decl) => {
+                    let location = decl.information().location_data.clone();
+                    match TypeCk::new(decl.information().current_symbol_table.clone()).transform_tree(&decl) {
+                        Ok(tree) => self.visit_tree(&tree),
+                        Err(e) => self.type_error(e, location),
+                    }
+                },
+            }
+        }
+    }
+
+    /// Errors that carry a token are reported on it, the rest on the whole item that failed.
+    fn type_error(&mut self, error: TransformError, item: LocationData) {
+        let location = match &error {
+            TransformError::TypeCkError(TypeCkError::ExpressionBinaryTypeCkFail(_, _, token) | TypeCkError::ExpressionUnaryTypeCkFail(_, token) | TypeCkError::InvalidLiteralInPosition(token)) => token.location(),
+            _ => item,
+        };
+        self.diagnostics.push(Diagnostic { location, message: error.to_string() });
+    }
+
+    fn visit_tree(&mut self, tree: &AbstractTree<TypeCkOutput>) {
+        match tree.inner() {
+            InnerAbstractTree::Expression((expr, _)) => self.visit_expression(expr),
+            InnerAbstractTree::Statement((stmt, _)) => self.visit_statement(stmt),
+        }
+    }
+
+    fn visit_statement(&mut self, stmt: &Statement<TypeCkOutput>) {
+        match stmt {
+            Statement::ExpressionStatement { expression, information: _ }
+            | Statement::PrintStatement { expression, information: _ }
+            | Statement::VarStatement { ident: _, init: expression, information: _ }
+            | Statement::Partial { expression, information: _ } => self.visit_expression(expression),
+            Statement::BreakpointStatement { information: _ } => {},
+        }
+    }
+
+    fn visit_expression(&mut self, expr: &Expression<TypeCkOutput>) {
+        let information = expr.information();
+        self.types.push((information.pi.location_data.clone(), information.ty.clone()));
+
+        match expr {
+            Expression::Binary { left, operator: _, right, information: _ } => {
+                self.visit_expression(left);
+                self.visit_expression(right);
+            },
+            Expression::Unary { operator: _, right, information: _ } => self.visit_expression(right),
+            Expression::Literal { literal: _, information: _ } => {},
+            Expression::Sequence { seq, information: _ } => seq.iter().for_each(|expr| self.visit_expression(expr)),
+            Expression::Assignment { name: _, value, information: _ } => self.visit_expression(value),
+            Expression::If { check_expression, body, else_body, information: _ } => {
+                self.visit_expression(check_expression);
+                self.visit_expression(body);
+                if let Some(else_body) = else_body {
+                    self.visit_expression(else_body);
+                }
+            },
+            Expression::BlockExpression { statements, final_expression, information: _ } => {
+                statements.iter().for_each(|stmt| self.visit_statement(stmt));
+                if let Some(final_expression) = final_expression {
+                    self.visit_expression(final_expression);
+                }
+            },
+            Expression::LoopExpression { predicate, body, information: _ } => {
+                if let Some(predicate) = predicate {
+                    self.visit_expression(predicate);
+                }
+                self.visit_expression(body);
+            },
+            Expression::Call { callee, args, information: _ } => {
+                self.visit_expression(callee);
+                args.iter().for_each(|arg| self.visit_expression(arg));
+            },
+            Expression::Return { ret, information: _ } => {
+                if let Some(ret) = ret {
+                    self.visit_expression(ret);
+                }
+            },
+            Expression::Format { pieces: _, args, information: _ } => args.iter().for_each(|arg| self.visit_expression(arg)),
+            Expression::Cast { value, ty: _, information: _ } => self.visit_expression(value),
+        }
+    }
+}
+
+/// Reads `(name: Type, ...) -> Type` starting at the opening parenthesis.
+/// Returns the index of every parameter's name token with its type, the return type, and the index after the signature.
+fn function_signature(tokens: &[Token], start: usize) -> (Vec<(usize, TypeInformation)>, TypeInformation, usize) {
+    let mut args = Vec::new();
+    let mut idx = start;
+
+    if tokens.get(idx).map(Token::ty) == Some(TokenType::LeftParen) {
+        idx += 1;
+        while let Some(token) = tokens.get(idx) {
+            match (token.ty(), tokens.get(idx + 1).map(Token::ty), tokens.get(idx + 2).map(Token::ty)) {
+                (TokenType::RightParen, _, _) => {
+                    idx += 1;
+                    break;
+                },
+                (TokenType::Identifier(_), Some(TokenType::Colon), Some(TokenType::Identifier(ty))) => {
+                    args.push((idx, resolve_type(ty)));
+                    idx += 3;
+                },
+                (TokenType::LeftBrace, _, _) | (TokenType::Eof, _, _) => break,
+                _ => idx += 1,
+            }
+        }
+    }
+
+    let return_ty = match (tokens.get(idx).map(Token::ty), tokens.get(idx + 1).map(Token::ty)) {
+        (Some(TokenType::Arrow), Some(TokenType::Identifier(ty))) => {
+            idx += 2;
+            resolve_type(ty)
+        },
+        _ => TypeInformation::None,
+    };
+
+    (args, return_ty, idx)
+}
+
+/// Looks a type name up among the built in types, the same way the parser does.
+fn resolve_type(name: String) -> TypeInformation {
+    let symbol = Symbol::from(name);
+    match SymbolTable::default().get_symbol_data(&symbol, 0) {
+        Some(SymbolData::Type { ty }) => ty,
+        _ => TypeInformation::NonLiteral(symbol),
+    }
+}
+
+/// The names of every built in type.
+pub fn type_names() -> Vec<(String, TypeInformation)> {
+    let table = SymbolTable::default();
+    let mut names: Vec<(String, TypeInformation)> = table.symbols().filter_map(|(symbol, data)| match data {
+        SymbolData::Type { ty } => Some((symbol.id().clone(), ty.clone())),
+        _ => None,
+    }).collect();
+    names.sort_by(|(a, _), (b, _)| a.cmp(b));
+    names
+}
@@ -0,0 +1,226 @@
+use std::{collections::HashMap, io::{BufRead, Write}};
+
+use serde_json::{json, Value};
+use theta_types::types::LocationData;
+
+use crate::protocol::{read_message, write_message, ProtocolError};
+
+mod analysis;
+pub use self::analysis::*;
+
+/// JSON-RPC error code for requests the server does not implement.
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+/// LSP `CompletionItemKind` and `SymbolKind` values used by the server.
+const COMPLETION_FUNCTION: i64 = 3;
+const COMPLETION_VARIABLE: i64 = 6;
+const COMPLETION_CLASS: i64 = 7;
+const SYMBOL_FUNCTION: i64 = 12;
+const SEVERITY_ERROR: i64 = 1;
+
+/// Converts between character offsets, which the compiler uses, and LSP positions,
+/// which are a line and a column counted in UTF-16 code units.
+#[derive(Debug, Clone)]
+pub struct LineIndex {
+    chars: Vec<char>,
+    /// The offset each line starts at.
+    line_starts: Vec<usize>,
+}
+
+impl LineIndex {
+    pub fn new(source: &str) -> LineIndex {
+        let chars: Vec<char> = source.chars().collect();
+        let line_starts = std::iter::once(0).chain(chars.iter().enumerate().filter(|(_, c)| **c == '\n').map(|(idx, _)| idx + 1)).collect();
+        LineIndex { chars, line_starts }
+    }
+
+    pub fn position(&self, offset: usize) -> Value {
+        let offset = offset.min(self.chars.len());
+        let line = self.line_starts.partition_point(|start| *start <= offset) - 1;
+        let character: usize = self.chars[self.line_starts[line]..offset].iter().map(|c| c.len_utf16()).sum();
+        json!({ "line": line, "character": character })
+    }
+
+    pub fn offset(&self, position: &Value) -> Option<usize> {
+        let line = position["line"].as_u64()? as usize;
+        let mut remaining = position["character"].as_u64()? as usize;
+
+        let mut offset = *self.line_starts.get(line)?;
+        while remaining > 0 && offset < self.chars.len() && self.chars[offset] != '\n' {
+            remaining = remaining.saturating_sub(self.chars[offset].len_utf16());
+            offset += 1;
+        }
+        Some(offset)
+    }
+
+    pub fn range(&self, location: &LocationData) -> Value {
+        json!({ "start": self.position(location.begin()), "end": self.position(location.end()) })
+    }
+}
+
+struct Document {
+    lines: LineIndex,
+    analysis: Analysis,
+}
+
+impl Document {
+    fn new(text: &str) -> Document {
+        Document { lines: LineIndex::new(text), analysis: Analysis::new(text) }
+    }
+}
+
+/// A Language Server Protocol server for Theta source files.
+///
+/// Documents are synchronised in full and re-analysed on every change,
+/// publishing the compiler's errors as diagnostics.
+pub struct LanguageServer<R: BufRead, W: Write> {
+    input: R,
+    output: W,
+    documents: HashMap<String, Document>,
+}
+
+impl<R: BufRead, W: Write> LanguageServer<R, W> {
+    pub fn new(input: R, output: W) -> LanguageServer<R, W> {
+        LanguageServer { input, output, documents: HashMap::new() }
+    }
+
+    /// Handles messages until the client sends `exit` or closes the input.
+    pub fn serve(&mut self) -> Result<(), ProtocolError> {
+        while let Some(message) = read_message(&mut self.input)? {
+            let method = message["method"].as_str().unwrap_or_default().to_string();
+            let params = message.get("params").cloned().unwrap_or(Value::Null);
+
+            match message.get("id") {
+                Some(id) => {
+                    let response = match self.handle_request(&method, &params) {
+                        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                        Err((code, message)) => json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } }),
+                    };
+                    write_message(&mut self.output, &response)?;
+                },
+                None => {
+                    if method == "exit" {
+                        return Ok(());
+                    }
+                    self.handle_notification(&method, &params)?;
+                },
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_request(&mut self, method: &str, params: &Value) -> Result<Value, (i64, String)> {
+        Ok(match method {
+            "initialize" => json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                    "documentSymbolProvider": true,
+                    "completionProvider": {},
+                },
+                "serverInfo": { "name": "thetalsp", "version": env!("CARGO_PKG_VERSION") },
+            }),
+            "shutdown" => Value::Null,
+            "textDocument/hover" => {
+                let (document, offset) = self.position(params)?;
+                let analysis = &document.analysis;
+
+                let contents = match analysis.definition_at(offset) {
+                    Some(def) => format!("{}: {}", def.name, def.ty),
+                    None => match analysis.type_at(offset) {
+                        Some(ty) => ty.to_string(),
+                        None => return Ok(Value::Null),
+                    },
+                };
+                json!({ "contents": { "kind": "plaintext", "value": contents } })
+            },
+            "textDocument/definition" => {
+                let uri = params["textDocument"]["uri"].clone();
+                let (document, offset) = self.position(params)?;
+                match document.analysis.definition_at(offset) {
+                    Some(def) => json!({ "uri": uri, "range": document.lines.range(&def.location) }),
+                    None => Value::Null,
+                }
+            },
+            "textDocument/documentSymbol" => {
+                let document = self.document(params)?;
+                let symbols: Vec<Value> = document.analysis.functions().map(|def| json!({
+                    "name": def.name,
+                    "detail": def.ty.to_string(),
+                    "kind": SYMBOL_FUNCTION,
+                    "range": document.lines.range(&def.extent),
+                    "selectionRange": document.lines.range(&def.location),
+                })).collect();
+                json!(symbols)
+            },
+            "textDocument/completion" => {
+                let (document, offset) = self.position(params)?;
+                let mut items: Vec<Value> = document.analysis.visible_at(offset).into_iter().map(|def| json!({
+                    "label": def.name,
+                    "kind": if def.kind == DefinitionKind::Function { COMPLETION_FUNCTION } else { COMPLETION_VARIABLE },
+                    "detail": def.ty.to_string(),
+                })).collect();
+                items.extend(type_names().into_iter().map(|(name, ty)| json!({ "label": name, "kind": COMPLETION_CLASS, "detail": ty.to_string() })));
+                json!(items)
+            },
+            _ => return Err((METHOD_NOT_FOUND, format!("unsupported request {}", method))),
+        })
+    }
+
+    fn handle_notification(&mut self, method: &str, params: &Value) -> Result<(), ProtocolError> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default().to_string();
+        match method {
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                self.documents.insert(uri.clone(), Document::new(text));
+                self.publish_diagnostics(&uri)
+            },
+            "textDocument/didChange" => {
+                // full synchronisation, so the last change holds the whole document
+                let Some(text) = params["contentChanges"].as_array().and_then(|changes| changes.last()).and_then(|change| change["text"].as_str()) else {
+                    return Ok(());
+                };
+                self.documents.insert(uri.clone(), Document::new(text));
+                self.publish_diagnostics(&uri)
+            },
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                self.publish_diagnostics(&uri)
+            },
+            _ => Ok(()),
+        }
+    }
+
+    /// Publishes the diagnostics of a document, or clears them once it has been closed.
+    fn publish_diagnostics(&mut self, uri: &str) -> Result<(), ProtocolError> {
+        let diagnostics: Vec<Value> = match self.documents.get(uri) {
+            Some(document) => document.analysis.diagnostics.iter().map(|diagnostic| json!({
+                "range": document.lines.range(&diagnostic.location),
+                "severity": SEVERITY_ERROR,
+                "source": "theta",
+                "message": diagnostic.message,
+            })).collect(),
+            None => Vec::new(),
+        };
+
+        write_message(&mut self.output, &json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": { "uri": uri, "diagnostics": diagnostics },
+        }))
+    }
+
+    fn document(&self, params: &Value) -> Result<&Document, (i64, String)> {
+        let uri = params["textDocument"]["uri"].as_str().ok_or((INVALID_PARAMS, String::from("missing textDocument")))?;
+        self.documents.get(uri).ok_or_else(|| (INVALID_PARAMS, format!("{} is not open", uri)))
+    }
+
+    fn position(&self, params: &Value) -> Result<(&Document, usize), (i64, String)> {
+        let document = self.document(params)?;
+        let offset = document.lines.offset(&params["position"]).ok_or((INVALID_PARAMS, String::from("invalid position")))?;
+        Ok((document, offset))
+    }
+}
@@ -1,20 +1,113 @@
+use clap::{clap_derive::ArgEnum, Args, Parser as ClapParser, Subcommand};
 use log::error;
-use theta::repl::{Repl, ReplStatus};
+use std::{cell::RefCell, path::PathBuf, rc::Rc};
+
+use rustyline::{error::ReadlineError, history::FileHistory, Editor};
+use theta::{coverage::CoverageCollector, program::Program, repl::{Repl, ReplStatus, is_incomplete, editor::ReplHelper}};
+use theta_vm::vm::{VM, ExecutionStatus, Profiler, DEFAULT_PROFILE_SAMPLE_INTERVAL};
+
+const PROMPT: &str = "> ";
+const CONTINUATION_PROMPT: &str = "... ";
+/// The file in the home directory that REPL history is kept in between sessions.
+const HISTORY_FILE: &str = ".theta_history";
+
+#[derive(ClapParser)]
+#[clap(version = "0.0.1", author = "Evan Merlock")]
+struct ThetaOptions {
+    #[clap(subcommand)]
+    command: Option<ThetaCommand>,
+}
+
+#[derive(Subcommand)]
+enum ThetaCommand {
+    /// Starts an interactive session reading from stdin (the default)
+    Repl,
+    /// Compiles and runs a source file
+    Run(RunOptions),
+}
+
+#[derive(Args)]
+struct RunOptions {
+    in_file: String,
+    /// Prints a report of the time and instructions spent in each function to stderr
+    #[clap(long)]
+    profile: bool,
+    /// Writes the profiled stacks in the folded format used by flamegraph tools
+    #[clap(long, requires = "profile")]
+    folded: Option<String>,
+    /// How many instructions are executed between samples of the stack while profiling
+    #[clap(long, default_value_t = DEFAULT_PROFILE_SAMPLE_INTERVAL)]
+    sample_interval: u64,
+    /// Writes a report of the lines that executed
+    #[clap(long)]
+    coverage: Option<String>,
+    #[clap(long, arg_enum, default_value = "lcov", requires = "coverage")]
+    coverage_format: CoverageFormat,
+    /// Fails if less than this percentage of lines executed
+    #[clap(long, requires = "coverage")]
+    fail_under: Option<f64>,
+}
+
+#[derive(Clone, ArgEnum)]
+enum CoverageFormat {
+    Lcov,
+    Cobertura,
+    /// The source with the number of times each line executed
+    Annotated,
+}
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     env_logger::init();
+    let options = ThetaOptions::parse();
+
+    match options.command.unwrap_or(ThetaCommand::Repl) {
+        ThetaCommand::Repl => repl(),
+        ThetaCommand::Run(options) => run(options),
+    }
+}
 
+fn repl() -> Result<(), Box<dyn std::error::Error>> {
     // REPL
     let mut repl = Repl::init();
+    let mut editor: Editor<ReplHelper, FileHistory> = Editor::new()?;
+    editor.set_helper(Some(ReplHelper::new(repl.symbols().clone())));
 
-    // READ IN LINE
-    for line in std::io::stdin().lines() {
-        let valid_line = line?;
-        let resp = match repl.line(valid_line) {
+    let history = std::env::var_os("HOME").map(|home| PathBuf::from(home).join(HISTORY_FILE));
+    if let Some(path) = &history {
+        // there is no history before the first session
+        let _ = editor.load_history(path);
+    }
+
+    // READ IN ENTRY, continuing it on the next line while braces or parentheses are open
+    'entries: loop {
+        let mut entry = match editor.readline(PROMPT) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e.into()),
+        };
+        while is_incomplete(&entry) {
+            match editor.readline(CONTINUATION_PROMPT) {
+                Ok(line) => {
+                    entry.push('\n');
+                    entry.push_str(&line);
+                },
+                // abandons the entry
+                Err(ReadlineError::Interrupted) => continue 'entries,
+                Err(ReadlineError::Eof) => break 'entries,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        if entry.trim().is_empty() {
+            continue;
+        }
+        editor.add_history_entry(entry.as_str())?;
+
+        let resp = match repl.line(entry) {
             Ok(repl_status) => repl_status,
             Err(e) => {
-                error!("REPL evaluation failed: {}, resetting VM", e);
-                repl = Repl::init();
+                // the line was rolled back, so the session carries on
+                error!("REPL evaluation failed: {}", e);
                 continue;
             },
         };
@@ -24,6 +117,55 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     }
 
+    if let Some(path) = &history {
+        editor.save_history(path)?;
+    }
+    Ok(())
+}
+
+fn run(options: RunOptions) -> Result<(), Box<dyn std::error::Error>> {
+    let source = std::fs::read_to_string(&options.in_file)?;
+    let program = Program::compile(&source)?;
+
+    let mut machine = VM::new(Box::new(std::io::stdout()));
+    let profiler = options.profile.then(|| Rc::new(RefCell::new(Profiler::new().with_sample_interval(options.sample_interval))));
+    if let Some(profiler) = &profiler {
+        machine.add_observer(Box::new(profiler.clone()));
+    }
+    program.load(&mut machine)?;
+    let coverage = options.coverage.as_ref().map(|_| CoverageCollector::attach(&mut machine));
+
+    // nothing is attached to stop for, so breakpoints are ignored
+    let mut result = machine.execute_code();
+    while let Ok(ExecutionStatus::Breakpoint) = result {
+        result = machine.resume();
+    }
+
+    // a program that fails still has a useful profile up to the failure
+    if let Some(profiler) = profiler {
+        let profile = profiler.take().finish();
+        eprint!("{}", profile);
+        if let Some(folded) = options.folded {
+            profile.write_folded(&mut std::fs::File::create(folded)?)?;
+        }
+    }
+
+    if let (Some(collector), Some(path)) = (coverage, options.coverage) {
+        let coverage = collector.finish(&mut machine);
+        let mut out = std::fs::File::create(path)?;
+        match options.coverage_format {
+            CoverageFormat::Lcov => coverage.write_lcov(&mut out, &options.in_file)?,
+            CoverageFormat::Cobertura => coverage.write_cobertura(&mut out, &options.in_file)?,
+            CoverageFormat::Annotated => coverage.write_annotated(&mut out, &source)?,
+        }
+
+        let percent = coverage.line_rate() * 100.0;
+        if let Some(minimum) = options.fail_under.filter(|minimum| percent < *minimum) {
+            result?;
+            return Err(format!("line coverage of {:.2}% is under the required {:.2}%", percent, minimum).into());
+        }
+    }
 
+    result?;
     Ok(())
-}
\ No newline at end of file
+}
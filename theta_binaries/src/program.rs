@@ -0,0 +1,96 @@
+use std::rc::Rc;
+
+use theta_compiler::{ast::{symbol::ExtSymbolTable, transformers::{typeck::TypeCk, to_bytecode::ToByteCode, ASTTransformer}}, lexer::{BasicLexer, Lexer}, parser::{BasicParser, Parser}};
+use theta_types::{bytecode::{ThetaBitstream, ThetaFunction, ThetaConstant, ThetaString, Chunk, OpCode, BasicAssembler, BasicDisassembler, Assembler, Disassembler}, types::TypeInformation};
+use theta_vm::vm::{VM, ThetaCallFrame};
+
+use crate::repl::parser::{ReplParser, ReplItem};
+
+/// The name of the function holding a program's top level code.
+pub const SCRIPT_FUNCTION: &str = "<script>";
+
+/// A whole source file compiled into a bitstream.
+/// Top level code is compiled into a function named `SCRIPT_FUNCTION`, so it gets debug info like any other function.
+pub struct Program {
+    pub bitstream: ThetaBitstream,
+}
+
+impl Program {
+    pub fn compile(source: &str) -> Result<Program, Box<dyn std::error::Error>> {
+        let tbl = ExtSymbolTable::default();
+
+        let mut chars = source.chars();
+        let lexer = BasicLexer::new(&mut chars);
+        let tokens = lexer.lex()?;
+        let parser = BasicParser::new_sym(tokens.output(), tbl.clone());
+        let parser = ReplParser::new(parser);
+        // the whole file is parsed before anything is checked, so every function's signature is in the table
+        // by the time the bodies that call it are type checked
+        let trees = parser.parse()?;
+
+        let byte_code_translator = ToByteCode::new(tokens.line_mapping());
+        let mut bitstream = ThetaBitstream::new();
+        let mut script = Chunk::new();
+
+        for item in trees {
+            match item {
+                ReplItem::ParserItem(pi) => {
+                    let type_cker = TypeCk::new(pi.information().current_symbol_table.clone());
+                    let type_check = type_cker.transform_item(&pi)?;
+
+                    let mut theta_func = byte_code_translator.transform_item(&type_check)?;
+                    let reloc = bitstream.constants.len();
+                    bitstream.constants.extend_from_slice(theta_func.chunk.constants());
+                    theta_func.chunk = theta_func.chunk.relocate(reloc);
+                    bitstream.functions.push(theta_func);
+                },
+                ReplItem::Declaration(decl) => {
+                    let type_cker = TypeCk::new(decl.information().current_symbol_table.clone());
+                    let type_check = type_cker.transform_tree(&decl)?;
+                    // merging relocates the declaration's constants behind the script's, the script is relocated once it is complete
+                    script = script.merge_chunk(byte_code_translator.transform_tree(&type_check)?);
+                },
+            };
+        }
+
+        script.write_to_chunk(OpCode::ReturnVoid);
+        let reloc = bitstream.constants.len();
+        bitstream.constants.extend_from_slice(script.constants());
+        bitstream.functions.push(ThetaFunction {
+            args: vec![],
+            chunk: script.relocate(reloc),
+            name: ThetaString::new(String::from(SCRIPT_FUNCTION)),
+            return_ty: TypeInformation::None,
+            locals: 0,
+        });
+
+        Ok(Program { bitstream })
+    }
+
+    /// Loads the program into `machine` and pushes a frame that runs its top level code,
+    /// so that the next `execute_code` starts the program.
+    pub fn load(self, machine: &mut VM) -> Result<(), Box<dyn std::error::Error>> {
+        let mut bitstream = self.bitstream;
+        bitstream.write_constant(ThetaConstant::Str(String::from(SCRIPT_FUNCTION)));
+        let script_name = bitstream.constants.len() - 1;
+
+        let mut compiled_bitstream = Vec::new();
+        BasicAssembler::new(&mut compiled_bitstream).assemble_bitstream(bitstream)?;
+
+        let mut intern_fn = |x| machine.intern_string(x);
+        let comp_bs = BasicDisassembler::new(&mut intern_fn).disassemble(&compiled_bitstream)?;
+        let loaded_bs = machine.load_bitstream(comp_bs)?;
+
+        let mut entry = Chunk::new();
+        entry.write_to_chunk(OpCode::Constant { offset: script_name });
+        entry.write_to_chunk(OpCode::CallDirect { name_offset: script_name });
+        entry.write_to_chunk(OpCode::ReturnVoid);
+
+        let mut compiled_entry = Vec::new();
+        BasicAssembler::new(&mut compiled_entry).assemble_chunk(entry)?;
+        let code = machine.load_chunk(&compiled_entry, &loaded_bs)?;
+        machine.push_frame(ThetaCallFrame { rip: 0, base: machine.stack().len(), function: None, bitstream: Rc::clone(&loaded_bs), chunk: code });
+
+        Ok(())
+    }
+}
@@ -0,0 +1,68 @@
+use std::{error::Error, fmt, io::{BufRead, Write}};
+
+use serde_json::Value;
+
+/// Errors reading or writing JSON messages framed with a `Content-Length` header,
+/// as used by both the Debug Adapter Protocol and the Language Server Protocol.
+#[derive(Debug)]
+pub enum ProtocolError {
+    IOError(std::io::Error),
+    JsonError(serde_json::Error),
+    /// A message without a usable `Content-Length` header.
+    InvalidHeader(String),
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProtocolError::IOError(io) => write!(f, "I/O error: {}", io),
+            ProtocolError::JsonError(json) => write!(f, "invalid message: {}", json),
+            ProtocolError::InvalidHeader(header) => write!(f, "invalid message header: {}", header),
+        }
+    }
+}
+
+impl Error for ProtocolError {}
+
+impl From<std::io::Error> for ProtocolError {
+    fn from(err: std::io::Error) -> Self {
+        ProtocolError::IOError(err)
+    }
+}
+
+impl From<serde_json::Error> for ProtocolError {
+    fn from(err: serde_json::Error) -> Self {
+        ProtocolError::JsonError(err)
+    }
+}
+
+/// Reads the next message, or None once the input is closed.
+pub fn read_message(input: &mut impl BufRead) -> Result<Option<Value>, ProtocolError> {
+    let mut content_length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(length) = header.strip_prefix("Content-Length:") {
+            content_length = Some(length.trim().parse::<usize>().map_err(|_| ProtocolError::InvalidHeader(header.to_string()))?);
+        }
+    }
+
+    let length = content_length.ok_or_else(|| ProtocolError::InvalidHeader(String::from("missing Content-Length")))?;
+    let mut content = vec![0; length];
+    input.read_exact(&mut content)?;
+    Ok(Some(serde_json::from_slice(&content)?))
+}
+
+pub fn write_message(output: &mut impl Write, message: &Value) -> Result<(), ProtocolError> {
+    let content = serde_json::to_string(message)?;
+    write!(output, "Content-Length: {}\r\n\r\n{}", content.len(), content)?;
+    output.flush()?;
+    Ok(())
+}
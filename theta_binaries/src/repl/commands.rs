@@ -0,0 +1,137 @@
+use std::{cell::RefCell, error::Error, fmt::Write, rc::Rc};
+
+use theta_compiler::{ast::{symbol::SymbolTable, transformers::{typeck::TypeCk, ASTTransformer}, InnerAbstractTree, Statement}, lexer::{BasicLexer, Lexer}, parser::{BasicParser, Parser}};
+use theta_types::{bytecode::{ThetaString, ThetaValue, Symbol}, types::TypeInformation};
+use theta_vm::vm::{Instruction, LoadedFunction};
+
+use super::{parser::{ReplParser, ReplItem}, Repl, ReplStatus};
+
+const HELP: &str = "\
+:type <expr>      shows the type of an expression without running it
+:disasm <fn>      shows the instructions of a function
+:load <file>      runs a file in the session
+:save <file>      writes every line that defined a function or global to a file
+:globals          lists the global variables and their values
+:functions        lists the functions and their signatures
+:reset            forgets everything that has been defined
+:help             shows this message
+:quit             ends the session
+";
+
+impl Repl {
+    /// Runs a REPL command, given without its leading `:`. Output goes to the same place as the programs' output.
+    pub(super) fn command(&mut self, command: &str) -> Result<ReplStatus, Box<dyn Error>> {
+        let (name, argument) = command.split_once(char::is_whitespace).map_or((command, ""), |(name, argument)| (name, argument.trim()));
+        let required = |usage: &str| if argument.is_empty() { Err(format!("usage: :{} {}", name, usage)) } else { Ok(argument) };
+
+        let output = match name {
+            "type" => format!("{}\n", self.type_of(required("<expr>")?)?),
+            "disasm" => self.disassemble(required("<fn>")?)?,
+            "load" => return self.line(std::fs::read_to_string(required("<file>")?)?),
+            "save" => {
+                let mut source = self.definitions.join("\n");
+                source.push('\n');
+                std::fs::write(required("<file>")?, source)?;
+                String::new()
+            },
+            "globals" => self.list_globals(),
+            "functions" => self.list_functions(),
+            "reset" => {
+                self.machine.rollback(&self.initial)?;
+                // the table is shared with the line editor, so it is emptied rather than replaced
+                *self.tbl.borrow_mut() = SymbolTable::default();
+                self.definitions.clear();
+                String::new()
+            },
+            "help" => String::from(HELP),
+            "quit" | "exit" => return Ok(ReplStatus::ReplTerminate),
+            _ => return Err(format!("unknown command :{}, see :help", name).into()),
+        };
+
+        let stdout = self.machine.stdout();
+        stdout.write_all(output.as_bytes())?;
+        stdout.flush()?;
+        Ok(ReplStatus::ReplOk)
+    }
+
+    /// Type checks an expression against the session without defining anything.
+    fn type_of(&self, source: &str) -> Result<TypeInformation, Box<dyn Error>> {
+        let mut chars = source.chars();
+        let tokens = BasicLexer::new(&mut chars).lex()?;
+        // the parser adds what it declares to the table it is given
+        let tbl = Rc::new(RefCell::new(self.tbl.borrow().clone()));
+        let mut trees = ReplParser::new(BasicParser::new_sym(tokens.output(), tbl)).parse()?;
+
+        let (Some(ReplItem::Declaration(decl)), true) = (trees.pop(), trees.is_empty()) else {
+            return Err("expected a single expression".into());
+        };
+        let checked = TypeCk::new(decl.information().current_symbol_table.clone()).transform_tree(&decl)?;
+        match checked.inner() {
+            InnerAbstractTree::Expression((expression, _)) => Ok(expression.information().ty.clone()),
+            InnerAbstractTree::Statement((Statement::ExpressionStatement { expression, information: _ } | Statement::Partial { expression, information: _ }, _)) => Ok(expression.information().ty.clone()),
+            _ => Err("expected an expression".into()),
+        }
+    }
+
+    fn disassemble(&self, name: &str) -> Result<String, Box<dyn Error>> {
+        let func = self.machine.function(&ThetaString::new(name.to_string())).ok_or_else(|| format!("no function named {}", name))?;
+
+        let mut output = format!("{}\n", signature(func));
+        for (idx, instruction) in func.code.iter().enumerate() {
+            let line = match func.lines.iter().find(|(start, _)| *start == idx) {
+                Some((_, line)) => line.to_string(),
+                None => String::new(),
+            };
+            let _ = writeln!(output, "{:>5} {:>5}  {}", idx, line, self.describe(*instruction));
+        }
+        Ok(output)
+    }
+
+    /// Describes an instruction, naming the functions and globals it refers to.
+    fn describe(&self, instruction: Instruction) -> String {
+        let function = |function: usize| match self.machine.functions().get(function) {
+            Some(Some(func)) => func.function.name.to_string(),
+            _ => format!("<function {}>", function),
+        };
+        let global = |slot: usize| match self.machine.stack().global_slots().iter().find(|(_, s)| **s == slot) {
+            Some((name, _)) => name.clone(),
+            None => format!("<global {}>", slot),
+        };
+
+        match instruction {
+            Instruction::Op(op) => op.human_readable(),
+            Instruction::Jump { target } => format!("Jump to {}", target),
+            Instruction::JumpIfFalse { target } => format!("Jump if false to {}", target),
+            Instruction::Call { function: callee } => format!("Call {}", function(callee)),
+            Instruction::TailCall { function: callee } => format!("Tail call {}", function(callee)),
+            Instruction::DefineGlobal { slot } => format!("Define global {}", global(slot)),
+            Instruction::GetGlobal { slot } => format!("Get global {}", global(slot)),
+        }
+    }
+
+    fn list_globals(&self) -> String {
+        let mut globals: Vec<(&str, &ThetaValue)> = self.machine.globals().into_iter().collect();
+        globals.sort_by_key(|(name, _)| *name);
+
+        let tbl = self.tbl.borrow();
+        globals.into_iter().map(|(name, value)| match tbl.get_symbol_data(&Symbol::from(name.to_string()), 0) {
+            Some(data) => format!("{}: {} = {:#}\n", name, data.ty(), value),
+            None => format!("{} = {:#}\n", name, value),
+        }).collect()
+    }
+
+    fn list_functions(&self) -> String {
+        let mut functions: Vec<&LoadedFunction> = self.machine.functions().iter().flatten().collect();
+        functions.sort_by(|a, b| a.function.name.cmp(&b.function.name));
+        functions.into_iter().map(|func| format!("{}\n", signature(func))).collect()
+    }
+}
+
+/// A function's signature as it is written in source, e.g. `double(Int) -> Int`.
+fn signature(func: &LoadedFunction) -> String {
+    let args: Vec<String> = func.function.args.iter().map(|arg| arg.ty.to_string()).collect();
+    match &func.function.return_ty {
+        TypeInformation::None => format!("{}({})", func.function.name.as_str(), args.join(", ")),
+        return_ty => format!("{}({}) -> {}", func.function.name.as_str(), args.join(", "), return_ty),
+    }
+}
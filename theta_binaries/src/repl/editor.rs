@@ -0,0 +1,57 @@
+use rustyline::{completion::{Completer, Pair}, highlight::Highlighter, hint::Hinter, validate::Validator, Context, Helper};
+use theta_compiler::ast::symbol::{ExtSymbolTable, SymbolData};
+
+/// Line editing support for the REPL, completing names from the session's symbol table.
+pub struct ReplHelper {
+    symbols: ExtSymbolTable,
+}
+
+impl ReplHelper {
+    /// The table is shared with the session, so names defined later are offered too.
+    pub fn new(symbols: ExtSymbolTable) -> ReplHelper {
+        ReplHelper { symbols }
+    }
+
+    /// Every global, function and type name starting with `prefix`, sorted by name.
+    pub fn completions(&self, prefix: &str) -> Vec<Pair> {
+        let mut completions: Vec<Pair> = self.symbols.borrow().symbols()
+            .filter(|(symbol, _)| symbol.id().starts_with(prefix))
+            .filter_map(|(symbol, data)| {
+                let display = match data {
+                    SymbolData::Type { .. } => symbol.id().clone(),
+                    SymbolData::GlobalVariable { ty } => format!("{}: {}", symbol.id(), ty),
+                    SymbolData::Function { args, return_ty, .. } => {
+                        let args: Vec<String> = args.iter().map(|arg| arg.ty.to_string()).collect();
+                        format!("{}({}) -> {}", symbol.id(), args.join(", "), return_ty)
+                    },
+                    // locals belong to functions that have already been defined
+                    SymbolData::LocalVariable { .. } => return None,
+                };
+                Some(Pair { display, replacement: symbol.id().clone() })
+            })
+            .collect();
+        completions.sort_by(|a, b| a.replacement.cmp(&b.replacement));
+        completions
+    }
+}
+
+impl Completer for ReplHelper {
+    type Candidate = Pair;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let start = line[..pos].char_indices().rev()
+            .find(|(_, c)| !(c.is_alphanumeric() || *c == '_'))
+            .map_or(0, |(idx, c)| idx + c.len_utf8());
+        Ok((start, self.completions(&line[start..pos])))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {}
+
+impl Validator for ReplHelper {}
+
+impl Helper for ReplHelper {}
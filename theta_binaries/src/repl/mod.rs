@@ -3,22 +3,45 @@
 
 
 
-use std::rc::Rc;
+
+use std::io::Write;
 
 use log::{LevelFilter, debug};
-use theta_compiler::{ast::{symbol::{ExtSymbolTable, SymbolData}, transformers::{typeck::TypeCk, to_bytecode::ToByteCode, ASTTransformer}, Item}, lexer::{BasicLexer, Lexer}, parser::{BasicParser, Parser}};
+use theta_compiler::{ast::{symbol::ExtSymbolTable, transformers::{typeck::TypeCk, to_bytecode::ToByteCode, ASTTransformer}, AbstractTree, InnerAbstractTree, Statement, Expression}, lexer::{BasicLexer, Lexer}, parser::{BasicParser, Parser}};
 use theta_types::{bytecode::{ThetaBitstream, Chunk, OpCode, BasicAssembler, BasicDisassembler, Assembler, Disassembler}, types::TypeInformation};
-use theta_vm::vm::{VM, ThetaCallFrame};
+use theta_vm::vm::{VM, ThetaCallFrame, ExecutionStatus};
 
 use self::parser::{ReplParser, ReplItem};
 
 pub mod parser;
+pub mod editor;
+mod commands;
 
 // TODO: move REPL to using a direct to instruction assembler. Then we don't need the disassembly step.
 // chunk building should only occur when a stream of bytecode is known.
 pub struct Repl {
     machine: VM,
     tbl: ExtSymbolTable,
+    // the machine before anything was defined, for `:reset`
+    initial: Vec<u8>,
+    // the source of every line that defined a function or global, for `:save`
+    definitions: Vec<String>,
+}
+
+/// Whether `source` leaves a brace or parenthesis open, so the entry continues on the next line.
+pub fn is_incomplete(source: &str) -> bool {
+    let mut depth = 0;
+    let mut in_string = false;
+    for c in source.chars() {
+        match c {
+            // the lexer has no escapes, so every quote starts or ends a string
+            '"' => in_string = !in_string,
+            '{' | '(' if !in_string => depth += 1,
+            '}' | ')' if !in_string => depth -= 1,
+            _ => {},
+        }
+    }
+    depth > 0
 }
 
 pub enum ReplStatus {
@@ -28,13 +51,49 @@ pub enum ReplStatus {
 
 impl Repl {
     pub fn init() -> Repl {
+        Repl::new(Box::new(std::io::stdout()))
+    }
+
+    /// Starts a session whose programs print to `stdout`.
+    pub fn new(stdout: Box<dyn Write>) -> Repl {
+        let machine = VM::new(stdout);
+        let initial = machine.snapshot().expect("an empty VM can always be snapshotted");
         Repl {
-            machine: VM::default(),
+            machine,
             tbl: ExtSymbolTable::default(),
+            initial,
+            definitions: vec![],
         }
     }
 
+    pub fn machine(&self) -> &VM {
+        &self.machine
+    }
+
+    pub fn symbols(&self) -> &ExtSymbolTable {
+        &self.tbl
+    }
+
+    /// Evaluates a line as a whole. If lexing, parsing, type checking or execution fails,
+    /// every symbol, global and function the line added is dropped again and the session carries on as it was.
+    /// Lines starting with `:` are commands, see `:help`.
     pub fn line(&mut self, valid_line: String) -> Result<ReplStatus, Box<dyn std::error::Error>> {
+        if let Some(command) = valid_line.trim_start().strip_prefix(':') {
+            return self.command(command.trim());
+        }
+
+        let symbols = self.tbl.borrow().clone();
+        let snapshot = self.machine.snapshot()?;
+
+        let result = self.eval(valid_line);
+        if result.is_err() {
+            *self.tbl.borrow_mut() = symbols;
+            self.machine.rollback(&snapshot)?;
+        }
+        result
+    }
+
+    fn eval(&mut self, valid_line: String) -> Result<ReplStatus, Box<dyn std::error::Error>> {
                 // CONVERT LINE TO CHUNKS
                 if valid_line.starts_with("--") && log::max_level() >= LevelFilter::Debug {
                     match valid_line.as_str().trim_end() {
@@ -46,6 +105,7 @@ impl Repl {
                         },
                         "--heap" => {
                             debug!("Heap: {:?}", self.machine.heap());
+                            debug!("Heap usage: {} bytes (peak {} bytes)", self.machine.heap_used(), self.machine.heap_peak());
                         },
                         "--globals" => {
                             debug!("Globals: {:#?}", self.machine.globals());
@@ -55,6 +115,7 @@ impl Repl {
                         },
                         "--functions" => {
                             debug!("Function Pool: {:#?}", self.machine.functions());
+                            debug!("Function Indices: {:#?}", self.machine.function_indices());
                         },
                         "--symbols" => {
                             debug!("Symbol Table: {:#?}", self.tbl);
@@ -80,13 +141,26 @@ impl Repl {
                 let mut bitstream = ThetaBitstream::new();
                 let mut chunk = Chunk::new();
 
-                for item in trees {
-                    self.repl_item(item, tokens.line_mapping(), &mut bitstream, &mut chunk)?;
+                let defines = trees.iter().any(|item| match item {
+                    ReplItem::ParserItem(_) => true,
+                    ReplItem::Declaration(decl) => matches!(decl.inner(), InnerAbstractTree::Statement((Statement::VarStatement { .. }, _))),
+                });
+
+                // the value of the line's final expression is returned by the chunk, so it can be shown
+                let last = trees.len().saturating_sub(1);
+                let mut result_ty = None;
+                for (idx, item) in trees.into_iter().enumerate() {
+                    result_ty = self.repl_item(item, idx == last, tokens.line_mapping(), &mut bitstream, &mut chunk)?;
                 }
 
                 if !chunk.instructions().is_empty() {
-                    // append a return void to the chunk
-                    chunk.write_to_chunk(OpCode::ReturnVoid);
+                    // append a return to the chunk
+                    chunk.write_to_chunk(if result_ty.is_some() { OpCode::Return } else { OpCode::ReturnVoid });
+
+                    // the chunk's constants go behind the functions', now that the chunk is complete
+                    let reloc = bitstream.constants.len();
+                    bitstream.constants.extend_from_slice(chunk.constants());
+                    chunk = chunk.relocate(reloc);
                 }
 
                 // compile bitstream 
@@ -100,7 +174,7 @@ impl Repl {
                 let mut intern_fn = |x| self.machine.intern_string(x);
                 let mut basic_diassembler = BasicDisassembler::new(&mut intern_fn);
                 let comp_bs = basic_diassembler.disassemble(&compiled_bitstream)?;
-                let loaded_bs = self.machine.load_bitstream(comp_bs);
+                let loaded_bs = self.machine.load_bitstream(comp_bs)?;
                 
 
                 if !chunk.instructions().is_empty() {
@@ -110,16 +184,35 @@ impl Repl {
                     basic_assembler.assemble_chunk(chunk)?;
 
                     // do the magic stack frame thing
-                    self.machine.push_frame(ThetaCallFrame { rip: 0, locals: vec![], bitstream: loaded_bs, chunk: Rc::new(compiled_chunk) });
+                    let code = self.machine.load_chunk(&compiled_chunk, &loaded_bs)?;
+                    self.machine.push_frame(ThetaCallFrame { rip: 0, base: self.machine.stack().len(), function: None, bitstream: loaded_bs, chunk: code });
     
-                    // execute chunk
-                    self.machine.execute_code()?;
+                    // execute chunk. a runtime error rolls back the whole line, including anything it defined before failing.
+                    // the REPL has no debugger attached, so breakpoints only log the call stack.
+                    let mut status = self.machine.execute_code();
+                    while let Ok(ExecutionStatus::Breakpoint) = status {
+                        debug!("Breakpoint hit. Frames: {:#?}", self.machine.frames());
+                        status = self.machine.resume();
+                    }
+                    status?;
+
+                    if let Some(ty) = result_ty {
+                        let value = self.machine.pop_value().expect("the chunk returned a value");
+                        let stdout = self.machine.stdout();
+                        writeln!(stdout, "{:#}: {}", value, ty)?;
+                        stdout.flush()?;
+                    }
                 }
 
+                if defines {
+                    self.definitions.push(valid_line);
+                }
                 Ok(ReplStatus::ReplOk)
     }
 
-    fn repl_item(&mut self, item: ReplItem, mappings: &Vec<usize>, bitstream: &mut ThetaBitstream, chunk: &mut Chunk) -> Result<(), Box<dyn std::error::Error>> {
+    /// Compiles an item into the line's bitstream and chunk.
+    /// If `keep_result` is set and the item is an expression with a value, the value is left on the stack and its type returned.
+    fn repl_item(&mut self, item: ReplItem, keep_result: bool, mappings: &[usize], bitstream: &mut ThetaBitstream, chunk: &mut Chunk) -> Result<Option<TypeInformation>, Box<dyn std::error::Error>> {
         let byte_code_translator = ToByteCode::new(mappings);
         match item {
             ReplItem::ParserItem(pi) => {
@@ -128,17 +221,6 @@ impl Repl {
                     let type_cker = TypeCk::new(sym.clone());
                     let type_check = type_cker.transform_item(&pi)?;
 
-                    match pi {
-                        Item::Function(func) => {
-                            let mut tbl = self.tbl.borrow_mut();
-                            tbl.insert_symbol(func.name, SymbolData::Function { 
-                                return_ty: func.return_ty.clone(), 
-                                args: func.args.clone(), 
-                                fn_ty: TypeInformation::Function(Box::new(func.return_ty.clone()), func.args.into_iter().map(|x| x.ty).collect())
-                            });
-                        },
-                    };
-
                     let mut theta_func = byte_code_translator.transform_item(&type_check)?;
 
                     // need to pull out constants and reloc
@@ -155,19 +237,28 @@ impl Repl {
                 let sym = decl.information().current_symbol_table.clone();
                 debug!("sym: {:?}", sym.borrow());
                 let type_cker = TypeCk::new(sym);
-                let type_check = type_cker.transform_tree(&decl)?;
-                let ty_chunk = byte_code_translator.transform_tree(&type_check)?;
+                let mut type_check = type_cker.transform_tree(&decl)?;
 
-                let reloc = bitstream.constants.len();
-                let new_chunk = ty_chunk.relocate(reloc);
-
-                bitstream.constants.extend_from_slice(new_chunk.constants());
+                let result_ty = match type_check.inner() {
+                    // assignments are not shown, their value was just written
+                    InnerAbstractTree::Statement((Statement::ExpressionStatement { expression, information } | Statement::Partial { expression, information }, tree_info))
+                        if keep_result && expression.information().ty != TypeInformation::None && !matches!(expression, Expression::Assignment { .. }) => {
+                        let ty = expression.information().ty.clone();
+                        // partials are lowered without popping their value
+                        type_check = AbstractTree::statement(Statement::Partial { expression: expression.clone(), information: information.clone() }, tree_info.clone());
+                        Some(ty)
+                    },
+                    _ => None,
+                };
+                let ty_chunk = byte_code_translator.transform_tree(&type_check)?;
 
-                let new_ck = chunk.clone().merge_chunk(new_chunk);
+                // merging relocates the declaration's constants behind the chunk's, the chunk is relocated once the line is complete
+                let new_ck = chunk.clone().merge_chunk(ty_chunk);
                 *chunk = new_ck;
+                return Ok(result_ty);
             },
         };
 
-        Ok(())
+        Ok(None)
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,83 @@
+use std::{collections::HashMap, fmt, io::BufRead};
+
+use serde_json::Value;
+
+/// Totals computed from a JSON Lines trace written by `theta_vm::vm::Tracer`.
+#[derive(Debug, Default)]
+pub struct TraceSummary {
+    pub instructions: u64,
+    /// Executions of each op, by name.
+    pub ops: HashMap<String, u64>,
+    /// Instructions executed in each function. Top level code is counted under None.
+    pub functions: HashMap<Option<String>, u64>,
+    /// Instructions executed on each source line of each function.
+    pub lines: HashMap<(Option<String>, usize), u64>,
+    /// How many entries of each table `Display` shows.
+    pub top: usize,
+}
+
+impl TraceSummary {
+    pub fn from_reader(input: impl BufRead) -> Result<TraceSummary, Box<dyn std::error::Error>> {
+        let mut summary = TraceSummary { top: 10, ..Default::default() };
+
+        for (idx, line) in input.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record: Value = serde_json::from_str(&line).map_err(|e| format!("invalid trace record on line {}: {}", idx + 1, e))?;
+            summary.add(&record);
+        }
+
+        Ok(summary)
+    }
+
+    pub fn add(&mut self, record: &Value) {
+        let function = record["function"].as_str().map(String::from);
+
+        self.instructions += 1;
+        *self.ops.entry(record["op"].as_str().unwrap_or("?").to_string()).or_default() += 1;
+        *self.functions.entry(function.clone()).or_default() += 1;
+        if let Some(line) = record["line"].as_u64() {
+            *self.lines.entry((function, line as usize)).or_default() += 1;
+        }
+    }
+}
+
+/// Sorts counts from most to least frequent, breaking ties by key so the output is stable.
+fn sorted<K: Ord>(counts: &HashMap<K, u64>) -> Vec<(&K, u64)> {
+    let mut counts: Vec<(&K, u64)> = counts.iter().map(|(key, count)| (key, *count)).collect();
+    counts.sort_by(|(a_key, a), (b_key, b)| b.cmp(a).then(a_key.cmp(b_key)));
+    counts
+}
+
+fn function_name(function: &Option<String>) -> &str {
+    function.as_deref().unwrap_or("<top level>")
+}
+
+impl fmt::Display for TraceSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let percent = |count: u64| count as f64 * 100.0 / self.instructions.max(1) as f64;
+
+        writeln!(f, "{} instructions executed", self.instructions)?;
+
+        writeln!(f, "\nops:")?;
+        for (op, count) in sorted(&self.ops).into_iter().take(self.top) {
+            writeln!(f, "  {:<16} {:>10} {:>6.2}%", op, count, percent(count))?;
+        }
+
+        writeln!(f, "\nfunctions:")?;
+        for (function, count) in sorted(&self.functions).into_iter().take(self.top) {
+            writeln!(f, "  {:<16} {:>10} {:>6.2}%", function_name(function), count, percent(count))?;
+        }
+
+        if !self.lines.is_empty() {
+            writeln!(f, "\nlines:")?;
+            for ((function, line), count) in sorted(&self.lines).into_iter().take(self.top) {
+                writeln!(f, "  {:<16} {:>10} {:>6.2}%", format!("{}:{}", function_name(function), line), count, percent(count))?;
+            }
+        }
+
+        Ok(())
+    }
+}
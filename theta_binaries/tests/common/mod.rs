@@ -1,10 +1,10 @@
 use std::{rc::Rc, io::Write, cell::RefCell};
 
-use theta_vm::vm::VM;
+use theta_vm::vm::{VM, Instruction};
 
 
-use theta_compiler::{lexer::{BasicLexer, Lexer}, parser::{BasicParser, Parser}, ast::{symbol::{ExtSymbolTable, SymbolData}, transformers::{typeck::TypeCk, ASTTransformer, to_bytecode::ToByteCode}, Item}};
-use theta_types::{bytecode::{ThetaBitstream, ThetaConstant, OpCode, BasicDisassembler, Disassembler, BasicAssembler, Assembler, ThetaCompiledBitstream, Chunk}, types::TypeInformation, build_chunk};
+use theta_compiler::{lexer::{BasicLexer, Lexer}, parser::{BasicParser, Parser}, ast::{symbol::ExtSymbolTable, transformers::{typeck::TypeCk, ASTTransformer, to_bytecode::ToByteCode}}};
+use theta_types::{bytecode::{ThetaBitstream, ThetaConstant, OpCode, BasicDisassembler, Disassembler, BasicAssembler, Assembler, ThetaCompiledBitstream, Chunk}, build_chunk};
 
 #[derive(Clone)]
 pub struct TestOutput {
@@ -27,7 +27,7 @@ impl Write for TestOutput {
     }
 }
 
-pub fn build_test_vm(code: &'static str, fn_name: &'static str, fn_transform: impl Fn(Chunk) -> Chunk, stdout: Box<dyn Write>) -> Result<(VM, Rc<ThetaCompiledBitstream>, Vec<u8>), Box<dyn std::error::Error>> { 
+pub fn build_test_vm(code: &str, fn_name: &'static str, fn_transform: impl Fn(Chunk) -> Chunk, stdout: Box<dyn Write>) -> Result<(VM, Rc<ThetaCompiledBitstream>, Rc<[Instruction]>), Box<dyn std::error::Error>> { 
 
     let tbl = ExtSymbolTable::default();
     let mut machine = VM::new(stdout);
@@ -45,17 +45,6 @@ pub fn build_test_vm(code: &'static str, fn_name: &'static str, fn_transform: im
         let type_cker = TypeCk::new(sym.clone());
         let type_check = type_cker.transform_item(&item)?;
 
-        match item {
-            Item::Function(func) => {
-                let mut tbl =  tbl.borrow_mut();
-                tbl.insert_symbol(func.name, SymbolData::Function { 
-                    return_ty: func.return_ty.clone(), 
-                    args: func.args.clone(), 
-                    fn_ty: TypeInformation::Function(Box::new(func.return_ty.clone()), func.args.into_iter().map(|x| x.ty).collect())
-                });
-            },
-        };
-
         let tbc = ToByteCode::new(tokens.line_mapping());
         let mut theta_func = tbc.transform_item(&type_check)?;
 
@@ -83,13 +72,15 @@ pub fn build_test_vm(code: &'static str, fn_name: &'static str, fn_transform: im
     let mut intern_fn = |x| machine.intern_string(x);
     let mut basic_diassembler = BasicDisassembler::new(&mut intern_fn);
     let comp_bs = basic_diassembler.disassemble(&compiled_bitstream)?;
-    let loaded_bs = machine.load_bitstream(comp_bs);
+    let loaded_bs = machine.load_bitstream(comp_bs)?;
 
     let mut compiled_chunk = Vec::new();
     let mut basic_assembler = BasicAssembler::new(&mut compiled_chunk);
     basic_assembler.assemble_chunk(call_function_chunk)?;
 
-    Ok((machine, loaded_bs, compiled_chunk))
+    let code = machine.load_chunk(&compiled_chunk, &loaded_bs)?;
+
+    Ok((machine, loaded_bs, code))
 }
 
 pub fn identity<T>(x: T) -> T {
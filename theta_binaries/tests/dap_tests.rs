@@ -0,0 +1,171 @@
+use std::{io::Write, process::{Command, Stdio}};
+
+use serde_json::{json, Value};
+
+const PROGRAM: &str =
+"fun double(n: Int) -> Int {
+    let m: Int = n * 2;
+    m
+}
+let x: Int = 20;
+print(double(x));
+print(x + 1);
+";
+
+/// Runs the debug adapter over a scripted session and returns every message it sent.
+fn run_session(name: &str, stop_on_entry: bool, requests: Vec<Value>) -> Result<Vec<Value>, Box<dyn std::error::Error>> {
+    let path = std::env::temp_dir().join(format!("theta_dap_{}_{}.the", name, std::process::id()));
+    std::fs::write(&path, PROGRAM)?;
+
+    let mut input = Vec::new();
+    let launch = vec![
+        json!({ "command": "initialize", "arguments": { "adapterID": "theta" } }),
+        json!({ "command": "launch", "arguments": { "program": path, "stopOnEntry": stop_on_entry } }),
+    ];
+    for (seq, mut request) in launch.into_iter().chain(requests).enumerate() {
+        request["seq"] = json!(seq + 1);
+        request["type"] = json!("request");
+        let content = serde_json::to_string(&request)?;
+        write!(input, "Content-Length: {}\r\n\r\n{}", content.len(), content)?;
+    }
+
+    let mut adapter = Command::new(env!("CARGO_BIN_EXE_thetadap")).stdin(Stdio::piped()).stdout(Stdio::piped()).spawn()?;
+    adapter.stdin.take().expect("stdin is piped").write_all(&input)?;
+    let output = adapter.wait_with_output()?;
+    std::fs::remove_file(&path)?;
+    assert!(output.status.success());
+
+    let output = String::from_utf8(output.stdout)?;
+    let mut messages = Vec::new();
+    for part in output.split("Content-Length: ").skip(1) {
+        let (_, content) = part.split_once("\r\n\r\n").expect("header should be terminated");
+        messages.push(serde_json::from_str(content)?);
+    }
+    Ok(messages)
+}
+
+fn response<'a>(messages: &'a [Value], command: &str) -> Vec<&'a Value> {
+    messages.iter().filter(|m| m["type"] == "response" && m["command"] == command).collect()
+}
+
+fn events<'a>(messages: &'a [Value], event: &str) -> Vec<&'a Value> {
+    messages.iter().filter(|m| m["type"] == "event" && m["event"] == event).collect()
+}
+
+#[test]
+pub fn dap_breakpoints_and_variables() -> Result<(), Box<dyn std::error::Error>> {
+    let messages = run_session("breakpoints", false, vec![
+        json!({ "command": "setBreakpoints", "arguments": { "source": {}, "breakpoints": [{ "line": 2 }, { "line": 4 }] } }),
+        json!({ "command": "configurationDone" }),
+        json!({ "command": "stackTrace", "arguments": { "threadId": 1 } }),
+        json!({ "command": "scopes", "arguments": { "frameId": 0 } }),
+        json!({ "command": "variables", "arguments": { "variablesReference": 2 } }),
+        json!({ "command": "variables", "arguments": { "variablesReference": 1 } }),
+        json!({ "command": "next", "arguments": { "threadId": 1 } }),
+        json!({ "command": "variables", "arguments": { "variablesReference": 2 } }),
+        json!({ "command": "continue", "arguments": { "threadId": 1 } }),
+        json!({ "command": "disconnect" }),
+    ])?;
+
+    assert!(messages.iter().filter(|m| m["type"] == "response").all(|m| m["success"] == true));
+    assert_eq!(events(&messages, "initialized").len(), 1);
+
+    // line 4 only has a closing brace on it
+    let breakpoints = &response(&messages, "setBreakpoints")[0]["body"]["breakpoints"];
+    assert_eq!(breakpoints[0]["verified"], true);
+    assert_eq!(breakpoints[1]["verified"], false);
+
+    let stopped = events(&messages, "stopped");
+    assert_eq!(stopped[0]["body"]["reason"], "breakpoint");
+    assert_eq!(stopped[1]["body"]["reason"], "step");
+
+    let frames = &response(&messages, "stackTrace")[0]["body"]["stackFrames"];
+    assert_eq!(frames.as_array().map(Vec::len), Some(2));
+    assert_eq!(frames[0]["name"], "double");
+    assert_eq!(frames[0]["line"], 2);
+    assert_eq!(frames[1]["name"], "<script>");
+    assert_eq!(frames[1]["line"], 6);
+
+    let scopes = &response(&messages, "scopes")[0]["body"]["scopes"];
+    assert_eq!(scopes[0]["variablesReference"], 2);
+    assert_eq!(scopes[1]["variablesReference"], 1);
+
+    let variables = response(&messages, "variables");
+    assert_eq!(variables[0]["body"]["variables"], json!([
+        { "name": "arg0", "value": "20", "variablesReference": 0 },
+        { "name": "local0", "value": "0", "variablesReference": 0 },
+    ]));
+    assert_eq!(variables[1]["body"]["variables"], json!([{ "name": "x", "value": "20", "variablesReference": 0 }]));
+    assert_eq!(variables[2]["body"]["variables"][1]["value"], "40");
+
+    let output: String = events(&messages, "output").iter().filter_map(|e| e["body"]["output"].as_str()).collect();
+    assert!(output.contains("40") && output.contains("21"));
+    assert_eq!(events(&messages, "exited")[0]["body"]["exitCode"], 0);
+    assert_eq!(events(&messages, "terminated").len(), 1);
+
+    Ok(())
+}
+
+#[test]
+pub fn dap_step_in_and_out() -> Result<(), Box<dyn std::error::Error>> {
+    let messages = run_session("stepping", false, vec![
+        json!({ "command": "setBreakpoints", "arguments": { "source": {}, "breakpoints": [{ "line": 6 }] } }),
+        json!({ "command": "configurationDone" }),
+        json!({ "command": "stepIn", "arguments": { "threadId": 1 } }),
+        json!({ "command": "stackTrace", "arguments": { "threadId": 1 } }),
+        json!({ "command": "stepOut", "arguments": { "threadId": 1 } }),
+        json!({ "command": "stackTrace", "arguments": { "threadId": 1 } }),
+        json!({ "command": "disconnect" }),
+    ])?;
+
+    let stopped = events(&messages, "stopped");
+    assert_eq!(stopped.iter().map(|e| e["body"]["reason"].as_str()).collect::<Vec<_>>(), vec![Some("breakpoint"), Some("step"), Some("step")]);
+
+    let traces = response(&messages, "stackTrace");
+    assert_eq!(traces[0]["body"]["stackFrames"][0]["name"], "double");
+    assert_eq!(traces[0]["body"]["stackFrames"][0]["line"], 2);
+    assert_eq!(traces[1]["body"]["totalFrames"], 1);
+    assert_eq!(traces[1]["body"]["stackFrames"][0]["name"], "<script>");
+
+    // the session ended while paused, so the program never finished
+    assert!(events(&messages, "terminated").is_empty());
+
+    Ok(())
+}
+
+#[test]
+pub fn dap_stop_on_entry() -> Result<(), Box<dyn std::error::Error>> {
+    let messages = run_session("entry", true, vec![
+        json!({ "command": "configurationDone" }),
+        json!({ "command": "stackTrace", "arguments": { "threadId": 1 } }),
+        json!({ "command": "continue", "arguments": { "threadId": 1 } }),
+    ])?;
+
+    let stopped = events(&messages, "stopped");
+    assert_eq!(stopped.len(), 1);
+    assert_eq!(stopped[0]["body"]["reason"], "entry");
+    // nothing has run yet, so the script has not been called
+    assert_eq!(response(&messages, "stackTrace")[0]["body"]["totalFrames"], 0);
+
+    let output: String = events(&messages, "output").iter().filter_map(|e| e["body"]["output"].as_str()).collect();
+    assert!(output.contains("40") && output.contains("21"));
+    assert_eq!(events(&messages, "exited")[0]["body"]["exitCode"], 0);
+
+    Ok(())
+}
+
+#[test]
+pub fn dap_rejects_invalid_requests() -> Result<(), Box<dyn std::error::Error>> {
+    let messages = run_session("missing", false, vec![
+        json!({ "command": "launch", "arguments": {} }),
+        json!({ "command": "configurationDone" }),
+        json!({ "command": "evaluate", "arguments": { "expression": "x" } }),
+    ])?;
+
+    let launches = response(&messages, "launch");
+    assert_eq!(launches[0]["success"], true);
+    assert_eq!(launches[1]["success"], false);
+    assert_eq!(response(&messages, "evaluate")[0]["success"], false);
+
+    Ok(())
+}
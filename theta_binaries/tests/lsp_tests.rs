@@ -0,0 +1,163 @@
+use std::{io::Write, process::{Command, Stdio}};
+
+use serde_json::{json, Value};
+
+const URI: &str = "file:///tmp/example.the";
+
+const PROGRAM: &str =
+"fun double(n: Int) -> Int {
+    let m: Int = n * 2;
+    m
+}
+let x: Int = 20;
+print(double(x));
+";
+
+/// Runs the language server over a scripted session and returns every message it sent.
+/// Messages with a method but no id are sent as notifications.
+fn run_session(messages: Vec<Value>) -> Result<Vec<Value>, Box<dyn std::error::Error>> {
+    let mut input = Vec::new();
+    let session = std::iter::once(json!({ "id": 0, "method": "initialize", "params": { "capabilities": {} } }))
+        .chain(messages)
+        .chain([json!({ "id": 1000, "method": "shutdown" }), json!({ "method": "exit" })]);
+    for mut message in session {
+        message["jsonrpc"] = json!("2.0");
+        let content = serde_json::to_string(&message)?;
+        write!(input, "Content-Length: {}\r\n\r\n{}", content.len(), content)?;
+    }
+
+    let mut server = Command::new(env!("CARGO_BIN_EXE_thetalsp")).stdin(Stdio::piped()).stdout(Stdio::piped()).spawn()?;
+    server.stdin.take().expect("stdin is piped").write_all(&input)?;
+    let output = server.wait_with_output()?;
+    assert!(output.status.success());
+
+    let output = String::from_utf8(output.stdout)?;
+    let mut messages = Vec::new();
+    for part in output.split("Content-Length: ").skip(1) {
+        let (_, content) = part.split_once("\r\n\r\n").expect("header should be terminated");
+        messages.push(serde_json::from_str(content)?);
+    }
+    Ok(messages)
+}
+
+fn open(text: &str) -> Value {
+    json!({ "method": "textDocument/didOpen", "params": { "textDocument": { "uri": URI, "languageId": "theta", "version": 1, "text": text } } })
+}
+
+fn request(id: i64, method: &str, line: usize, character: usize) -> Value {
+    json!({ "id": id, "method": method, "params": { "textDocument": { "uri": URI }, "position": { "line": line, "character": character } } })
+}
+
+fn result(messages: &[Value], id: i64) -> &Value {
+    &messages.iter().find(|m| m["id"] == id).expect("every request gets a response")["result"]
+}
+
+fn diagnostics(messages: &[Value]) -> Vec<&Value> {
+    messages.iter().filter(|m| m["method"] == "textDocument/publishDiagnostics").map(|m| &m["params"]["diagnostics"]).collect()
+}
+
+#[test]
+pub fn lsp_initialize_and_shutdown() -> Result<(), Box<dyn std::error::Error>> {
+    let messages = run_session(vec![json!({ "id": 1, "method": "workspace/unknown" })])?;
+
+    let capabilities = &result(&messages, 0)["capabilities"];
+    assert_eq!(capabilities["hoverProvider"], true);
+    assert_eq!(capabilities["definitionProvider"], true);
+    assert_eq!(capabilities["textDocumentSync"], 1);
+
+    assert_eq!(messages.iter().find(|m| m["id"] == 1).unwrap()["error"]["code"], -32601);
+    assert_eq!(*result(&messages, 1000), Value::Null);
+    Ok(())
+}
+
+#[test]
+pub fn lsp_diagnostics_follow_changes() -> Result<(), Box<dyn std::error::Error>> {
+    let messages = run_session(vec![
+        open(PROGRAM),
+        json!({ "method": "textDocument/didChange", "params": {
+            "textDocument": { "uri": URI, "version": 2 },
+            "contentChanges": [{ "text": "let x: Int = 1;\nprint(x + true);\n" }],
+        } }),
+        json!({ "method": "textDocument/didChange", "params": {
+            "textDocument": { "uri": URI, "version": 3 },
+            "contentChanges": [{ "text": "let x: Int = (1;\n" }],
+        } }),
+        json!({ "method": "textDocument/didClose", "params": { "textDocument": { "uri": URI } } }),
+    ])?;
+
+    let diagnostics = diagnostics(&messages);
+    assert_eq!(diagnostics.len(), 4);
+    assert_eq!(diagnostics[0].as_array().unwrap().len(), 0);
+
+    // the type error is reported on the operator
+    let type_error = &diagnostics[1][0];
+    assert_eq!(type_error["range"]["start"], json!({ "line": 1, "character": 8 }));
+    assert_eq!(type_error["range"]["end"], json!({ "line": 1, "character": 9 }));
+    assert_eq!(type_error["severity"], 1);
+
+    assert_eq!(diagnostics[2].as_array().unwrap().len(), 1);
+    assert_eq!(diagnostics[3].as_array().unwrap().len(), 0);
+    Ok(())
+}
+
+#[test]
+pub fn lsp_hover_and_definition() -> Result<(), Box<dyn std::error::Error>> {
+    let messages = run_session(vec![
+        open(PROGRAM),
+        // `m` in the body
+        request(1, "textDocument/hover", 2, 4),
+        // `double` in the call
+        request(2, "textDocument/hover", 5, 7),
+        // the `*` of `n * 2`
+        request(3, "textDocument/hover", 1, 19),
+        request(4, "textDocument/definition", 5, 7),
+        // `x` in the call
+        request(5, "textDocument/definition", 5, 13),
+        // `n` inside the body
+        request(6, "textDocument/definition", 1, 17),
+    ])?;
+
+    assert_eq!(result(&messages, 1)["contents"]["value"], "m: Int");
+    assert_eq!(result(&messages, 2)["contents"]["value"], "double: Fn([Int]) -> Int");
+    assert_eq!(result(&messages, 3)["contents"]["value"], "Int");
+
+    assert_eq!(result(&messages, 4)["uri"], URI);
+    assert_eq!(result(&messages, 4)["range"]["start"], json!({ "line": 0, "character": 4 }));
+    assert_eq!(result(&messages, 5)["range"]["start"], json!({ "line": 4, "character": 4 }));
+    assert_eq!(result(&messages, 6)["range"]["start"], json!({ "line": 0, "character": 11 }));
+    Ok(())
+}
+
+#[test]
+pub fn lsp_document_symbols_and_completion() -> Result<(), Box<dyn std::error::Error>> {
+    let messages = run_session(vec![
+        open(PROGRAM),
+        json!({ "id": 1, "method": "textDocument/documentSymbol", "params": { "textDocument": { "uri": URI } } }),
+        // inside the body of `double`
+        request(2, "textDocument/completion", 2, 4),
+        // at the top level, after the function
+        request(3, "textDocument/completion", 5, 0),
+    ])?;
+
+    let symbols = result(&messages, 1).as_array().unwrap();
+    assert_eq!(symbols.len(), 1);
+    assert_eq!(symbols[0]["name"], "double");
+    assert_eq!(symbols[0]["kind"], 12);
+    assert_eq!(symbols[0]["range"]["start"], json!({ "line": 0, "character": 0 }));
+    assert_eq!(symbols[0]["range"]["end"], json!({ "line": 3, "character": 1 }));
+
+    let labels = |id| -> Vec<String> {
+        result(&messages, id).as_array().unwrap().iter().map(|item| item["label"].as_str().unwrap().to_string()).collect()
+    };
+
+    let in_body = labels(2);
+    assert!(["double", "n", "m", "Int", "String"].iter().all(|name| in_body.contains(&name.to_string())));
+    assert!(!in_body.contains(&String::from("x")));
+
+    let top_level = labels(3);
+    assert!(top_level.contains(&String::from("double")));
+    assert!(top_level.contains(&String::from("x")));
+    assert!(!top_level.contains(&String::from("n")));
+    assert!(!top_level.contains(&String::from("m")));
+    Ok(())
+}
@@ -0,0 +1,153 @@
+use std::{cell::RefCell, rc::Rc};
+
+use theta::program::Program;
+use theta_types::bytecode::{ThetaValue, ThetaHeapValue};
+use theta_vm::vm::{VM, VmObserver, LoadedFunction, Instruction};
+
+#[test]
+pub fn observer_sees_vm_events() -> Result<(), Box<dyn std::error::Error>> {
+    #[derive(Default)]
+    struct Recorder {
+        events: Rc<RefCell<Vec<String>>>,
+        instructions: Rc<RefCell<u64>>,
+    }
+
+    impl VmObserver for Recorder {
+        fn on_instruction(&mut self, _function: Option<&LoadedFunction>, _offset: usize, _instruction: Instruction, _stack: &[ThetaValue]) {
+            *self.instructions.borrow_mut() += 1;
+        }
+
+        fn on_call(&mut self, function: &LoadedFunction, args: &[ThetaValue], tail_call: bool) {
+            self.events.borrow_mut().push(format!("call {} {:?} {}", function.function.name.internal(), args, tail_call));
+        }
+
+        fn on_return(&mut self, function: Option<&LoadedFunction>, value: Option<&ThetaValue>) {
+            let name = function.map(|func| func.function.name.internal().to_string());
+            self.events.borrow_mut().push(format!("return {:?} {:?}", name, value));
+        }
+
+        fn on_global_define(&mut self, name: &str, value: &ThetaValue) {
+            self.events.borrow_mut().push(format!("global {} {:?}", name, value));
+        }
+
+        fn on_alloc(&mut self, value: &ThetaHeapValue, _size: usize) {
+            self.events.borrow_mut().push(format!("alloc {:?}", value));
+        }
+    }
+
+    let code = 
+    "fun shout(word: String) -> String {
+        word + \"!\"
+    }
+    let loud: String = shout(\"hey\");";
+
+    let recorder = Recorder::default();
+    let events = recorder.events.clone();
+    let instructions = recorder.instructions.clone();
+
+    let mut machine = VM::new(Box::new(std::io::sink()));
+    let program = Program::compile(code)?;
+    program.load(&mut machine)?;
+    // constants are allocated while loading, before anything is observed
+    let observer = machine.add_observer(Box::new(recorder));
+    machine.execute_code()?;
+
+    assert_eq!(*instructions.borrow(), machine.executed());
+    let events = events.borrow();
+    let hey = "Pointer(Str(ThetaString { internal: \"hey\" }))";
+    let shouted = "Pointer(Str(ThetaString { internal: \"hey!\" }))";
+    assert_eq!(events.as_slice(), &[
+        String::from("call <script> [] false"),
+        format!("call shout [{}] false", hey),
+        String::from("alloc Str(ThetaString { internal: \"hey!\" })"),
+        format!("return Some(\"shout\") Some({})", shouted),
+        format!("global loud {}", shouted),
+        String::from("return Some(\"<script>\") None"),
+        String::from("return None None"),
+    ]);
+
+    assert!(machine.remove_observer(observer).is_some());
+    Ok(())
+}
+
+#[test]
+pub fn observers_run_side_by_side() -> Result<(), Box<dyn std::error::Error>> {
+    use theta_vm::vm::Profiler;
+
+    #[derive(Default)]
+    struct Loads {
+        names: Vec<String>,
+    }
+
+    impl VmObserver for Loads {
+        fn on_load(&mut self, function: &LoadedFunction) {
+            self.names.push(function.function.name.internal().to_string());
+        }
+    }
+
+    let code = 
+    "fun inc(n: Int) -> Int {
+        n + 1
+    }
+    let x: Int = inc(inc(1));";
+
+    let mut machine = VM::new(Box::new(std::io::sink()));
+    let program = Program::compile(code)?;
+    program.load(&mut machine)?;
+
+    // functions loaded before an observer is added are still reported to it
+    let loads = Rc::new(RefCell::new(Loads::default()));
+    let profiler = Rc::new(RefCell::new(Profiler::new()));
+    machine.add_observer(Box::new(loads.clone()));
+    let observer = machine.add_observer(Box::new(profiler.clone()));
+    machine.execute_code()?;
+
+    let mut names = loads.borrow().names.clone();
+    names.sort();
+    assert_eq!(names, vec!["<script>", "inc"]);
+
+    assert!(machine.remove_observer(observer).is_some());
+    let profile = profiler.take().finish();
+    assert_eq!(profile.function("inc").expect("inc was called").calls, 2);
+    assert_eq!(profile.instructions(), machine.executed());
+    Ok(())
+}
+
+#[test]
+pub fn observers_see_unwinds() -> Result<(), Box<dyn std::error::Error>> {
+    use theta_vm::vm::Profiler;
+
+    #[derive(Default)]
+    struct Unwinds {
+        count: u64,
+    }
+
+    impl VmObserver for Unwinds {
+        fn on_unwind(&mut self) {
+            self.count += 1;
+        }
+    }
+
+    let mut machine = VM::new(Box::new(std::io::sink()));
+    let unwinds = Rc::new(RefCell::new(Unwinds::default()));
+    let profiler = Rc::new(RefCell::new(Profiler::new().with_sample_interval(1)));
+    machine.add_observer(Box::new(unwinds.clone()));
+    machine.add_observer(Box::new(profiler.clone()));
+
+    let failing = Program::compile("fun fail(s: String) -> Int {\n    s as Int\n}\nlet x: Int = fail(\"x\");")?;
+    failing.load(&mut machine)?;
+    assert!(machine.execute_code().is_err());
+    assert_eq!(unwinds.borrow().count, 1);
+
+    // the failed frames are gone, so later samples are not charged to them
+    let next = Program::compile("fun ok() -> Int {\n    1\n}\nlet y: Int = ok();")?;
+    next.load(&mut machine)?;
+    machine.execute_code()?;
+
+    let profile = profiler.take().finish();
+    let stacks: Vec<&Vec<String>> = profile.stacks.iter().map(|(stack, _)| stack).filter(|stack| stack.iter().any(|name| name == "ok")).collect();
+    assert!(!stacks.is_empty());
+    assert!(stacks.iter().all(|stack| !stack.iter().any(|name| name == "fail")));
+    assert!(stacks.iter().all(|stack| stack.len() == 3));
+    Ok(())
+}
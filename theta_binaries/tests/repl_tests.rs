@@ -0,0 +1,268 @@
+use std::{cell::RefCell, io::Write, rc::Rc};
+
+use theta::repl::{Repl, is_incomplete, editor::ReplHelper};
+use theta_types::{bytecode::{ThetaValue, Symbol}, types::TypeInformation};
+use theta_vm::vm::RuntimeError;
+
+#[derive(Clone, Default)]
+struct Output(Rc<RefCell<Vec<u8>>>);
+
+impl Output {
+    /// Everything written since the last call.
+    fn take(&self) -> String {
+        String::from_utf8(self.0.take()).expect("output is UTF-8")
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+pub fn failed_lines_keep_session() -> Result<(), Box<dyn std::error::Error>> {
+    let mut repl = Repl::new(Box::new(std::io::sink()));
+    repl.line(String::from("let x: Int = 1;"))?;
+    repl.line(String::from("fun double(n: Int) -> Int { n * 2 }"))?;
+
+    // a typo, a type error and a runtime error each leave the session as it was
+    assert!(repl.line(String::from("let y: Int = ;")).is_err());
+    assert!(repl.line(String::from("let y: Int = true;")).is_err());
+    repl.line(String::from("fun forever(n: Int) -> Int { forever(n + 1) + 1 }"))?;
+    assert!(repl.line(String::from("let z: Int = 5; let w: Int = forever(1);")).is_err());
+
+    assert_eq!(repl.machine().global("x"), Some(&ThetaValue::Int(1)));
+    assert_eq!(repl.machine().global("z"), None);
+    assert_eq!(repl.machine().stack().depth(), 0);
+    repl.line(String::from("let y: Int = double(x);"))?;
+    assert_eq!(repl.machine().global("y"), Some(&ThetaValue::Int(2)));
+
+    Ok(())
+}
+
+#[test]
+pub fn failed_lines_drop_their_symbols() -> Result<(), Box<dyn std::error::Error>> {
+    let mut repl = Repl::new(Box::new(std::io::sink()));
+
+    // the first function checks, but the line fails as a whole
+    assert!(repl.line(String::from("fun good() -> Int { 1 } fun bad() -> Int { true }")).is_err());
+    assert!(repl.symbols().borrow().symbols().all(|(symbol, _)| *symbol != Symbol::from("good")));
+    assert!(repl.line(String::from("let x: Int = good();")).is_err());
+
+    repl.line(String::from("fun good() -> Int { 1 }"))?;
+    repl.line(String::from("let x: Int = good();"))?;
+    assert_eq!(repl.machine().global("x"), Some(&ThetaValue::Int(1)));
+
+    Ok(())
+}
+
+#[test]
+pub fn open_braces_continue_entry() {
+    assert!(is_incomplete("fun double(n: Int) -> Int {"));
+    assert!(is_incomplete("print(double("));
+    assert!(is_incomplete("fun f() -> String { \"}\""));
+    assert!(!is_incomplete("fun double(n: Int) -> Int {\n    n * 2\n}"));
+    assert!(!is_incomplete("print(\"(\");"));
+    // too many closing braces is a syntax error, not something to wait for
+    assert!(!is_incomplete("}"));
+}
+
+#[test]
+pub fn completes_session_names() -> Result<(), Box<dyn std::error::Error>> {
+    let mut repl = Repl::new(Box::new(std::io::sink()));
+    let helper = ReplHelper::new(repl.symbols().clone());
+    repl.line(String::from("let total: Int = 1;"))?;
+    repl.line(String::from("fun double(n: Int) -> Int {\n    n * 2\n}"))?;
+
+    let names = |prefix: &str| helper.completions(prefix).into_iter().map(|pair| pair.replacement).collect::<Vec<String>>();
+    assert_eq!(names("do"), vec!["double"]);
+    assert_eq!(names("t"), vec!["total"]);
+    assert_eq!(names("S"), vec!["String"]);
+    assert!(names("").contains(&String::from("Int")));
+    assert_eq!(helper.completions("dou")[0].display, "double(Int) -> Int");
+
+    Ok(())
+}
+
+#[test]
+pub fn commands_inspect_session() -> Result<(), Box<dyn std::error::Error>> {
+    let output = Output::default();
+    let mut repl = Repl::new(Box::new(output.clone()));
+    repl.line(String::from("let x: Int = 21;"))?;
+    repl.line(String::from("fun double(n: Int) -> Int { n * 2 }"))?;
+    output.take();
+
+    repl.line(String::from(":type double(x) + 1"))?;
+    assert_eq!(output.take(), "Int\n");
+    // checking a type runs nothing and defines nothing
+    repl.line(String::from(":type 1.5"))?;
+    assert_eq!(output.take(), "Float\n");
+    assert!(repl.line(String::from(":type y")).is_err());
+
+    repl.line(String::from(":globals"))?;
+    assert_eq!(output.take(), "x: Int = 21\n");
+    repl.line(String::from(":functions"))?;
+    assert_eq!(output.take(), "double(Int) -> Int\n");
+
+    repl.line(String::from(":disasm double"))?;
+    let disassembly = output.take();
+    assert!(disassembly.starts_with("double(Int) -> Int\n"));
+    assert!(disassembly.lines().any(|line| line.ends_with("Multiply")));
+    assert!(repl.line(String::from(":disasm missing")).is_err());
+
+    assert!(repl.line(String::from(":unknown")).is_err());
+    assert!(repl.line(String::from(":load")).is_err());
+
+    Ok(())
+}
+
+#[test]
+pub fn save_reset_and_load() -> Result<(), Box<dyn std::error::Error>> {
+    let path = std::env::temp_dir().join(format!("theta_session_{}.the", std::process::id()));
+    let output = Output::default();
+    let mut repl = Repl::new(Box::new(output.clone()));
+    repl.line(String::from("let x: Int = 21;"))?;
+    repl.line(String::from("print(x);"))?;
+    repl.line(String::from("fun double(n: Int) -> Int { n * 2 }"))?;
+    repl.line(format!(":save {}", path.display()))?;
+
+    let saved = std::fs::read_to_string(&path)?;
+    assert_eq!(saved, "let x: Int = 21;\nfun double(n: Int) -> Int { n * 2 }\n");
+
+    repl.line(String::from(":reset"))?;
+    assert!(repl.machine().globals().is_empty());
+    assert!(repl.line(String::from("let y: Int = double(2);")).is_err());
+
+    repl.line(format!(":load {}", path.display()))?;
+    std::fs::remove_file(&path)?;
+    repl.line(String::from("let y: Int = double(x);"))?;
+    assert_eq!(repl.machine().global("y"), Some(&ThetaValue::Int(42)));
+
+    Ok(())
+}
+
+#[test]
+pub fn shows_expression_results() -> Result<(), Box<dyn std::error::Error>> {
+    let output = Output::default();
+    let mut repl = Repl::new(Box::new(output.clone()));
+
+    repl.line(String::from("1 + 2"))?;
+    assert_eq!(output.take(), "3: Int\n");
+    repl.line(String::from("1 + 2;"))?;
+    assert_eq!(output.take(), "3: Int\n");
+    repl.line(String::from("\"a\" + \"b\""))?;
+    assert_eq!(output.take(), "\"ab\": String\n");
+    repl.line(String::from("1.5 == 1.5"))?;
+    assert_eq!(output.take(), "true: Bool\n");
+
+    // declarations, output and functions without a value show nothing
+    repl.line(String::from("let x: Int = 21;"))?;
+    repl.line(String::from("fun shout(n: Int) { print(n); }"))?;
+    assert_eq!(output.take(), "");
+    repl.line(String::from("shout(x);"))?;
+    assert!(!output.take().contains(": "));
+
+    // only the line's final expression is shown
+    repl.line(String::from("x * 2; x * 3"))?;
+    assert_eq!(output.take(), "63: Int\n");
+    assert_eq!(repl.machine().stack().depth(), 0);
+    assert!(repl.machine().stack().values().is_empty());
+
+    Ok(())
+}
+
+#[test]
+pub fn prints_and_formats_values() -> Result<(), Box<dyn std::error::Error>> {
+    let output = Output::default();
+    let mut repl = Repl::new(Box::new(output.clone()));
+
+    repl.line(String::from("print(\"hi\");"))?;
+    repl.line(String::from("print(2.0);"))?;
+    repl.line(String::from("print(1 < 2);"))?;
+    assert_eq!(output.take(), "hi\n2.0\ntrue\n");
+
+    repl.line(String::from("let x: Int = 21;"))?;
+    repl.line(String::from("print(format(\"x = {}, {{x}} = {}\", x, x * 2 == 42));"))?;
+    assert_eq!(output.take(), "x = 21, {x} = true\n");
+    repl.line(String::from("format(\"{}{}\", \"a\", 1.5)"))?;
+    assert_eq!(output.take(), "\"a1.5\": String\n");
+
+    // the placeholders and arguments are checked before anything runs
+    assert!(repl.line(String::from("format(\"{} and {}\", x);")).is_err());
+    assert!(repl.line(String::from("format(\"{}\", print);")).is_err());
+    repl.line(String::from("fun nothing() { }"))?;
+    assert!(repl.line(String::from("format(\"{}\", nothing());")).is_err());
+    assert!(repl.line(String::from("format(\"{\", x);")).is_err());
+    assert!(repl.line(String::from("format(x);")).is_err());
+    assert_eq!(output.take(), "");
+
+    Ok(())
+}
+
+#[test]
+pub fn interpolates_strings() -> Result<(), Box<dyn std::error::Error>> {
+    let output = Output::default();
+    let mut repl = Repl::new(Box::new(output.clone()));
+    repl.line(String::from("let a: Int = 1;"))?;
+    repl.line(String::from("let b: Int = 2;"))?;
+
+    repl.line(String::from("print(\"total: ${a + b}\");"))?;
+    repl.line(String::from("print(\"${a < b}, ${1.5}, ${\"nested ${b}\"}, {} and $\");"))?;
+    assert_eq!(output.take(), "total: 3\ntrue, 1.5, nested 2, {} and $\n");
+
+    repl.line(String::from("fun describe(n: Int) -> String { \"n is ${if (n < 0) { \"negative\" } else { \"not negative\" }}\" }"))?;
+    repl.line(String::from("describe(0 - 1)"))?;
+    assert_eq!(output.take(), "\"n is negative\": String\n");
+
+    repl.line(String::from("fun nothing() { }"))?;
+    assert!(repl.line(String::from("\"${nothing()}\";")).is_err());
+    assert!(repl.line(String::from("\"${missing}\";")).is_err());
+    assert!(repl.line(String::from("\"${a b}\";")).is_err());
+    assert!(repl.line(String::from("\"${a\";")).is_err());
+
+    Ok(())
+}
+
+#[test]
+pub fn casts_between_types() -> Result<(), Box<dyn std::error::Error>> {
+    let output = Output::default();
+    let mut repl = Repl::new(Box::new(output.clone()));
+
+    let shows = |repl: &mut Repl, line: &str| -> Result<String, Box<dyn std::error::Error>> {
+        repl.line(String::from(line))?;
+        Ok(output.take())
+    };
+    assert_eq!(shows(&mut repl, "1 as Float + 0.5")?, "1.5: Float\n");
+    assert_eq!(shows(&mut repl, "-2.75 as Int")?, "-2: Int\n");
+    assert_eq!(shows(&mut repl, "true as Int * 3")?, "3: Int\n");
+    assert_eq!(shows(&mut repl, "2.0 as String")?, "\"2.0\": String\n");
+    assert_eq!(shows(&mut repl, "\"41\" as Int + 1")?, "42: Int\n");
+    assert_eq!(shows(&mut repl, "\"0.25\" as Float")?, "0.25: Float\n");
+    assert_eq!(shows(&mut repl, "\"false\" as Bool")?, "false: Bool\n");
+    assert_eq!(shows(&mut repl, "1 as Int as String as Int")?, "1: Int\n");
+
+    // casts without a conversion are rejected before anything runs
+    assert!(repl.line(String::from("1.5 as Bool;")).is_err());
+    assert!(repl.line(String::from("1 as Missing;")).is_err());
+    repl.line(String::from("fun nothing() { }"))?;
+    assert!(repl.line(String::from("nothing() as String;")).is_err());
+
+    // strings that do not hold a value of the type fail when they are parsed
+    let error = repl.line(String::from("\"4x\" as Int;")).err().expect("the parse fails");
+    assert!(matches!(error.downcast_ref::<RuntimeError>(), Some(RuntimeError::InvalidConversion { input, ty: TypeInformation::Int }) if input == "4x"));
+    assert!(repl.line(String::from("\"yes\" as Bool;")).is_err());
+
+    // as do floats an Int cannot hold
+    let error = repl.line(String::from("(1.0 / 0.0) as Int;")).err().expect("infinity is not an Int");
+    assert!(matches!(error.downcast_ref::<RuntimeError>(), Some(RuntimeError::InvalidConversion { input, ty: TypeInformation::Int }) if input == "inf"));
+    assert!(matches!(repl.line(String::from("(0.0 / 0.0) as Int;")).err().and_then(|e| e.downcast::<RuntimeError>().ok()).as_deref(), Some(RuntimeError::InvalidConversion { .. })));
+    assert!(matches!(repl.line(String::from("10000000000000000000000.0 as Int;")).err().and_then(|e| e.downcast::<RuntimeError>().ok()).as_deref(), Some(RuntimeError::InvalidConversion { .. })));
+    assert_eq!(repl.machine().stack().depth(), 0);
+
+    Ok(())
+}
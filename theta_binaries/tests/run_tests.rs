@@ -0,0 +1,113 @@
+use std::process::Command;
+
+const PROGRAM: &str =
+"fun fib(n: Int) -> Int {
+    if (n <= 1) { n } else { fib(n - 1) + fib(n - 2) }
+}
+print(fib(15));
+";
+
+#[test]
+pub fn run_with_profile() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = std::env::temp_dir();
+    let path = dir.join(format!("theta_run_{}.the", std::process::id()));
+    let folded = dir.join(format!("theta_run_{}.folded", std::process::id()));
+    std::fs::write(&path, PROGRAM)?;
+
+    let output = Command::new(env!("CARGO_BIN_EXE_theta")).arg("run").arg(&path).arg("--profile").arg("--folded").arg(&folded).output()?;
+    let folded_stacks = std::fs::read_to_string(&folded)?;
+    std::fs::remove_file(&path)?;
+    std::fs::remove_file(&folded)?;
+    assert!(output.status.success());
+
+    // the program's output is kept apart from the report
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("610"));
+    let report = String::from_utf8(output.stderr)?;
+    assert!(report.lines().next().is_some_and(|header| header.contains("calls") && header.contains("instructions")));
+    // rows are ordered by time, so only the counts of fib's row are stable
+    let fib: Vec<&str> = report.lines().map(|line| line.split_whitespace().collect::<Vec<_>>()).find(|columns| columns.last() == Some(&"fib")).expect("fib should be profiled");
+    assert_eq!(fib[0..2], ["1973", "22686"]);
+
+    assert!(folded_stacks.lines().all(|line| line.starts_with("<top level>;<script>")));
+    Ok(())
+}
+
+const BRANCHES: &str =
+"fun unused() -> Int {
+    42
+}
+
+fun classify(n: Int) -> Int {
+    if (n < 0) {
+        0 - 1
+    } else {
+        1
+    }
+}
+let i: Int = 0;
+while (i < 3) {
+    i = i + 1;
+};
+print(classify(5));
+";
+
+#[test]
+pub fn run_with_coverage() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = std::env::temp_dir();
+    let path = dir.join(format!("theta_coverage_{}.the", std::process::id()));
+    let lcov = dir.join(format!("theta_coverage_{}.info", std::process::id()));
+    std::fs::write(&path, BRANCHES)?;
+
+    let output = Command::new(env!("CARGO_BIN_EXE_theta")).arg("run").arg(&path).arg("--coverage").arg(&lcov).output()?;
+    assert!(output.status.success());
+    let tracefile = std::fs::read_to_string(&lcov)?;
+
+    let gated = Command::new(env!("CARGO_BIN_EXE_theta")).arg("run").arg(&path).arg("--coverage").arg(&lcov).arg("--fail-under").arg("80").output()?;
+    std::fs::remove_file(&path)?;
+    std::fs::remove_file(&lcov)?;
+
+    let lines: Vec<&str> = tracefile.lines().collect();
+    assert_eq!(lines[1], format!("SF:{}", path.display()));
+    assert!(lines.contains(&"FN:6,classify"));
+    assert!(lines.contains(&"FNDA:0,unused"));
+    assert!(lines.contains(&"FNDA:1,classify"));
+    // the branch that was not taken, and the loop condition checked once more than its body ran
+    assert!(lines.contains(&"DA:7,0"));
+    assert!(lines.contains(&"DA:9,1"));
+    assert!(lines.contains(&"DA:13,4"));
+    assert!(lines.contains(&"DA:14,3"));
+    assert!(lines.contains(&"LF:8"));
+    assert!(lines.contains(&"LH:6"));
+    assert_eq!(lines.last(), Some(&"end_of_record"));
+
+    // 75% of lines ran
+    assert!(!gated.status.success());
+    assert!(String::from_utf8(gated.stderr)?.contains("75.00%"));
+    Ok(())
+}
+
+#[test]
+pub fn annotated_coverage() -> Result<(), Box<dyn std::error::Error>> {
+    use theta::{coverage::CoverageCollector, program::Program};
+    use theta_vm::vm::VM;
+
+    let mut machine = VM::new(Box::new(std::io::sink()));
+    Program::compile(BRANCHES)?.load(&mut machine)?;
+    let collector = CoverageCollector::attach(&mut machine);
+    machine.execute_code()?;
+    let coverage = collector.finish(&mut machine);
+
+    assert_eq!(coverage.lines_hit(), 6);
+    assert_eq!(coverage.functions.iter().map(|func| (func.name.as_str(), func.calls)).collect::<Vec<_>>(), [("unused", 0), ("classify", 1), ("<script>", 1)]);
+
+    let mut report = Vec::new();
+    coverage.write_annotated(&mut report, BRANCHES)?;
+    let report = String::from_utf8(report)?;
+    let lines: Vec<&str> = report.lines().collect();
+    assert_eq!(lines[0], "        - | fun unused() -> Int {");
+    assert_eq!(lines[1], "    ##### |     42");
+    assert_eq!(lines[12], "        4 | while (i < 3) {");
+    assert!(report.ends_with("lines: 6/8 (75.00%)\nfunctions: 1/2\n"));
+    Ok(())
+}
@@ -0,0 +1,52 @@
+use std::{cell::RefCell, rc::Rc};
+
+use serde_json::{json, Value};
+use theta::trace::TraceSummary;
+use theta_types::bytecode::ThetaValue;
+use theta_vm::vm::{ThetaCallFrame, Tracer};
+
+use crate::common::identity;
+
+mod common;
+
+#[test]
+pub fn tracer_records_instructions() -> Result<(), Box<dyn std::error::Error>> {
+    let code = 
+    "fun double(n: Int) -> Int {
+        n * 2
+    }";
+
+    let stdout = common::TestOutput::new();
+    let trace = common::TestOutput::new();
+
+    let (mut machine, loaded_bs, compiled_chunk) = crate::common::build_test_vm(code, "double", identity, Box::new(stdout.clone()))?;
+    let tracer = Rc::new(RefCell::new(Tracer::new(Box::new(trace.clone())).with_stack_values(2)));
+    let observer = machine.add_observer(Box::new(tracer.clone()));
+
+    machine.push_value(ThetaValue::Int(21));
+    machine.push_frame(ThetaCallFrame { rip: 0, base: 0, function: None, bitstream: loaded_bs, chunk: compiled_chunk });
+    machine.execute_code()?;
+    assert!(machine.remove_observer(observer).is_some());
+    tracer.borrow_mut().finish()?;
+
+    let trace = String::from_utf8(trace.inner.borrow().clone())?;
+    let records = trace.lines().map(serde_json::from_str).collect::<Result<Vec<Value>, _>>()?;
+    assert_eq!(records.len() as u64, machine.executed());
+
+    assert_eq!(records[0]["function"], Value::Null);
+    assert_eq!(records[0]["op"], "Call");
+    assert_eq!(records[0]["text"], "Call double");
+
+    let multiply = records.iter().find(|record| record["op"] == "Multiply").expect("double multiplies");
+    assert_eq!(multiply["function"], "double");
+    assert_eq!(multiply["line"], 2);
+    // topmost first, limited to two values
+    assert_eq!(multiply["stack"], json!([2, 21]));
+
+    let summary = TraceSummary::from_reader(trace.as_bytes())?;
+    assert_eq!(summary.instructions, machine.executed());
+    assert_eq!(summary.ops["Multiply"], 1);
+    assert_eq!(summary.functions[&Some(String::from("double"))], records.iter().filter(|record| record["function"] == "double").count() as u64);
+
+    Ok(())
+}
@@ -10,7 +10,6 @@ mod common;
 
 #[test]
 pub fn fibbonaci_test() -> Result<(), Box<dyn std::error::Error>> {
-    use std::rc::Rc;
     use theta_vm::vm::ThetaCallFrame;
 
     let code = 
@@ -27,14 +26,15 @@ pub fn fibbonaci_test() -> Result<(), Box<dyn std::error::Error>> {
     let (mut machine, loaded_bs, compiled_chunk) = crate::common::build_test_vm(code, "fib", identity, Box::new(stdout.clone()))?;
 
     // do the magic stack frame thing
-    machine.push_frame(ThetaCallFrame { rip: 0, locals: vec![Some(ThetaValue::Int(10))], bitstream: loaded_bs, chunk: Rc::new(compiled_chunk) });
+    machine.push_value(ThetaValue::Int(10));
+    machine.push_frame(ThetaCallFrame { rip: 0, base: 0, function: None, bitstream: loaded_bs, chunk: compiled_chunk });
 
     // execute chunk
     machine.execute_code()?;
     
     let output = stdout.inner.borrow();
     assert_eq!(output.as_slice(), &[]);
-    assert_eq!(machine.stack().curr_frame().expect("failed to get stack").locals.last().expect("nothing on top of stack").clone().expect("nothing on top of stack").clone(), ThetaValue::Int(55));
+    assert_eq!(machine.stack().peek().expect("nothing on top of stack").clone(), ThetaValue::Int(55));
 
 
     Ok(())
@@ -43,7 +43,6 @@ pub fn fibbonaci_test() -> Result<(), Box<dyn std::error::Error>> {
 
 #[test]
 pub fn loop_test_1() -> Result<(), Box<dyn std::error::Error>> {
-    use std::rc::Rc;
     use theta_vm::vm::ThetaCallFrame;
 
     let code = 
@@ -60,24 +59,14 @@ pub fn loop_test_1() -> Result<(), Box<dyn std::error::Error>> {
     let (mut machine, loaded_bs, compiled_chunk) = crate::common::build_test_vm(code, "looptest", identity, Box::new(stdout.clone()))?;
 
     // do the magic stack frame thing
-    machine.push_frame(ThetaCallFrame { rip: 0, locals: vec![], bitstream: loaded_bs, chunk: Rc::new(compiled_chunk) });
+    machine.push_frame(ThetaCallFrame { rip: 0, base: 0, function: None, bitstream: loaded_bs, chunk: compiled_chunk });
 
     // execute chunk
     machine.execute_code()?;
     
     let output = stdout.inner.borrow();
     let stdout_str = String::from_utf8(output.clone()).expect("failed to convert stdout to string");
-    assert_eq!(&stdout_str, "Some(Pointer(Str(ThetaString { internal: \"hello, world\" })))
-Some(Pointer(Str(ThetaString { internal: \"hello, world\" })))
-Some(Pointer(Str(ThetaString { internal: \"hello, world\" })))
-Some(Pointer(Str(ThetaString { internal: \"hello, world\" })))
-Some(Pointer(Str(ThetaString { internal: \"hello, world\" })))
-Some(Pointer(Str(ThetaString { internal: \"hello, world\" })))
-Some(Pointer(Str(ThetaString { internal: \"hello, world\" })))
-Some(Pointer(Str(ThetaString { internal: \"hello, world\" })))
-Some(Pointer(Str(ThetaString { internal: \"hello, world\" })))
-Some(Pointer(Str(ThetaString { internal: \"hello, world\" })))
-");
+    assert_eq!(stdout_str, "hello, world\n".repeat(10));
 
 
     Ok(())
@@ -85,7 +74,6 @@ Some(Pointer(Str(ThetaString { internal: \"hello, world\" })))
 }
 
 pub fn loop_test_bp() -> Result<(), Box<dyn std::error::Error>> {
-    use std::rc::Rc;
     use theta_vm::vm::ThetaCallFrame;
 
     let code = 
@@ -102,7 +90,7 @@ pub fn loop_test_bp() -> Result<(), Box<dyn std::error::Error>> {
     }, Box::new(stdout.clone()))?;
 
     // do the magic stack frame thing
-    machine.push_frame(ThetaCallFrame { rip: 0, locals: vec![], bitstream: loaded_bs, chunk: Rc::new(compiled_chunk) });
+    machine.push_frame(ThetaCallFrame { rip: 0, base: 0, function: None, bitstream: loaded_bs, chunk: compiled_chunk });
 
     // execute chunk
     machine.execute_code()?;
@@ -124,4 +112,599 @@ Some(Pointer(Str(ThetaString { internal: \"hello, world\" })))
 
     Ok(())
 
-}
\ No newline at end of file
+}
+
+#[test]
+pub fn if_far_jump_test() -> Result<(), Box<dyn std::error::Error>> {
+    use theta_vm::vm::ThetaCallFrame;
+
+    // the else branch is well over 127 bytes, so the builder must relax both jumps to far jumps.
+    let code = format!(
+    "fun far(n: Int) -> Int {{
+        if (n <= 1) {{
+            n
+        }} else {{
+            {}
+        }}
+    }}", vec!["n"; 60].join(" + "));
+
+    let stdout = common::TestOutput::new();
+
+    let (mut machine, loaded_bs, compiled_chunk) = crate::common::build_test_vm(&code, "far", identity, Box::new(stdout.clone()))?;
+
+    machine.push_value(ThetaValue::Int(2));
+    machine.push_frame(ThetaCallFrame { rip: 0, base: 0, function: None, bitstream: loaded_bs, chunk: compiled_chunk });
+    machine.execute_code()?;
+
+    assert_eq!(machine.stack().peek().expect("nothing on top of stack").clone(), ThetaValue::Int(120));
+
+    Ok(())
+}
+
+#[test]
+pub fn decode_chunk_resolves_jumps() -> Result<(), Box<dyn std::error::Error>> {
+    use theta_types::bytecode::{BasicAssembler, Assembler, OpCode, ThetaConstant, DisassembleError};
+    use theta_types::build_chunk;
+    use theta_vm::vm::{decode_chunk, Instruction};
+
+    let chunk = build_chunk!(OpCode::Constant { offset: 0 }, OpCode::JumpFarIfFalse { offset: 10 }, OpCode::Pop, OpCode::JumpLocal { offset: -10 }; ThetaConstant::Bool(true));
+    let mut bytes = Vec::new();
+    BasicAssembler::new(&mut bytes).assemble_chunk(chunk)?;
+
+    let code = decode_chunk(&bytes)?;
+    assert_eq!(code.as_ref(), &[
+        Instruction::Op(OpCode::Constant { offset: 0 }),
+        Instruction::JumpIfFalse { target: 3 },
+        Instruction::Op(OpCode::Pop),
+        Instruction::Jump { target: 1 },
+    ]);
+
+    let chunk = build_chunk!(OpCode::JumpLocal { offset: 1 }, OpCode::Pop);
+    let mut bytes = Vec::new();
+    BasicAssembler::new(&mut bytes).assemble_chunk(chunk)?;
+    assert!(matches!(decode_chunk(&bytes), Err(DisassembleError::InvalidJumpTarget(1))));
+
+    // a size that runs past the end of the chunk, even overflowing
+    bytes[8..16].copy_from_slice(&usize::to_le_bytes(usize::MAX));
+    assert!(matches!(decode_chunk(&bytes), Err(DisassembleError::TruncatedInstruction(_))));
+
+    Ok(())
+}
+
+#[test]
+pub fn calls_resolved_at_load_time() -> Result<(), Box<dyn std::error::Error>> {
+    use theta_types::bytecode::{OpCode, ThetaString};
+    use theta_vm::vm::Instruction;
+
+    let code = 
+    "fun fib(n: Int) -> Int {
+        if (n <= 1) {
+            n
+        } else {
+            fib(n-1) + fib(n-2)
+        }
+    }";
+
+    let (machine, _, harness) = crate::common::build_test_vm(code, "fib", identity, Box::new(common::TestOutput::new()))?;
+
+    let fib_index = *machine.function_indices().get(&ThetaString::new(String::from("fib"))).expect("fib should have an index");
+    let fib = machine.function(&ThetaString::new(String::from("fib"))).expect("fib should be loaded");
+
+    assert_eq!(harness.as_ref(), &[Instruction::Call { function: fib_index }]);
+    assert_eq!(fib.code.iter().filter(|inst| **inst == Instruction::Call { function: fib_index }).count(), 2);
+    assert!(!fib.code.iter().any(|inst| matches!(inst, Instruction::Op(OpCode::CallDirect { .. }))));
+
+    Ok(())
+}
+
+#[test]
+pub fn frames_share_value_stack() -> Result<(), Box<dyn std::error::Error>> {
+    use theta_vm::vm::ThetaCallFrame;
+
+    let code = 
+    "fun depth(n: Int) -> Int {
+        let m: Int = n - 1;
+        if (m < 0) {
+            0
+        } else {
+            depth(m) + 1
+        }
+    }";
+
+    let stdout = common::TestOutput::new();
+
+    let (mut machine, loaded_bs, compiled_chunk) = crate::common::build_test_vm(code, "depth", identity, Box::new(stdout.clone()))?;
+    assert_eq!(machine.function(&theta_types::bytecode::ThetaString::new(String::from("depth"))).expect("depth should be loaded").function.locals, 1);
+
+    machine.push_value(ThetaValue::Int(5000));
+    machine.push_frame(ThetaCallFrame { rip: 0, base: 0, function: None, bitstream: loaded_bs, chunk: compiled_chunk });
+
+    machine.execute_code()?;
+
+    // every callee frame has been unwound, leaving only the result where the argument was
+    assert_eq!(machine.stack().values(), &[ThetaValue::Int(5000)]);
+
+    Ok(())
+}
+
+#[test]
+pub fn tail_calls_reuse_frame() -> Result<(), Box<dyn std::error::Error>> {
+    use theta_types::bytecode::ThetaString;
+    use theta_vm::vm::{ThetaCallFrame, Instruction};
+
+    let code = 
+    "fun count(n: Int, acc: Int) -> Int {
+        if (n <= 0) {
+            acc
+        } else {
+            count(n - 1, acc + 1)
+        }
+    }";
+
+    let stdout = common::TestOutput::new();
+
+    let (mut machine, loaded_bs, compiled_chunk) = crate::common::build_test_vm(code, "count", identity, Box::new(stdout.clone()))?;
+
+    let count_index = *machine.function_indices().get(&ThetaString::new(String::from("count"))).expect("count should have an index");
+    let count = machine.function(&ThetaString::new(String::from("count"))).expect("count should be loaded");
+    assert!(count.code.contains(&Instruction::TailCall { function: count_index }));
+    assert!(!count.code.iter().any(|inst| matches!(inst, Instruction::Call { .. })));
+
+    machine.push_value(ThetaValue::Int(100000));
+    machine.push_value(ThetaValue::Int(0));
+    machine.push_frame(ThetaCallFrame { rip: 0, base: 0, function: None, bitstream: loaded_bs, chunk: compiled_chunk });
+
+    machine.execute_code()?;
+
+    assert_eq!(machine.stack().values(), &[ThetaValue::Int(100000)]);
+
+    Ok(())
+}
+
+#[test]
+pub fn mutual_tail_calls_reuse_frame() -> Result<(), Box<dyn std::error::Error>> {
+    use theta_types::bytecode::ThetaString;
+    use theta_vm::vm::{ThetaCallFrame, Instruction};
+
+    // even calls odd before odd has been declared
+    let code = 
+    "fun even(n: Int) -> Bool {
+        if (n == 0) {
+            true
+        } else {
+            odd(n - 1)
+        }
+    }
+    fun odd(n: Int) -> Bool {
+        if (n == 0) {
+            false
+        } else {
+            even(n - 1)
+        }
+    }";
+
+    let stdout = common::TestOutput::new();
+
+    let (mut machine, loaded_bs, compiled_chunk) = crate::common::build_test_vm(code, "even", identity, Box::new(stdout.clone()))?;
+
+    let even_index = *machine.function_indices().get(&ThetaString::new(String::from("even"))).expect("even should have an index");
+    let odd_index = *machine.function_indices().get(&ThetaString::new(String::from("odd"))).expect("odd should have an index");
+    let even = machine.function(&ThetaString::new(String::from("even"))).expect("even should be loaded");
+    assert!(even.code.contains(&Instruction::TailCall { function: odd_index }));
+    let odd = machine.function(&ThetaString::new(String::from("odd"))).expect("odd should be loaded");
+    assert!(odd.code.contains(&Instruction::TailCall { function: even_index }));
+
+    // the entry frame plus a single callee frame, so any growth overflows
+    machine.set_max_call_depth(2);
+    machine.push_value(ThetaValue::Int(100000));
+    machine.push_frame(ThetaCallFrame { rip: 0, base: 0, function: None, bitstream: loaded_bs, chunk: compiled_chunk });
+
+    machine.execute_code()?;
+
+    assert_eq!(machine.stack().values(), &[ThetaValue::Bool(true)]);
+
+    Ok(())
+}
+
+#[test]
+pub fn stack_overflow_unwinds() -> Result<(), Box<dyn std::error::Error>> {
+    use theta_vm::vm::{ThetaCallFrame, RuntimeError, StackLimit, BACKTRACE_FRAMES};
+
+    let code = 
+    "fun down(n: Int) -> Int {
+        if (n <= 0) {
+            0
+        } else {
+            down(n - 1) + 1
+        }
+    }";
+
+    let stdout = common::TestOutput::new();
+
+    let (mut machine, loaded_bs, compiled_chunk) = crate::common::build_test_vm(code, "down", identity, Box::new(stdout.clone()))?;
+    machine.set_max_call_depth(100);
+
+    machine.push_value(ThetaValue::Int(1000));
+    machine.push_frame(ThetaCallFrame { rip: 0, base: 0, function: None, bitstream: loaded_bs.clone(), chunk: compiled_chunk.clone() });
+
+    match machine.execute_code() {
+        Err(RuntimeError::StackOverflow { limit, backtrace }) => {
+            assert_eq!(limit, StackLimit::CallDepth(100));
+            // the call that overflowed, the 99 frames of down below it and the top level frame
+            assert_eq!(backtrace.depth(), 101);
+            assert_eq!(backtrace.innermost.len(), BACKTRACE_FRAMES);
+            assert!(backtrace.innermost.iter().all(|frame| frame.as_deref() == Some("down")));
+            assert_eq!(backtrace.outermost.last(), Some(&None));
+        },
+        other => panic!("expected a stack overflow, got {:?}", other),
+    }
+
+    // the VM is left with no frames and can run again
+    assert_eq!(machine.stack().depth(), 0);
+    assert!(machine.stack().is_empty());
+
+    machine.set_max_call_depth(10_000);
+    machine.set_max_stack_size(200);
+    machine.push_value(ThetaValue::Int(1000));
+    machine.push_frame(ThetaCallFrame { rip: 0, base: 0, function: None, bitstream: loaded_bs.clone(), chunk: compiled_chunk.clone() });
+    assert!(matches!(machine.execute_code(), Err(RuntimeError::StackOverflow { limit: StackLimit::StackSize(200), .. })));
+
+    machine.push_value(ThetaValue::Int(50));
+    machine.push_frame(ThetaCallFrame { rip: 0, base: 0, function: None, bitstream: loaded_bs, chunk: compiled_chunk });
+    machine.execute_code()?;
+    assert_eq!(machine.stack().values(), &[ThetaValue::Int(50)]);
+
+    Ok(())
+}
+
+#[test]
+pub fn fuel_pauses_infinite_loop() -> Result<(), Box<dyn std::error::Error>> {
+    use std::time::{Duration, Instant};
+    use theta_vm::vm::{ThetaCallFrame, ExecutionStatus};
+
+    let code = 
+    "fun looptest() {
+        while {
+            print(\"hello, world\");
+        };
+    }";
+
+    let stdout = common::TestOutput::new();
+    let lines = |stdout: &common::TestOutput| stdout.inner.borrow().iter().filter(|b| **b == b'\n').count();
+
+    let (mut machine, loaded_bs, compiled_chunk) = crate::common::build_test_vm(code, "looptest", identity, Box::new(stdout.clone()))?;
+
+    machine.set_fuel(Some(100));
+    machine.push_frame(ThetaCallFrame { rip: 0, base: 0, function: None, bitstream: loaded_bs, chunk: compiled_chunk });
+
+    assert_eq!(machine.execute_code()?, ExecutionStatus::OutOfFuel);
+    assert_eq!(machine.fuel(), Some(0));
+    let printed = lines(&stdout);
+    assert!(printed > 0);
+
+    // resuming without fuel does nothing
+    assert_eq!(machine.resume()?, ExecutionStatus::OutOfFuel);
+    assert_eq!(lines(&stdout), printed);
+
+    machine.add_fuel(100);
+    assert_eq!(machine.resume()?, ExecutionStatus::OutOfFuel);
+    assert!(lines(&stdout) > printed);
+
+    machine.set_fuel(None);
+    machine.set_deadline(Some(Instant::now() + Duration::from_millis(10)));
+    assert_eq!(machine.resume()?, ExecutionStatus::DeadlineExceeded);
+
+    machine.abort();
+    assert_eq!(machine.stack().depth(), 0);
+    assert!(machine.stack().is_empty());
+
+    Ok(())
+}
+
+#[test]
+pub fn heap_limit_stops_allocation() -> Result<(), Box<dyn std::error::Error>> {
+    use theta_vm::vm::{ThetaCallFrame, RuntimeError};
+
+    let code = 
+    "fun grow() {
+        let s: String = \"ab\";
+        while {
+            s = s + s;
+        };
+    }";
+
+    let stdout = common::TestOutput::new();
+
+    let (mut machine, loaded_bs, compiled_chunk) = crate::common::build_test_vm(code, "grow", identity, Box::new(stdout.clone()))?;
+
+    // constants loaded with the bitstream count towards usage
+    let loaded = machine.heap_used();
+    assert!(loaded > 0);
+
+    machine.set_heap_limit(Some(loaded + 4096));
+    machine.push_frame(ThetaCallFrame { rip: 0, base: 0, function: None, bitstream: loaded_bs, chunk: compiled_chunk });
+
+    match machine.execute_code() {
+        Err(RuntimeError::OutOfMemory { requested, limit }) => {
+            assert_eq!(limit, loaded + 4096);
+            assert!(machine.heap_used() + requested > limit);
+        },
+        other => panic!("expected out of memory, got {:?}", other),
+    }
+    assert!(machine.heap_peak() <= loaded + 4096);
+    assert!(machine.heap_peak() >= machine.heap_used());
+
+    // once the frames holding the strings are unwound, they can be collected
+    assert!(machine.collect_garbage() > 0);
+    assert_eq!(machine.heap_used(), loaded);
+    assert!(machine.stack().is_empty());
+
+    Ok(())
+}
+
+#[test]
+pub fn debugger_breakpoints_and_stepping() -> Result<(), Box<dyn std::error::Error>> {
+    use theta_types::bytecode::ThetaString;
+    use theta_vm::vm::{ThetaCallFrame, ExecutionStatus};
+
+    let code = 
+    "fun fact(n: Int) -> Int {
+        let m: Int = n - 1;
+        if (n <= 1) {
+            1
+        } else {
+            n * fact(m)
+        }
+    }";
+
+    let stdout = common::TestOutput::new();
+
+    let (mut machine, loaded_bs, compiled_chunk) = crate::common::build_test_vm(code, "fact", identity, Box::new(stdout.clone()))?;
+
+    let locations = machine.set_line_breakpoint(2);
+    assert_eq!(locations.len(), 1);
+
+    machine.push_value(ThetaValue::Int(3));
+    machine.push_frame(ThetaCallFrame { rip: 0, base: 0, function: None, bitstream: loaded_bs, chunk: compiled_chunk });
+
+    assert_eq!(machine.execute_code()?, ExecutionStatus::Breakpoint);
+    let frames = machine.frames();
+    assert_eq!(frames.len(), 2);
+    assert_eq!(frames[0].function, Some(ThetaString::new(String::from("fact"))));
+    assert_eq!(frames[0].line, Some(2));
+    assert_eq!(frames[0].locals[0], ThetaValue::Int(3));
+    assert_eq!(frames[1].function, None);
+
+    assert_eq!(machine.step_over()?, ExecutionStatus::Stepped);
+    let frames = machine.frames();
+    assert_eq!(frames[0].line, Some(3));
+    // m has been defined by the line that was stepped over
+    assert_eq!(frames[0].locals, &[ThetaValue::Int(3), ThetaValue::Int(2)]);
+
+    // the recursive call hits the breakpoint again
+    assert_eq!(machine.resume()?, ExecutionStatus::Breakpoint);
+    let frames = machine.frames();
+    assert_eq!(frames.len(), 3);
+    assert_eq!(frames[0].locals[0], ThetaValue::Int(2));
+    assert_eq!(frames[1].line, Some(6));
+
+    machine.clear_breakpoint(locations[0]);
+    assert_eq!(machine.step_out()?, ExecutionStatus::Stepped);
+    let frames = machine.frames();
+    assert_eq!(frames.len(), 2);
+    assert_eq!(frames[0].line, Some(6));
+    assert_eq!(frames[0].locals[0], ThetaValue::Int(3));
+
+    let before = machine.current_location().expect("should be in fact");
+    assert_eq!(machine.step_instruction()?, ExecutionStatus::Stepped);
+    assert_eq!(machine.current_location().expect("should be in fact").offset, before.offset + 1);
+
+    assert_eq!(machine.resume()?, ExecutionStatus::Finished);
+    assert_eq!(machine.stack().values(), &[ThetaValue::Int(6)]);
+
+    Ok(())
+}
+
+#[test]
+pub fn breakpoint_statement_pauses() -> Result<(), Box<dyn std::error::Error>> {
+    use theta_types::bytecode::ThetaString;
+    use theta_vm::vm::{ThetaCallFrame, ExecutionStatus};
+
+    let code = 
+    "fun pause() {
+        print(\"before\");
+        breakpoint;
+        print(\"after\");
+    }";
+
+    let stdout = common::TestOutput::new();
+
+    let (mut machine, loaded_bs, compiled_chunk) = crate::common::build_test_vm(code, "pause", identity, Box::new(stdout.clone()))?;
+    let pause = ThetaString::new(String::from("pause"));
+    let start = machine.set_breakpoint(&pause, 0).expect("pause should be loaded");
+    assert!(machine.set_breakpoint(&pause, 1000).is_none());
+
+    machine.push_frame(ThetaCallFrame { rip: 0, base: 0, function: None, bitstream: loaded_bs, chunk: compiled_chunk });
+
+    assert_eq!(machine.execute_code()?, ExecutionStatus::Breakpoint);
+    assert_eq!(machine.current_location(), Some(start));
+    assert!(stdout.inner.borrow().is_empty());
+
+    // the breakpoint statement has executed, so execution pauses on the statement after it
+    assert_eq!(machine.resume()?, ExecutionStatus::Breakpoint);
+    assert_eq!(machine.frames()[0].line, Some(4));
+    let output = String::from_utf8(stdout.inner.borrow().clone())?;
+    assert!(output.contains("before") && !output.contains("after"));
+
+    assert_eq!(machine.resume()?, ExecutionStatus::Finished);
+    let output = String::from_utf8(stdout.inner.borrow().clone())?;
+    assert!(output.contains("after"));
+
+    Ok(())
+}
+
+#[test]
+pub fn profiler_counts_functions() -> Result<(), Box<dyn std::error::Error>> {
+    use std::{cell::RefCell, rc::Rc};
+    use theta_vm::vm::{ThetaCallFrame, Profiler, TOP_LEVEL_NAME};
+
+    let code = 
+    "fun fib(n: Int) -> Int {
+        if (n <= 1) {
+            n
+        } else {
+            fib(n-1) + fib(n-2)
+        }
+    }";
+
+    let stdout = common::TestOutput::new();
+
+    let (mut machine, loaded_bs, compiled_chunk) = crate::common::build_test_vm(code, "fib", identity, Box::new(stdout.clone()))?;
+    let profiler = Rc::new(RefCell::new(Profiler::new().with_sample_interval(1)));
+    let observer = machine.add_observer(Box::new(profiler.clone()));
+
+    machine.push_value(ThetaValue::Int(10));
+    machine.push_frame(ThetaCallFrame { rip: 0, base: 0, function: None, bitstream: loaded_bs, chunk: compiled_chunk });
+    machine.execute_code()?;
+
+    assert!(machine.remove_observer(observer).is_some());
+    assert!(machine.remove_observer(observer).is_none());
+    let profile = profiler.take().finish();
+
+    // fib(10) makes 177 calls in total
+    let fib = profile.function("fib").expect("fib was called");
+    assert_eq!(fib.calls, 177);
+    assert!(fib.inclusive >= fib.exclusive);
+    assert_eq!(profile.instructions(), machine.executed());
+    assert_eq!(profile.function(TOP_LEVEL_NAME).expect("the entry chunk ran").calls, 0);
+
+    // sampling every instruction puts every instruction in a stack
+    assert_eq!(profile.stacks.iter().map(|(_, samples)| samples).sum::<u64>(), machine.executed());
+    let deepest = profile.stacks.iter().map(|(stack, _)| stack.len()).max().expect("stacks were sampled");
+    // the top level frame, then fib(10) down to fib(1)
+    assert_eq!(deepest, 11);
+
+    let mut folded = Vec::new();
+    profile.write_folded(&mut folded)?;
+    let folded = String::from_utf8(folded)?;
+    assert!(folded.lines().any(|line| line.starts_with("<top level>;fib;fib ")));
+
+    Ok(())
+}
+
+const COUNT: &str =
+"fun count(n: Int) -> Int {
+    let i: Int = 0;
+    while (i < n) {
+        print(\"at\");
+        print(i);
+        i = i + 1;
+    };
+    i
+}";
+
+#[test]
+pub fn snapshot_resumes_in_new_vm() -> Result<(), Box<dyn std::error::Error>> {
+    use theta_vm::vm::{ThetaCallFrame, ExecutionStatus, VM};
+
+    let uninterrupted = common::TestOutput::new();
+    let (mut machine, loaded_bs, compiled_chunk) = crate::common::build_test_vm(COUNT, "count", identity, Box::new(uninterrupted.clone()))?;
+    machine.push_value(ThetaValue::Int(20));
+    machine.push_frame(ThetaCallFrame { rip: 0, base: 0, function: None, bitstream: loaded_bs, chunk: compiled_chunk });
+    assert_eq!(machine.execute_code()?, ExecutionStatus::Finished);
+
+    let before = common::TestOutput::new();
+    let (mut machine, loaded_bs, compiled_chunk) = crate::common::build_test_vm(COUNT, "count", identity, Box::new(before.clone()))?;
+    machine.set_fuel(Some(60));
+    machine.push_value(ThetaValue::Int(20));
+    machine.push_frame(ThetaCallFrame { rip: 0, base: 0, function: None, bitstream: loaded_bs, chunk: compiled_chunk });
+    assert_eq!(machine.execute_code()?, ExecutionStatus::OutOfFuel);
+
+    let snapshot = machine.snapshot()?;
+    drop(machine);
+
+    let after = common::TestOutput::new();
+    let mut restored = VM::restore(&snapshot, Box::new(after.clone()))?;
+    assert_eq!(restored.snapshot()?, snapshot);
+    assert_eq!(restored.fuel(), Some(0));
+    assert_eq!(restored.stack().depth(), 2);
+
+    restored.set_fuel(None);
+    assert_eq!(restored.resume()?, ExecutionStatus::Finished);
+    assert_eq!(restored.stack().peek(), Some(&ThetaValue::Int(20)));
+
+    let mut output = before.inner.borrow().clone();
+    output.extend_from_slice(&after.inner.borrow());
+    assert_eq!(output, *uninterrupted.inner.borrow());
+
+    Ok(())
+}
+
+#[test]
+pub fn rollback_restores_paused_state() -> Result<(), Box<dyn std::error::Error>> {
+    use theta_vm::vm::{ThetaCallFrame, ExecutionStatus};
+
+    let stdout = common::TestOutput::new();
+    let (mut machine, loaded_bs, compiled_chunk) = crate::common::build_test_vm(COUNT, "count", identity, Box::new(stdout.clone()))?;
+    machine.set_fuel(Some(20));
+    machine.push_value(ThetaValue::Int(3));
+    machine.push_frame(ThetaCallFrame { rip: 0, base: 0, function: None, bitstream: loaded_bs, chunk: compiled_chunk });
+    assert_eq!(machine.execute_code()?, ExecutionStatus::OutOfFuel);
+    let snapshot = machine.snapshot()?;
+
+    machine.set_fuel(None);
+    assert_eq!(machine.resume()?, ExecutionStatus::Finished);
+    assert_eq!(machine.stack().depth(), 1);
+    let printed = stdout.inner.borrow().len();
+
+    // the paused call is back, and still writes to the same output
+    machine.rollback(&snapshot)?;
+    assert_eq!(machine.fuel(), Some(0));
+    assert_eq!(machine.stack().depth(), 2);
+    machine.set_fuel(None);
+    assert_eq!(machine.resume()?, ExecutionStatus::Finished);
+    assert_eq!(machine.stack().peek(), Some(&ThetaValue::Int(3)));
+    assert!(stdout.inner.borrow().len() > printed);
+
+    Ok(())
+}
+
+#[test]
+pub fn restore_rejects_bad_snapshots() -> Result<(), Box<dyn std::error::Error>> {
+    use std::rc::Rc;
+    use theta_types::bytecode::{OpCode, ThetaString};
+    use theta_vm::vm::{ThetaCallFrame, SnapshotError, VM, Instruction};
+
+    let (mut machine, loaded_bs, compiled_chunk) = crate::common::build_test_vm(COUNT, "count", identity, Box::new(std::io::sink()))?;
+    machine.push_value(ThetaValue::Int(3));
+    machine.push_frame(ThetaCallFrame { rip: 0, base: 0, function: None, bitstream: loaded_bs, chunk: compiled_chunk });
+    let snapshot = machine.snapshot()?;
+
+    assert!(matches!(VM::restore(b"not a snapshot", Box::new(std::io::sink())), Err(SnapshotError::InvalidHeader(_))));
+    assert!(matches!(VM::restore(&snapshot[..snapshot.len() - 1], Box::new(std::io::sink())), Err(SnapshotError::Truncated(_))));
+
+    let mut newer = snapshot.clone();
+    newer[8] += 1;
+    assert!(matches!(VM::restore(&newer, Box::new(std::io::sink())), Err(SnapshotError::UnsupportedVersion(_))));
+
+    let mut longer = snapshot.clone();
+    longer.push(0);
+    assert!(matches!(VM::restore(&longer, Box::new(std::io::sink())), Err(SnapshotError::TrailingData(at)) if at == snapshot.len()));
+
+    // a constant past the end of the frame's constant pool
+    let (mut machine, loaded_bs, _) = crate::common::build_test_vm(COUNT, "count", identity, Box::new(std::io::sink()))?;
+    let chunk: Rc<[Instruction]> = Rc::from(vec![Instruction::Op(OpCode::Constant { offset: loaded_bs.constants.len() })]);
+    machine.push_frame(ThetaCallFrame { rip: 0, base: 0, function: None, bitstream: loaded_bs, chunk });
+    let snapshot = machine.snapshot()?;
+    assert!(matches!(VM::restore(&snapshot, Box::new(std::io::sink())), Err(SnapshotError::InvalidReference { kind: "constant", .. })));
+
+    // a frame for count without room for its argument and locals
+    let (mut machine, loaded_bs, _) = crate::common::build_test_vm(COUNT, "count", identity, Box::new(std::io::sink()))?;
+    let count = machine.function(&ThetaString::new(String::from("count"))).expect("count should be loaded");
+    let (function, chunk) = (count.index, count.code.clone());
+    machine.push_frame(ThetaCallFrame { rip: 0, base: 0, function: Some(function), bitstream: loaded_bs, chunk });
+    let snapshot = machine.snapshot()?;
+    assert!(matches!(VM::restore(&snapshot, Box::new(std::io::sink())), Err(SnapshotError::InvalidReference { kind: "stack value", .. })));
+
+    Ok(())
+}
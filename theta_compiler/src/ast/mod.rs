@@ -1,6 +1,5 @@
 mod tree;
-pub use self::tree::{AbstractTree, Expression, Statement, Function, FunctionArg, Item};
-pub(crate) use self::tree::InnerAbstractTree;
+pub use self::tree::{AbstractTree, InnerAbstractTree, Expression, Statement, Function, FunctionArg, Item};
 
 pub mod transformers;
-pub mod symbol;
\ No newline at end of file
+pub mod symbol;
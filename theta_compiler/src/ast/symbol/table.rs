@@ -47,6 +47,11 @@ impl SymbolTable {
         sd
     }
 
+    /// Every symbol defined directly in this table, without those of enclosing tables.
+    pub fn symbols(&self) -> impl Iterator<Item = (&Symbol, &SymbolData)> {
+        self.entries.iter().map(|(SymbolKey(_, symbol), data)| (symbol, data))
+    }
+
     pub fn enclosing(&self) -> Option<ExtSymbolTable> {
         self.enclosing.clone()
     }
@@ -5,8 +5,8 @@ use std::fmt::Display;
 use crate::ast::symbol::SymbolData;
 use crate::ast::{Expression, Statement, AbstractTree, InnerAbstractTree, Item, Function};
 use theta_types::build_chunk;
-use theta_types::bytecode::{Chunk, OpCode, ThetaConstant, Symbol, ThetaFunction, ThetaFuncArg, ThetaString, TokenType};
-use theta_types::types::TypeInformation;
+use theta_types::bytecode::{Chunk, ChunkBuilder, ChunkBuildError, OpCode, ThetaConstant, Symbol, ThetaFunction, ThetaFuncArg, ThetaString, TokenType};
+use theta_types::types::{TypeInformation, LocationData};
 
 use super::typeck::TypeCkOutput;
 use super::{ASTTerminator, ASTTransformer, TransformError};
@@ -34,7 +34,7 @@ impl ASTTransformer<TypeCkOutput> for ToByteCode {
         // should only occur on stack boundaries
         // in interpreter mode, this can happen on expression / statement bounds.
 
-        // note: this doesn't include function bounds because the CALL instruction will allocate space on the stack for params
+        // note: this is only used for top level trees. functions have their locals allocated by the call instead.
         let local_size = tree.information().pi.frame_data.borrow().total_locals();
         let mut block_chunk = if local_size > 0 { 
             let mut block_chunk = Chunk::new();
@@ -44,10 +44,7 @@ impl ASTTransformer<TypeCkOutput> for ToByteCode {
             Chunk::new()
         };
 
-        block_chunk = block_chunk.merge_chunk(match tree.inner() {
-            InnerAbstractTree::Expression(exp) => self.visit_expression(&exp.0)?,
-            InnerAbstractTree::Statement(stmt) => self.visit_statement(&stmt.0)?,
-        });
+        block_chunk = block_chunk.merge_chunk(self.visit_tree(tree)?);
 
         let mut pop_block = Chunk::new();
         for _i in 0..local_size {
@@ -59,34 +56,148 @@ impl ASTTransformer<TypeCkOutput> for ToByteCode {
 
     fn transform_item(&self, item: &Item<TypeCkOutput>) -> Result<Self::ItemOut, TransformError> {
         match item {
-            Item::Function(func) => {
-                // Insert return opcode here
-                let mut ck = self.transform_tree(&func.chunk)?;
-
-                // Need to check return ty of func
-                ck = match func.return_ty {
-                    TypeInformation::None => ck.merge_chunk(build_chunk!(OpCode::ReturnVoid)),
-                    _ => ck.merge_chunk(build_chunk!(OpCode::Return)),
-                };
+            Item::Function(func) => self.visit_function(func),
+        }
+    }
+}
+
+impl ToByteCode {
+    fn visit_tree(&self, tree: &AbstractTree<TypeCkOutput>) -> Result<Chunk, TransformError> {
+        match tree.inner() {
+            InnerAbstractTree::Expression(exp) => self.visit_expression(&exp.0),
+            InnerAbstractTree::Statement(stmt) => self.visit_statement(&stmt.0),
+        }
+    }
+
+    /// Visits an expression whose value is returned from the enclosing function as-is.
+    /// Calls in tail position are emitted as tail calls so that they reuse the caller's frame.
+    fn visit_tail_expression(&self, expr: &Expression<TypeCkOutput>, return_ty: &TypeInformation) -> Result<Chunk, TransformError> {
+        Ok(match expr {
+            // the callee's return value only becomes ours if the types line up. e.g. a call to a
+            // function returning a value at the end of a function returning nothing is not a tail call.
+            Expression::Call { callee, args, information } if information.ty == *return_ty => {
+                let mut call_chunk = Chunk::new();
 
-                let func_name = ThetaString::from(func.name.clone());
+                for arg in args {
+                    call_chunk = call_chunk.merge_chunk(self.visit_expression(arg)?);
+                }
+
+                call_chunk = call_chunk.merge_chunk(self.visit_expression(callee)?);
+                call_chunk.merge_chunk(build_chunk!(OpCode::TailCallDirect { name_offset: 0 }))
+            },
+            Expression::If { check_expression, body, else_body, information: _ } => {
+                let check_block = self.visit_expression(check_expression)?;
+                let body_block = self.visit_tail_expression(body, return_ty)?;
+                let else_block = if let Some(else_clause) = else_body {
+                    Some(self.visit_tail_expression(else_clause, return_ty)?)
+                } else {
+                    None
+                };
 
-                let mut theta_func_args = Vec::new();
-                for arg in &func.args {
-                    theta_func_args.push(ThetaFuncArg {
-                        ty: arg.ty.clone(),
-                    })
+                self.if_chunk(check_block, body_block, else_block)?
+            },
+            Expression::BlockExpression { statements, information: _, final_expression: Some(expr) } => {
+                let mut block_chunk = Chunk::new();
+                for stmt in statements {
+                    block_chunk = block_chunk.merge_chunk(self.visit_statement(stmt)?);
                 }
 
+                block_chunk.merge_chunk(self.mark_line(self.visit_tail_expression(expr, return_ty)?, &expr.information().pi.location_data))
+            },
+            _ => self.visit_expression(expr)?,
+        })
+    }
+
+    fn statement_chunk(&self, stmt: &Statement<TypeCkOutput>) -> Result<Chunk, TransformError> {
+        match stmt {
+            Statement::ExpressionStatement { expression, information: _ } => {
+                let expr_chunk = self.visit_expression(expression)?;
 
-                Ok(ThetaFunction {
-                    args: theta_func_args,
-                    chunk: ck,
-                    name: func_name,
-                    return_ty: func.return_ty.clone(),
-                })
+                match expression.information().ty {
+                    TypeInformation::None => Ok(expr_chunk),
+                    _ => {
+                        let pop_chunk = build_chunk!(OpCode::Pop);
+                        Ok(expr_chunk.merge_chunk(pop_chunk))        
+                    }
+                }
+            },
+            Statement::PrintStatement { expression, information: _ } => {
+                let print_chunk = build_chunk!(OpCode::DebugPrint);
+                let expr_chunk = self.visit_expression(expression)?;
+                Ok(expr_chunk.merge_chunk(print_chunk))
             },
+            Statement::BreakpointStatement { information: _ } => Ok(build_chunk!(OpCode::Breakpoint)),
+            Statement::VarStatement { ident, init, information: info } => {
+                // we will emit the initializer and then define the global here. note that `information` may eventually carry scoping information
+                // for now all variables are globals. this should change when lexical scoping is added
+                // pop is handled by the DefineGlobal and DefineLocal opcodes.
+                match info.pi.scope_depth {
+                    0 => {
+                        // emit global when sd == 0
+                        let hv = ThetaConstant::Str(ident.id().to_owned());
+                        let init_chunk = self.visit_expression(init)?;
+                        let glob_chunk = build_chunk!(OpCode::DefineGlobal { offset: 0 }; hv);
+                        Ok(init_chunk.merge_chunk(glob_chunk))
+                    },
+                    sd => {
+                        // emit local when sd > 0
+                        let init_chunk = self.visit_expression(init)?;
+                        let local = info.pi.current_symbol_table.borrow().get_symbol_data(ident, sd);
+                        match local {
+                            Some(SymbolData::Type { ty: _ }) => return Err(TransformError::from(ToByteCodeError::InvalidLocal(ident.id().clone()))),
+                            Some(SymbolData::GlobalVariable { ty: _ }) => return Err(TransformError::from(ToByteCodeError::InvalidLocal(ident.id().clone()))),
+                            Some(SymbolData::LocalVariable { ty: _, scope_level: _, slot }) => {
+                                let glob_chunk = build_chunk!(OpCode::DefineLocal { offset: slot });
+                                Ok(init_chunk.merge_chunk(glob_chunk))
+                            },
+                            Some(SymbolData::Function { return_ty: _, args: _, fn_ty: _ }) => {
+                                // build closure and embed it
+                                todo!()
+                            },
+                            None => return Err(TransformError::from(ToByteCodeError::NoIdentFound(ident.id().clone())))
+                        }
+                    },
+                }
+            },
+            Statement::Partial { expression, information: _ } => {
+                // We must be very careful here. Partials can screw up the stack
+                self.visit_expression(expression)
+            },
+        }
+    }
+
+    /// Records the line `location` starts on against the first instruction of `chunk`.
+    fn mark_line(&self, mut chunk: Chunk, location: &LocationData) -> Chunk {
+        if !chunk.instructions().is_empty() {
+            // line mappings hold the offset every line after the first starts at
+            let line = self.line_mappings.partition_point(|start| *start <= location.begin()) + 1;
+            chunk.mark_line(0, line);
         }
+        chunk
+    }
+
+    fn if_chunk(&self, check_block: Chunk, body_block: Chunk, else_block: Option<Chunk>) -> Result<Chunk, TransformError> {
+        // the check block leaves a boolean on top of the stack which the jump does not consume,
+        // so both branches begin by popping it.
+        // jump sizes (local vs. far) are picked by the builder once the branch sizes are known.
+        let mut builder = ChunkBuilder::new();
+        let else_label = builder.new_label();
+        let end_label = builder.new_label();
+
+        builder.append_chunk(check_block)?;
+        builder.jump_if_false(else_label);
+        builder.emit(OpCode::Pop)?;
+        builder.append_chunk(body_block)?;
+        builder.jump(end_label);
+
+        builder.place_label(else_label);
+        builder.emit(OpCode::Pop)?;
+        if let Some(block) = else_block {
+            builder.append_chunk(block)?;
+        }
+        builder.place_label(end_label);
+
+        Ok(builder.build()?)
     }
 }
 
@@ -218,69 +329,7 @@ impl ASTTerminator<TypeCkOutput> for ToByteCode {
                     None
                 };
 
-                // we now need to compute how far the if needs to jump.
-                // there are multiple types of jump and we use the one that is most relevant.
-                // if the jump size is less than 127, we can use a local jump.
-                // if the jump size is greater than 127, we will instead switch to a full-sized jump.
-                // note that we can only jump at most by 2^(sizeof(isize) - 1) because we need the sign bit
-                // for when a jump is negative.
-
-                // first we need to know how big the main body is.
-                // TODO: if this overflows code you can execute code at a different location
-                let jump_size = body_block.instruction_size() as isize;
-                let jump_size: isize = jump_size + match &else_block {
-                    Some(_) => 2,
-                    None => 2
-                };
-
-                // depending on which jump is emitted we need to add the size of the jump to the offset!!!
-                // we also need to consider if we're jumping from the START of the jump instruction (we are) vs. the end of the jump instruction
-
-                let jump_chunk = if jump_size + 2 > 127 {
-                    // emit non-"local" jump
-                    // TODO: clean this up
-                    build_chunk!(OpCode::JumpFarIfFalse { offset: jump_size + 2 + (std::mem::size_of::<isize>() as isize) }, OpCode::Pop)
-                } else {
-                    build_chunk!(OpCode::JumpLocalIfFalse { offset: (jump_size + 3) as i8 }, OpCode::Pop)
-                };
-
-                // first we emit the check block, which should leave a boolean on top of the stack
-                // then we emit the jump chunk
-                let combined_block = check_block.merge_chunk(jump_chunk); 
-
-                // then we emit the body chunk
-                let combined_block = combined_block.merge_chunk(body_block);
-
-                // then we emit a jump chunk that skips to the end of the else block if it exists
-                // then we emit the else block if it exists
-
-                // TODO: we are missing a POP somewhere in here.
-                // MAYBE.
-                // occurs when the if statement falls through and there's no else block.
-
-                if let Some(block) = else_block {
-                    let jump_size: isize = block.instruction_size() as isize;
-
-                    // depending on which jump is emitted we need to add the size of the jump to the offset!!!
-                    // we also need to consider if we're jumping from the START of the jump instruction (we are) vs. the end of the jump instruction
-
-                    let jump_chunk = if jump_size + 2 > 127 {
-                        // emit non-"local" jump
-                        build_chunk!(OpCode::JumpFar { offset: jump_size + 2 + (std::mem::size_of::<isize>() as isize) }, OpCode::Pop)
-                    } else {
-                        // TODO: Why does this have to be 3? what is emitting the 2 after the POP opcode?
-                        // 3 because i8 + 2. why 2?
-                        // this offset needs to take into account the size of the else block...
-                        build_chunk!(OpCode::JumpLocal { offset: (jump_size + 3) as i8 }, OpCode::Pop)
-                    };
-
-                    let combined_block = combined_block.merge_chunk(jump_chunk);
-                    combined_block.merge_chunk(block)
-                } else {
-                    let jump_chunk = build_chunk!(OpCode::JumpLocal { offset: 3 }, OpCode::Pop);
-                    combined_block.merge_chunk(jump_chunk)
-                }
-
+                self.if_chunk(check_block, body_block, else_block)?
             },
             Expression::BlockExpression { statements, information: _, final_expression } => {
                 // we are at scope_depth +1 here.
@@ -291,7 +340,7 @@ impl ASTTerminator<TypeCkOutput> for ToByteCode {
                 }
 
                 block_chunk = block_chunk.merge_chunk(match final_expression {
-                    Some(expr) => self.visit_expression(expr)?,
+                    Some(expr) => self.mark_line(self.visit_expression(expr)?, &expr.information().pi.location_data),
                     None => Chunk::new(),
                 });
 
@@ -299,33 +348,27 @@ impl ASTTerminator<TypeCkOutput> for ToByteCode {
             },
             Expression::LoopExpression { predicate, body, information: _ } => {
                 let body_chunk = self.visit_expression(body)?;
-                
-                // TODO: this is fucked. something is super wrong here... loop offsets are totally wrong in some cases.
-                // TODO: predicate not generating pop instruction after jumping depending on predicate
-                let enc_pred = if let Some(x) = predicate {
-                    self.visit_expression(x)?
-                } else {
-                    Chunk::new()
-                };
 
-                // TODO: why is this 5?
-                // size of JMPFAR = 1 (instruction) + 8 (sizeof isize)
-                let jump_to_end_offset: usize = body_chunk.instruction_size() + enc_pred.instruction_size() + (1 + std::mem::size_of::<isize>());
-                let jump_to_end_chunk = match predicate {
-                    Some(_) => build_conditional_jump(jump_to_end_offset, false),
-                    None => Chunk::new(),
-                };
+                let mut builder = ChunkBuilder::new();
+                let loop_head = builder.label_here();
+                let loop_exit = builder.new_label();
 
-                let loop_head = enc_pred.merge_chunk(jump_to_end_chunk).merge_chunk(body_chunk);
+                // like if expressions, the predicate's boolean is left on the stack by the jump and popped on both paths.
+                if let Some(x) = predicate {
+                    builder.append_chunk(self.visit_expression(x)?)?;
+                    builder.jump_if_false(loop_exit);
+                    builder.emit(OpCode::Pop)?;
+                }
 
-                // removing jump optimization to ensure size is known
-                // size = JMPFAR = 1 (instruction) + 8 (sizeof isize)
-                let jump_to_beginning_chunk: Chunk = match predicate {
-                    Some(_) => unconditional_far_jump(loop_head.instruction_size(), true),
-                    None => unconditional_far_jump(loop_head.instruction_size(), true),
-                };
+                builder.append_chunk(body_chunk)?;
+                builder.jump(loop_head);
 
-                loop_head.merge_chunk(jump_to_beginning_chunk)
+                builder.place_label(loop_exit);
+                if predicate.is_some() {
+                    builder.emit(OpCode::Pop)?;
+                }
+
+                builder.build()?
             },
             Expression::Call { callee: function, args, information: _ } => {
                 // first we evaluate all arguments and ensure they're on the stack
@@ -350,10 +393,45 @@ impl ASTTerminator<TypeCkOutput> for ToByteCode {
 
                 call_chunk
             },
-            Expression::Return { ret, information: _ } => {
-                let chunk = match ret {
-                    Some(expr) => self.visit_expression(expr)?,
-                    None => Chunk::new(),
+            Expression::Format { pieces, args, information: _ } => {
+                // the pieces and the arguments, converted to strings, are concatenated in order
+                let mut chunk = build_chunk!(OpCode::Constant { offset: 0 }; ThetaConstant::Str(pieces[0].clone()));
+                for (arg, piece) in args.iter().zip(&pieces[1..]) {
+                    chunk = chunk.merge_chunk(self.visit_expression(arg)?);
+                    if arg.information().ty != TypeInformation::String {
+                        chunk.write_to_chunk(OpCode::Stringify);
+                    }
+                    chunk.write_to_chunk(OpCode::Add);
+
+                    if !piece.is_empty() {
+                        chunk = chunk.merge_chunk(build_chunk!(OpCode::Constant { offset: 0 }; ThetaConstant::Str(piece.clone())));
+                        chunk.write_to_chunk(OpCode::Add);
+                    }
+                }
+
+                chunk
+            },
+            Expression::Cast { value, ty, information: _ } => {
+                let value_chunk = self.visit_expression(value)?;
+                let op_chunk = match (&value.information().ty, ty) {
+                    (from, to) if from == to => Chunk::new(),
+                    (TypeInformation::Int, TypeInformation::Float) => build_chunk!(OpCode::IntToFloat),
+                    (TypeInformation::Float, TypeInformation::Int) => build_chunk!(OpCode::FloatToInt),
+                    (TypeInformation::Boolean, TypeInformation::Int) => build_chunk!(OpCode::BoolToInt),
+                    (TypeInformation::String, TypeInformation::Int) => build_chunk!(OpCode::ParseInt),
+                    (TypeInformation::String, TypeInformation::Float) => build_chunk!(OpCode::ParseFloat),
+                    (TypeInformation::String, TypeInformation::Boolean) => build_chunk!(OpCode::ParseBool),
+                    (_, TypeInformation::String) => build_chunk!(OpCode::Stringify),
+                    (from, to) => return Err(TransformError::from(ToByteCodeError::InvalidCast(format!("from {} to {}", from, to)))),
+                };
+                value_chunk.merge_chunk(op_chunk)
+            },
+            Expression::Return { ret, information } => {
+                let return_ty = information.pi.frame_data.borrow().return_ty.clone();
+                let chunk = match (ret, return_ty) {
+                    (Some(expr), Some(return_ty)) => self.visit_tail_expression(expr, &return_ty)?,
+                    (Some(expr), None) => self.visit_expression(expr)?,
+                    (None, _) => Chunk::new(),
                 };
 
                 let return_ck = match ret {
@@ -370,74 +448,31 @@ impl ASTTerminator<TypeCkOutput> for ToByteCode {
         &self,
         stmt: &Statement<TypeCkOutput>,
     ) -> Result<Self::ChunkOut, super::TransformError> {
-        match stmt {
-            Statement::ExpressionStatement { expression, information: _ } => {
-                let expr_chunk = self.visit_expression(expression)?;
-
-                match expression.information().ty {
-                    TypeInformation::None => Ok(expr_chunk),
-                    _ => {
-                        let pop_chunk = build_chunk!(OpCode::Pop);
-                        Ok(expr_chunk.merge_chunk(pop_chunk))        
-                    }
-                }
-            },
-            Statement::PrintStatement { expression, information: _ } => {
-                let print_chunk = build_chunk!(OpCode::DebugPrint);
-                let expr_chunk = self.visit_expression(expression)?;
-                Ok(expr_chunk.merge_chunk(print_chunk))
-            },
-            Statement::VarStatement { ident, init, information: info } => {
-                // we will emit the initializer and then define the global here. note that `information` may eventually carry scoping information
-                // for now all variables are globals. this should change when lexical scoping is added
-                // pop is handled by the DefineGlobal and DefineLocal opcodes.
-                match info.pi.scope_depth {
-                    0 => {
-                        // emit global when sd == 0
-                        let hv = ThetaConstant::Str(ident.id().to_owned());
-                        let init_chunk = self.visit_expression(init)?;
-                        let glob_chunk = build_chunk!(OpCode::DefineGlobal { offset: 0 }; hv);
-                        Ok(init_chunk.merge_chunk(glob_chunk))
-                    },
-                    sd => {
-                        // emit local when sd > 0
-                        let init_chunk = self.visit_expression(init)?;
-                        let local = info.pi.current_symbol_table.borrow().get_symbol_data(ident, sd);
-                        match local {
-                            Some(SymbolData::Type { ty: _ }) => return Err(TransformError::from(ToByteCodeError::InvalidLocal(ident.id().clone()))),
-                            Some(SymbolData::GlobalVariable { ty: _ }) => return Err(TransformError::from(ToByteCodeError::InvalidLocal(ident.id().clone()))),
-                            Some(SymbolData::LocalVariable { ty: _, scope_level: _, slot }) => {
-                                let glob_chunk = build_chunk!(OpCode::DefineLocal { offset: slot });
-                                Ok(init_chunk.merge_chunk(glob_chunk))
-                            },
-                            Some(SymbolData::Function { return_ty: _, args: _, fn_ty: _ }) => {
-                                // build closure and embed it
-                                todo!()
-                            },
-                            None => return Err(TransformError::from(ToByteCodeError::NoIdentFound(ident.id().clone())))
-                        }
-                    },
-                }
-            },
-            Statement::Partial { expression, information: _ } => {
-                // We must be very careful here. Partials can screw up the stack
-                self.visit_expression(expression)
-            },
-        }
+        let chunk = self.statement_chunk(stmt)?;
+        Ok(self.mark_line(chunk, &stmt.information().pi.location_data))
     }
 
     fn visit_function(&self, func: &Function<TypeCkOutput>) -> Result<ThetaFunction, TransformError> {
+        // the function's frame is sized when it is called, so no push or pops are needed here
+        let internal_ck = match func.chunk.inner() {
+            InnerAbstractTree::Expression(exp) => self.visit_tail_expression(&exp.0, &func.return_ty)?,
+            InnerAbstractTree::Statement(stmt) => self.visit_statement(&stmt.0)?,
+        };
 
-        let internal_ck = self.transform_tree(&func.chunk)?;
+        // Need to check return ty of func
+        let internal_ck = match func.return_ty {
+            TypeInformation::None => internal_ck.merge_chunk(build_chunk!(OpCode::ReturnVoid)),
+            _ => internal_ck.merge_chunk(build_chunk!(OpCode::Return)),
+        };
 
         let internal_args = func.args.iter().map(|x| ThetaFuncArg { ty: x.ty.clone() }).collect();
 
-
         Ok(ThetaFunction {
             args: internal_args,
             chunk: internal_ck,
             name: ThetaString::from(func.name.clone()),
             return_ty: func.return_ty.clone(),
+            locals: func.chunk.information().pi.frame_data.borrow().total_locals(),
         })
     }
 }
@@ -447,6 +482,8 @@ pub enum ToByteCodeError {
     InvalidToken(String),
     InvalidLocal(String),
     NoIdentFound(String),
+    InvalidCast(String),
+    ChunkBuildError(ChunkBuildError),
 }
 
 impl Display for ToByteCodeError {
@@ -455,64 +492,16 @@ impl Display for ToByteCodeError {
             ToByteCodeError::InvalidToken(s) => write!(f, "Invalid Token: {}", s),
             ToByteCodeError::InvalidLocal(s) => write!(f, "Invalid Local with Identifier: {}", s),
             ToByteCodeError::NoIdentFound(s) => write!(f, "No identifier found with name {}", s),
+            ToByteCodeError::InvalidCast(s) => write!(f, "Invalid cast {}", s),
+            ToByteCodeError::ChunkBuildError(e) => write!(f, "Failed to build chunk: {}", e),
         }
     }
 }
 
 impl Error for ToByteCodeError {}
 
-fn build_conditional_jump(jump_size: usize, negate_offset: bool) -> Chunk {
-    if jump_size > 127 {
-        conditional_far_jump(jump_size, negate_offset)
-    } else {
-        conditional_local_jump(jump_size, negate_offset)
+impl From<ChunkBuildError> for ToByteCodeError {
+    fn from(err: ChunkBuildError) -> Self {
+        ToByteCodeError::ChunkBuildError(err)
     }
 }
-
-fn conditional_local_jump(jump_size: usize, negate_offset: bool) -> Chunk {
-    let offset = i8::try_from(jump_size).expect("failed to convert to i8, offset?");
-    let offset = if negate_offset {
-        -offset
-    } else {
-        offset
-    };
-    build_chunk!(OpCode::JumpLocalIfFalse { offset })
-}
-
-fn conditional_far_jump(jump_size: usize, negate_offset: bool) -> Chunk {
-    let offset = isize::try_from(jump_size).expect("failed to convert to isize, offset too large");
-    let offset = if negate_offset {
-        -offset
-    } else {
-        offset
-    };
-    build_chunk!(OpCode::JumpFarIfFalse { offset })
-}
-
-fn build_unconditional_jump(jump_size: usize, negate_offset: bool) -> Chunk {
-    if jump_size > 127 {
-        unconditional_far_jump(jump_size, negate_offset)
-    } else {
-        unconditional_local_jump(jump_size, negate_offset)
-    }
-}
-
-fn unconditional_far_jump(jump_size: usize, negate_offset: bool) -> Chunk {
-    let offset = isize::try_from(jump_size).expect("failed to convert to isize, offset too large");
-    let offset = if negate_offset {
-        -offset
-    } else {
-        offset
-    };
-    build_chunk!(OpCode::JumpFar { offset })
-}
-
-fn unconditional_local_jump(jump_size: usize, negate_offset: bool) -> Chunk {
-    let offset = i8::try_from(jump_size).expect("failed to convert to i8, offset?");
-    let offset = if negate_offset {
-        -offset
-    } else {
-        offset
-    };
-    build_chunk!(OpCode::JumpLocal { offset })
-}
\ No newline at end of file
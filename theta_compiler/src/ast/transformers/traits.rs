@@ -1,6 +1,6 @@
 use std::{fmt::{self, Debug}, error::Error};
 
-use theta_types::bytecode::ThetaFunction;
+use theta_types::bytecode::{ThetaFunction, ChunkBuildError};
 
 use crate::ast::{AbstractTree, Expression, Statement, Function, tree::Item};
 
@@ -63,4 +63,10 @@ impl From<ToByteCodeError> for TransformError {
     fn from(err: ToByteCodeError) -> Self {
         TransformError::ToByteCodeError(err)
     }
+}
+
+impl From<ChunkBuildError> for TransformError {
+    fn from(err: ChunkBuildError) -> Self {
+        TransformError::ToByteCodeError(ToByteCodeError::from(err))
+    }
 }
\ No newline at end of file
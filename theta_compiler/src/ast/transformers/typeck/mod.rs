@@ -31,6 +31,9 @@ pub enum TypeCkError {
     InvalidFunctionReturn(TypeInformation, TypeInformation),
     InvalidNumberFunctionArgs(usize, usize),
     FunctionArgumentNoMatchDef(TypeInformation, TypeInformation),
+    InvalidNumberFormatArgs(usize, usize),
+    NotPrintable(TypeInformation),
+    InvalidCast(TypeInformation, TypeInformation),
 }
 
 impl Error for TypeCkError {
@@ -59,6 +62,9 @@ impl Display for TypeCkError {
             TypeCkError::InvalidFunctionReturn(expected, actual) => write!(f, "Type Mismatch! Expected a function returning {}, got: {}", expected, actual),
             TypeCkError::InvalidNumberFunctionArgs(expected, actual) => write!(f, "Invalid number of arguments for function call. Expected: {expected}, Actual: {actual}"),
             TypeCkError::FunctionArgumentNoMatchDef(expected, actual) => write!(f, "Invalid function argument. Expected: {expected}, Actual: {actual}"),
+            TypeCkError::InvalidNumberFormatArgs(expected, actual) => write!(f, "Invalid number of arguments to format. Expected: {expected}, Actual: {actual}"),
+            TypeCkError::NotPrintable(ty) => write!(f, "Type Mismatch! Expected a printable type, got: {ty}"),
+            TypeCkError::InvalidCast(from, to) => write!(f, "Invalid cast from {from} to {to}"),
         }
     }
 }
@@ -346,6 +352,46 @@ impl ASTVisitor<ParseInfo> for TypeCk {
 
                 Ok(Expression::Call { callee: Box::new(callee_expr), args: actual_args, information: TypeCkOutput { ty: *return_ty, pi: information.clone() }})
             },
+            Expression::Format { pieces, args, information } => {
+                // every placeholder sits between two pieces
+                if pieces.len() != args.len() + 1 {
+                    return Err(TransformError::TypeCkError(TypeCkError::InvalidNumberFormatArgs(pieces.len() - 1, args.len())))
+                }
+
+                let mut actual_args = Vec::new();
+                for arg in args {
+                    let annotated_arg = self.visit_expression(arg)?;
+
+                    if !annotated_arg.information().ty.is_printable() {
+                        return Err(TransformError::TypeCkError(TypeCkError::NotPrintable(annotated_arg.information().ty.clone())))
+                    }
+
+                    actual_args.push(annotated_arg);
+                }
+
+                Ok(Expression::Format { pieces: pieces.clone(), args: actual_args, information: TypeCkOutput { ty: TypeInformation::String, pi: information.clone() } })
+            },
+            Expression::Cast { value, ty, information } => {
+                let value_checked = self.visit_expression(value)?;
+                let value_ty = value_checked.information().ty.clone();
+
+                let valid = match (&value_ty, ty) {
+                    (from, to) if from == to => from.is_printable(),
+                    (TypeInformation::Int, TypeInformation::Float) => true,
+                    (TypeInformation::Float, TypeInformation::Int) => true,
+                    (TypeInformation::Boolean, TypeInformation::Int) => true,
+                    (from, TypeInformation::String) => from.is_printable(),
+                    // parsed at runtime, which can fail
+                    (TypeInformation::String, TypeInformation::Int | TypeInformation::Float | TypeInformation::Boolean) => true,
+                    _ => false,
+                };
+
+                if !valid {
+                    return Err(TransformError::TypeCkError(TypeCkError::InvalidCast(value_ty, ty.clone())))
+                }
+
+                Ok(Expression::Cast { value: Box::new(value_checked), ty: ty.clone(), information: TypeCkOutput { ty: ty.clone(), pi: information.clone() } })
+            },
             Expression::Return { ret, information } => {
                 // run typeck on expression
 
@@ -379,6 +425,9 @@ impl ASTVisitor<ParseInfo> for TypeCk {
                 let aug_expr = self.visit_expression(expression)?;
                 Ok(Statement::PrintStatement { expression: aug_expr, information: TypeCkOutput { ty: TypeInformation::None, pi: info.clone() } })
             },
+            Statement::BreakpointStatement { information: info } => {
+                Ok(Statement::BreakpointStatement { information: TypeCkOutput { ty: TypeInformation::None, pi: info.clone() } })
+            },
             Statement::VarStatement { ident, init, information: info } => {
                 // need symbol table to be built for this.
                 // we just check that the init expr creates the same type as requested.
@@ -1,6 +1,6 @@
 use std::fmt::Debug;
 
-use theta_types::bytecode::{Token, Symbol};
+use theta_types::{bytecode::{Token, Symbol}, types::TypeInformation};
 
 use super::Statement;
 
@@ -57,7 +57,19 @@ pub enum Expression<T> where T: Debug + PartialEq {
     Return {
         ret: Option<Box<Expression<T>>>,
         information: T,
-    }
+    },
+    /// A string of the pieces with each argument, as it prints, between consecutive pieces.
+    Format {
+        pieces: Vec<String>,
+        args: Vec<Expression<T>>,
+        information: T,
+    },
+    /// An explicit conversion of a value to another type.
+    Cast {
+        value: Box<Expression<T>>,
+        ty: TypeInformation,
+        information: T,
+    },
 }
 
 impl<T: Debug + PartialEq> Expression<T> {
@@ -73,6 +85,8 @@ impl<T: Debug + PartialEq> Expression<T> {
             Expression::LoopExpression { predicate: _, body: _, information } => information,
             Expression::Call { callee: _, args: _, information } => information,
             Expression::Return { ret: _, information } => information,
+            Expression::Format { pieces: _, args: _, information } => information,
+            Expression::Cast { value: _, ty: _, information } => information,
         }
     }
 
@@ -88,6 +102,8 @@ impl<T: Debug + PartialEq> Expression<T> {
             Expression::LoopExpression { predicate, body, information: _ } => Expression::LoopExpression { predicate: predicate.map(|x| Box::new(x.strip_information())), body: Box::new(body.strip_information()), information: () },
             Expression::Call { callee: function, args, information: _ } => Expression::Call { callee: Box::new(function.strip_information()), args: args.into_iter().map(|x| x.strip_information()).collect(), information: () },
             Expression::Return { ret, information: _ } => Expression::Return { ret: ret.map(|x| Box::new(x.strip_information())), information: () },
+            Expression::Format { pieces, args, information: _ } => Expression::Format { pieces, args: args.into_iter().map(|x| x.strip_information()).collect(), information: () },
+            Expression::Cast { value, ty, information: _ } => Expression::Cast { value: Box::new(value.strip_information()), ty, information: () },
         }
     }
 
@@ -103,6 +119,8 @@ impl<T: Debug + PartialEq> Expression<T> {
             Expression::LoopExpression { predicate, body, information } => Expression::LoopExpression { predicate: predicate.map(|x| Box::new(x.strip_token_information())), body: Box::new(body.strip_token_information()), information },
             Expression::Call { callee: function, args, information } => Expression::Call { callee: function, args: args.into_iter().map(|x| x.strip_token_information()).collect(), information },
             Expression::Return { ret, information } => Expression::Return { ret: ret.map(|x| Box::new(x.strip_token_information())), information },
+            Expression::Format { pieces, args, information } => Expression::Format { pieces, args: args.into_iter().map(|x| x.strip_token_information()).collect(), information },
+            Expression::Cast { value, ty, information } => Expression::Cast { value: Box::new(value.strip_token_information()), ty, information },
         }
     }
 
@@ -118,6 +136,8 @@ impl<T: Debug + PartialEq> Expression<T> {
             Expression::LoopExpression { predicate, body, information } => Expression::LoopExpression { predicate: predicate.map(|x| Box::new(x.map_information(map_fn))), body: Box::new(body.map_information(map_fn)), information: map_fn(information) },
             Expression::Call { callee: function, args, information } => Expression::Call { callee: Box::new(function.map_information(map_fn)), args: args.into_iter().map(|x| x.map_information(map_fn)).collect(), information: map_fn(information) },
             Expression::Return { ret, information } => Expression::Return { ret: ret.map(|x| Box::new(x.map_information(map_fn))), information: map_fn(information) },
+            Expression::Format { pieces, args, information } => Expression::Format { pieces, args: args.into_iter().map(|x| x.map_information(map_fn)).collect(), information: map_fn(information) },
+            Expression::Cast { value, ty, information } => Expression::Cast { value: Box::new(value.map_information(map_fn)), ty, information: map_fn(information) },
         }
     }
 }
\ No newline at end of file
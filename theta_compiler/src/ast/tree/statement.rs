@@ -20,6 +20,10 @@ pub enum Statement<T> where T: Debug + PartialEq {
         init: Expression<T>,
         information: T,
     },
+    /// Pauses execution for an attached debugger.
+    BreakpointStatement {
+        information: T
+    },
     /// Only necessary for block expression
     Partial {
         expression: Expression<T>,
@@ -33,6 +37,7 @@ impl<T: Debug + PartialEq> Statement<T> {
             Statement::ExpressionStatement { expression: _, information } => information,
             Statement::PrintStatement { information, expression: _ } => information,
             Statement::VarStatement { ident: _, init: _, information } => information,
+            Statement::BreakpointStatement { information } => information,
             Statement::Partial { expression: _, information } => information,
         }
     }
@@ -42,6 +47,7 @@ impl<T: Debug + PartialEq> Statement<T> {
             Statement::ExpressionStatement { expression, information: _ } => Statement::ExpressionStatement { expression: expression.strip_information(), information: () },
             Statement::PrintStatement { expression, information: _ } => Statement::PrintStatement { expression: expression.strip_information(), information: () },
             Statement::VarStatement { ident, init, information: _ } => Statement::VarStatement { ident, init: init.strip_information(), information: () },
+            Statement::BreakpointStatement { information: _ } => Statement::BreakpointStatement { information: () },
             Statement::Partial { expression, information: _ } => Statement::Partial { expression: expression.strip_information(), information: () },
         }
     }
@@ -51,6 +57,7 @@ impl<T: Debug + PartialEq> Statement<T> {
             Statement::ExpressionStatement { expression, information } => Statement::ExpressionStatement { expression: expression.strip_token_information(), information },
             Statement::PrintStatement { expression, information } => Statement::PrintStatement { expression: expression.strip_token_information(), information },
             Statement::VarStatement { ident, init, information } => Statement::VarStatement { ident, init: init.strip_token_information(), information },
+            Statement::BreakpointStatement { information } => Statement::BreakpointStatement { information },
             Statement::Partial { expression, information } => Statement::Partial { expression: expression.strip_token_information(), information },
         }
     }
@@ -60,6 +67,7 @@ impl<T: Debug + PartialEq> Statement<T> {
             Statement::ExpressionStatement { expression, information } => Statement::ExpressionStatement { expression: expression.map_information(map_fn), information: map_fn(information) },
             Statement::PrintStatement { expression, information } => Statement::PrintStatement { expression: expression.map_information(map_fn), information: map_fn(information) },
             Statement::VarStatement { ident, init, information } => Statement::VarStatement { ident, init: init.map_information(map_fn), information: map_fn(information) },
+            Statement::BreakpointStatement { information } => Statement::BreakpointStatement { information: map_fn(information) },
             Statement::Partial { expression, information } => Statement::Partial { expression: expression.map_information(map_fn), information: map_fn(information) },
         }
     }
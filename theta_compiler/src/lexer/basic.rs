@@ -1,6 +1,6 @@
 use std::{iter::Peekable, error::Error, fmt::Display, collections::HashMap};
 
-use theta_types::bytecode::{Token, TokenType, IDENTIFIERS};
+use theta_types::bytecode::{Token, TokenType, StringFragment, IDENTIFIERS};
 
 use super::*;
 
@@ -56,6 +56,7 @@ impl<'a> BasicLexer<'a> {
     fn string(&mut self) -> Result<Token, LexerError> {
 
         let mut buffer = String::new();
+        let mut fragments = Vec::new();
         let location = (self.line_num, self.current);
 
         while self.peek().map(|opt| opt != '"').unwrap_or(false) && !self.is_at_end() {
@@ -64,7 +65,12 @@ impl<'a> BasicLexer<'a> {
             }
 
             if let Some(c) = self.advance() {
-                buffer.push(c)
+                if c == '$' && self.match_char('{') {
+                    fragments.push(StringFragment::Text(std::mem::take(&mut buffer)));
+                    fragments.push(StringFragment::Tokens(self.interpolation(location)?));
+                } else {
+                    buffer.push(c)
+                }
             }
         }
 
@@ -74,7 +80,49 @@ impl<'a> BasicLexer<'a> {
 
         self.advance();
 
-        Ok(self.generate_token(TokenType::Str(buffer)))
+        if fragments.is_empty() {
+            Ok(self.generate_token(TokenType::Str(buffer)))
+        } else {
+            fragments.push(StringFragment::Text(buffer));
+            Ok(self.generate_token(TokenType::Interpolation(fragments)))
+        }
+    }
+
+    /// Lexes an expression embedded in a string, after its `${` and up to its closing `}`.
+    /// The tokens are located in the enclosing source.
+    fn interpolation(&mut self, location: (usize, usize)) -> Result<Vec<Token>, LexerError> {
+
+        let begin = self.current;
+        let mut source = String::new();
+        let mut depth = 0;
+        let mut in_string = false;
+
+        loop {
+            if self.peek().map(|opt| opt == '\n').unwrap_or(false) {
+                self.inc_line_number();
+            }
+
+            match self.advance() {
+                None => return Err(LexerError::UnterminatedString(location.0, location.1)),
+                Some('}') if depth == 0 && !in_string => break,
+                Some(c) => {
+                    match c {
+                        // strings inside the expression may hold braces of their own
+                        '"' => in_string = !in_string,
+                        '{' if !in_string => depth += 1,
+                        '}' if !in_string => depth -= 1,
+                        _ => {},
+                    }
+                    source.push(c);
+                },
+            }
+        }
+
+        let mut chars = source.chars();
+        let mut lexer = BasicLexer::new(&mut chars);
+        lexer.start = begin;
+        lexer.current = begin;
+        Ok(lexer.lex()?.output)
     }
 
     fn number(&mut self, c: char) -> Option<Token> {
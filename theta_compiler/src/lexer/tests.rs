@@ -1,4 +1,4 @@
-use theta_types::bytecode::{Token, TokenType};
+use theta_types::bytecode::{Token, TokenType, StringFragment};
 
 use super::{Lexer, BasicLexer};
 
@@ -55,6 +55,7 @@ define_complex_char_test!(basic_lexer_recog_lte, "<=", TokenType::LessEqual, 2);
 define_complex_char_test!(basic_lexer_recog_arrow, "->", TokenType::Arrow, 2);
 
 define_complex_char_test!(basic_lexer_recog_and, "and", TokenType::And, 3);
+define_complex_char_test!(basic_lexer_recog_as, "as", TokenType::As, 2);
 define_complex_char_test!(basic_lexer_recog_class, "class", TokenType::Class, 5);
 define_complex_char_test!(basic_lexer_recog_else, "else", TokenType::Else, 4);
 define_complex_char_test!(basic_lexer_recog_false, "false", TokenType::False, 5);
@@ -144,4 +145,39 @@ fn basic_lexer_recog_float_trailing_dot() {
             Token::new(0, 2, TokenType::Float(1.0)), 
             Token::new(2, 2, TokenType::Eof)
         ])
-}
\ No newline at end of file
+}
+
+#[test]
+fn basic_lexer_recog_interpolated_string() {
+    let input = "\"a${x + 1}b\"";
+
+    let mut iter = input.chars();
+
+    let lexer = BasicLexer::new(&mut iter);
+
+    assert_eq!(lexer.lex().expect("should not fail").output().clone(),
+        vec![
+            Token::new(0, 12, TokenType::Interpolation(vec![
+                StringFragment::Text(String::from("a")),
+                StringFragment::Tokens(vec![
+                    Token::new(4, 5, TokenType::Identifier(String::from("x"))),
+                    Token::new(6, 7, TokenType::Plus),
+                    Token::new(8, 9, TokenType::Integer(1)),
+                    Token::new(9, 9, TokenType::Eof),
+                ]),
+                StringFragment::Text(String::from("b")),
+            ])),
+            Token::new(12, 12, TokenType::Eof)
+        ])
+}
+
+#[test]
+fn basic_lexer_not_recog_unterminated_interpolation() {
+    let input = "\"a${x\"";
+
+    let mut iter = input.chars();
+
+    let lexer = BasicLexer::new(&mut iter);
+
+    assert!(lexer.lex().is_err())
+}
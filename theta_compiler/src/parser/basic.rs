@@ -1,6 +1,6 @@
 use std::{rc::Rc, cell::RefCell};
 use log::{debug, error, trace};
-use theta_types::{bytecode::{Token, TokenType, Symbol}, errors::parse::ParseError, types::TypeInformation};
+use theta_types::{bytecode::{Token, TokenType, StringFragment, Symbol}, errors::parse::ParseError, types::TypeInformation};
 
 use crate::ast::{symbol::{SymbolTable, SymbolData, ExtSymbolTable, ExtFrameData, FrameData}, Statement, Expression, AbstractTree, FunctionArg, Function, Item};
 use super::{Parser, ParseInfo};
@@ -324,6 +324,8 @@ impl<'a> BasicParser<'a> {
         trace!("read statement");
         if let Some(print_tok) = self.match_token([TokenType::Identifier(String::from("print"))]) {
             self.print_statement(print_tok)
+        } else if let Some(breakpoint_tok) = self.match_token([TokenType::Identifier(String::from("breakpoint"))]) {
+            self.breakpoint_statement(breakpoint_tok)
         } else {
             self.expression_statement()
         }
@@ -338,6 +340,12 @@ impl<'a> BasicParser<'a> {
         Ok(Statement::PrintStatement { expression, information: ParseInfo::new(self.symbol_tbl.borrow().scope_depth(), self.symbol_tbl.clone(), self.frame_data.clone(), begin.location().merge(end_tok.location())) })
     }
 
+    fn breakpoint_statement(&mut self, begin: Token) -> Result<Statement<ParseInfo>, ParseError> {
+        trace!("read breakpoint statement");
+        let end_tok = self.consume(TokenType::Semicolon, "Expected ';' after breakpoint")?;
+        Ok(Statement::BreakpointStatement { information: ParseInfo::new(self.symbol_tbl.borrow().scope_depth(), self.symbol_tbl.clone(), self.frame_data.clone(), begin.location().merge(end_tok.location())) })
+    }
+
     fn expression_statement(&mut self) -> Result<Statement<ParseInfo>, ParseError> {
         trace!("read expression statement");
         let expression = self.expression()?;
@@ -537,10 +545,10 @@ impl<'a> BasicParser<'a> {
 
     fn factor(&mut self) -> Result<Expression<ParseInfo>, ParseError> {
         trace!("read factor");
-        let mut lhs = self.unary()?;
+        let mut lhs = self.cast()?;
 
         while let Some(oper) = self.match_token([TokenType::Star, TokenType::Slash]) {
-            let rhs = self.unary()?;
+            let rhs = self.cast()?;
             let loc = lhs.information().location_data.clone().merge(rhs.information().location_data.clone());
             lhs = Expression::Binary {
                 left: Box::new(lhs),
@@ -553,6 +561,30 @@ impl<'a> BasicParser<'a> {
         Ok(lhs)
     }
 
+    fn cast(&mut self) -> Result<Expression<ParseInfo>, ParseError> {
+        trace!("read cast");
+        let mut lhs = self.unary()?;
+
+        while self.match_token([TokenType::As]).is_some() {
+            let ty_tok = self.consume_if(|ty| ty.is_ident(), "Expected type after 'as'")?;
+            let ty_ident = Symbol::new(ty_tok.clone())?;
+            let ty = match self.symbol_tbl.borrow().get_symbol_data(&ty_ident, self.symbol_tbl.borrow().scope_depth()) {
+                Some(SymbolData::Type { ty }) => ty,
+                Some(_) => return Err(ParseError::from_token(ty_tok, "Expected type after 'as'")),
+                None => TypeInformation::NonLiteral(ty_ident),
+            };
+
+            let loc = lhs.information().location_data.clone().merge(ty_tok.location());
+            lhs = Expression::Cast {
+                value: Box::new(lhs),
+                ty,
+                information: ParseInfo::new(self.symbol_tbl.borrow().scope_depth(), self.symbol_tbl.clone(), self.frame_data.clone(), loc),
+            };
+        }
+
+        Ok(lhs)
+    }
+
     fn unary(&mut self) -> Result<Expression<ParseInfo>, ParseError> {
         trace!("read unary");
         if let Some(oper) = self.match_token([TokenType::Bang, TokenType::Minus]) {
@@ -571,6 +603,13 @@ impl<'a> BasicParser<'a> {
     fn call(&mut self) -> Result<Expression<ParseInfo>, ParseError> {
         trace!("read call");
         let lval = self.primary()?;
+        match &lval {
+            Expression::Literal { literal, information: _ } if literal.ty() == TokenType::Identifier(String::from("format")) && self.check(&TokenType::LeftParen) => {
+                return self.format_expression(literal.clone());
+            },
+            _ => {},
+        }
+
         if let Some(_oper) = self.match_token([TokenType::LeftParen]) {
             let mut args = Vec::new();
 
@@ -596,6 +635,54 @@ impl<'a> BasicParser<'a> {
         }
     }
 
+    fn format_expression(&mut self, begin: Token) -> Result<Expression<ParseInfo>, ParseError> {
+        trace!("read format");
+        self.consume(TokenType::LeftParen, "Expected ( after format")?;
+        let template = self.consume_if(|ty| matches!(ty, TokenType::Str(_)), "Expected a string literal to format")?;
+        let TokenType::Str(text) = template.ty() else {
+            unreachable!("consumed a string literal");
+        };
+        let pieces = format_pieces(&text).ok_or_else(|| ParseError::from_token(template.clone(), "Unmatched brace in format string"))?;
+
+        let mut args = Vec::new();
+        while self.match_token([TokenType::Comma]).is_some() {
+            args.push(self.expression()?);
+        }
+        let end_tok = self.consume(TokenType::RightParen, "Expected ) after format arguments")?;
+
+        Ok(Expression::Format { pieces, args, information: ParseInfo::new(self.symbol_tbl.borrow().scope_depth(), self.symbol_tbl.clone(), self.frame_data.clone(), begin.location().merge(end_tok.location())) })
+    }
+
+    /// Interpolated strings are formatted, with each embedded expression standing in for a placeholder.
+    fn interpolation(&mut self, literal: Token, fragments: Vec<StringFragment>) -> Result<Expression<ParseInfo>, ParseError> {
+        trace!("read interpolated string");
+        let mut pieces = vec![String::new()];
+        let mut args = Vec::new();
+
+        for fragment in fragments {
+            match fragment {
+                StringFragment::Text(text) => pieces.last_mut().expect("there is always a piece").push_str(&text),
+                StringFragment::Tokens(tokens) => {
+                    // the embedded expression belongs to the scope the string is in
+                    let mut parser = BasicParser {
+                        tokens: &tokens,
+                        offset: 0,
+                        symbol_tbl: self.symbol_tbl.clone(),
+                        root_symbol_tbl: self.root_symbol_tbl.clone(),
+                        frame_data: self.frame_data.clone(),
+                    };
+                    args.push(parser.expression()?);
+                    if let Some(tok) = parser.peek().filter(|tok| tok.ty() != TokenType::Eof) {
+                        return Err(ParseError::from_token(tok.clone(), "Expected } after interpolated expression"));
+                    }
+                    pieces.push(String::new());
+                },
+            }
+        }
+
+        Ok(Expression::Format { pieces, args, information: ParseInfo::new(self.symbol_tbl.borrow().scope_depth(), self.symbol_tbl.clone(), self.frame_data.clone(), literal.location()) })
+    }
+
     fn primary(&mut self) -> Result<Expression<ParseInfo>, ParseError> {
         trace!("read primary");
         if let Some(begin_token) = self.match_token([TokenType::LeftParen]) {
@@ -616,6 +703,9 @@ impl<'a> BasicParser<'a> {
                 seq: seq_expressions,
                 information: ParseInfo::new(self.symbol_tbl.borrow().scope_depth(), self.symbol_tbl.clone(), self.frame_data.clone(), begin_token.location().merge(end_token.location())),
             })
+        } else if let Some(TokenType::Interpolation(fragments)) = self.peek().map(Token::ty) {
+            let literal = self.advance().ok_or_else(|| ParseError::from_other("Unexpected EOS"))?;
+            self.interpolation(literal, fragments)
         } else {
             // needs to match literals only
             self
@@ -642,12 +732,33 @@ impl<'a> Parser for BasicParser<'a> {
     }
 
     fn next(&mut self) -> Result<Self::Out, ParseError> {
-        // clean frame data and symbol tables. The root should likely not be changed when we add cross-modules'
-        // TODO: remove root_symbol_tbl changes here
+        // clean frame data and return to the root symbol table.
+        // every item shares the root, so the functions of a compile unit can call each other regardless of order.
         self.frame_data = Rc::new(RefCell::new(FrameData::new()));
-        self.root_symbol_tbl = Rc::new(RefCell::new(SymbolTable::default()));
         self.symbol_tbl = self.root_symbol_tbl.clone();
 
         self.item()
     }
-}
\ No newline at end of file
+}
+
+/// Splits a format string around its `{}` placeholders, so there is one more piece than there are placeholders.
+/// `{{` and `}}` stand for literal braces. Returns `None` if a brace is unmatched.
+fn format_pieces(template: &str) -> Option<Vec<String>> {
+    let mut pieces = vec![String::new()];
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('{', Some('}')) => {
+                chars.next();
+                pieces.push(String::new());
+            },
+            ('{', Some('{')) | ('}', Some('}')) => {
+                chars.next();
+                pieces.last_mut()?.push(c);
+            },
+            ('{' | '}', _) => return None,
+            (c, _) => pieces.last_mut()?.push(c),
+        }
+    }
+    Some(pieces)
+}
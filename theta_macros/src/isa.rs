@@ -0,0 +1,244 @@
+use std::collections::HashMap;
+
+use proc_macro2::TokenStream;
+use quote::{quote, format_ident};
+use syn::{parse::{Parse, ParseStream}, Attribute, Error, Fields, Ident, ItemEnum, LitInt, LitStr, Path, Token, Type};
+
+/// Arguments to `#[isa(...)]`: the error types the generated `encode` and `decode` report through.
+pub struct IsaArgs {
+    decode_error: Path,
+    encode_error: Path,
+}
+
+impl Parse for IsaArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut decode_error = None;
+        let mut encode_error = None;
+
+        while !input.is_empty() {
+            let k = input.parse::<Ident>()?;
+            input.parse::<Token![=]>()?;
+
+            match k.to_string().as_str() {
+                "decode_error" => decode_error = Some(input.parse()?),
+                "encode_error" => encode_error = Some(input.parse()?),
+                _ => return Err(Error::new(k.span(), "expected decode_error or encode_error")),
+            }
+
+            if input.is_empty() {
+                break;
+            }
+            input.parse::<Token![,]>()?;
+        }
+
+        match (decode_error, encode_error) {
+            (Some(decode_error), Some(encode_error)) => Ok(IsaArgs { decode_error, encode_error }),
+            _ => Err(input.error("isa requires decode_error and encode_error")),
+        }
+    }
+}
+
+/// `#[op(0x1, "Constant with offset {offset:#X}")]`, optionally followed by `, jump`.
+struct OpAttr {
+    code: LitInt,
+    description: LitStr,
+    jump: bool,
+}
+
+impl Parse for OpAttr {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let code = input.parse()?;
+        input.parse::<Token![,]>()?;
+        let description = input.parse()?;
+        let mut jump = false;
+
+        while input.parse::<Option<Token![,]>>()?.is_some() {
+            let flag = input.parse::<Ident>()?;
+            match flag.to_string().as_str() {
+                "jump" => jump = true,
+                _ => return Err(Error::new(flag.span(), "unknown op flag")),
+            }
+        }
+
+        Ok(OpAttr { code, description, jump })
+    }
+}
+
+struct Operand {
+    name: Ident,
+    ty: Type,
+    /// The type the operand is stored as in an assembled chunk.
+    wire: Type,
+    /// Whether the operand indexes into the constant pool.
+    constant: bool,
+}
+
+struct Instruction {
+    name: Ident,
+    op: OpAttr,
+    operands: Vec<Operand>,
+}
+
+fn take_attr(attrs: &mut Vec<Attribute>, name: &str) -> Option<Attribute> {
+    let idx = attrs.iter().position(|attr| attr.path().is_ident(name))?;
+    Some(attrs.remove(idx))
+}
+
+pub fn expand(args: IsaArgs, mut item: ItemEnum) -> syn::Result<TokenStream> {
+    let mut instructions = Vec::new();
+    let mut seen_codes: HashMap<u8, Ident> = HashMap::new();
+
+    for variant in item.variants.iter_mut() {
+        let op_attr = take_attr(&mut variant.attrs, "op").ok_or_else(|| Error::new(variant.ident.span(), "instruction is missing #[op(code, \"description\")]"))?;
+        let op: OpAttr = op_attr.parse_args()?;
+
+        let code = op.code.base10_parse::<u8>()?;
+        if let Some(other) = seen_codes.insert(code, variant.ident.clone()) {
+            return Err(Error::new(op.code.span(), format!("opcode {code:#X} is already used by {other}")));
+        }
+
+        let mut operands = Vec::new();
+        match &mut variant.fields {
+            Fields::Unit => {},
+            Fields::Named(fields) => {
+                for field in fields.named.iter_mut() {
+                    let wire = match take_attr(&mut field.attrs, "wire") {
+                        Some(attr) => attr.parse_args()?,
+                        None => field.ty.clone(),
+                    };
+                    let constant = take_attr(&mut field.attrs, "constant").is_some();
+
+                    operands.push(Operand {
+                        name: field.ident.clone().expect("named field"),
+                        ty: field.ty.clone(),
+                        wire,
+                        constant,
+                    });
+                }
+            },
+            Fields::Unnamed(fields) => return Err(Error::new_spanned(fields, "instruction operands must be named")),
+        }
+
+        instructions.push(Instruction { name: variant.ident.clone(), op, operands });
+    }
+
+    let enum_name = &item.ident;
+    let IsaArgs { decode_error, encode_error } = args;
+
+    let mut sizes = Vec::new();
+    let mut hexcodes = Vec::new();
+    let mut descriptions = Vec::new();
+    let mut jumps = Vec::new();
+    let mut remaps = Vec::new();
+    let mut encodes = Vec::new();
+    let mut decodes = Vec::new();
+
+    for Instruction { name, op, operands } in &instructions {
+        let code = &op.code;
+        let description = &op.description;
+        let names: Vec<&Ident> = operands.iter().map(|o| &o.name).collect();
+        let wires: Vec<&Type> = operands.iter().map(|o| &o.wire).collect();
+        let tys: Vec<&Type> = operands.iter().map(|o| &o.ty).collect();
+
+        sizes.push(quote! { #enum_name::#name { .. } => 1 #(+ ::std::mem::size_of::<#wires>())* });
+        hexcodes.push(quote! { #enum_name::#name { .. } => #code });
+        descriptions.push(quote! { #enum_name::#name { #(#names),* } => format!(#description) });
+
+        if op.jump {
+            jumps.push(quote! { #enum_name::#name { .. } });
+        }
+
+        if operands.iter().any(|o| o.constant) {
+            let remapped = operands.iter().map(|o| {
+                let name = &o.name;
+                if o.constant { quote! { #name: remap(#name) } } else { quote! { #name } }
+            });
+            remaps.push(quote! { #enum_name::#name { #(#names),* } => #enum_name::#name { #(#remapped),* } });
+        }
+
+        encodes.push(quote! {
+            #enum_name::#name { #(#names),* } => {
+                let mut bytes = vec![#code];
+                #(
+                    let operand = <#wires>::try_from(*#names).map_err(|_| #encode_error::OperandOutOfRange(self.human_readable()))?;
+                    bytes.extend_from_slice(&operand.to_le_bytes());
+                )*
+                bytes
+            }
+        });
+
+        let readers = operands.iter().map(|o| {
+            let Operand { name, wire, .. } = o;
+            let raw = format_ident!("{}_bytes", name);
+            quote! {
+                let mut #raw = [0u8; ::std::mem::size_of::<#wire>()];
+                let len = #raw.len();
+                #raw.copy_from_slice(code.get(at..at + len).ok_or(#decode_error::TruncatedInstruction(code.len()))?);
+                at += len;
+                let #name = <#wire>::from_le_bytes(#raw);
+            }
+        });
+
+        decodes.push(quote! {
+            #code => {
+                #(#readers)*
+                let _ = at;
+                #enum_name::#name { #(#names: <#tys>::try_from(#names).map_err(|_| #decode_error::InvalidOperand(#code))?),* }
+            }
+        });
+    }
+
+    let is_jump = if jumps.is_empty() { quote! { false } } else { quote! { matches!(self, #(#jumps)|*) } };
+    let remap_constants = if remaps.is_empty() {
+        quote! { let _ = remap; self }
+    } else {
+        quote! { match self { #(#remaps,)* _ => self } }
+    };
+
+    Ok(quote! {
+        #item
+
+        #[allow(clippy::useless_conversion, clippy::unnecessary_fallible_conversions)]
+        impl #enum_name {
+            /// The size of the OpCode in bytes when assembled to disk, in bytes
+            pub fn size(&self) -> usize {
+                match self { #(#sizes,)* }
+            }
+
+            pub fn as_hexcode(&self) -> usize {
+                match self { #(#hexcodes,)* }
+            }
+
+            #[allow(unused_variables)]
+            pub fn human_readable(&self) -> String {
+                match self { #(#descriptions,)* }
+            }
+
+            pub fn is_jump(&self) -> bool {
+                #is_jump
+            }
+
+            /// Rewrites every constant pool reference in the OpCode through `remap`.
+            pub fn remap_constants(self, remap: impl Fn(usize) -> usize) -> #enum_name {
+                #remap_constants
+            }
+
+            /// Assembles the OpCode into its on-disk representation.
+            /// Fails if an operand does not fit into the type it is stored as.
+            pub fn encode(&self) -> Result<Vec<u8>, #encode_error> {
+                Ok(match self { #(#encodes,)* })
+            }
+
+            /// Reads a single assembled OpCode from the front of `code`, returning it along with its size in bytes.
+            pub fn decode(code: &[u8]) -> Result<(#enum_name, usize), #decode_error> {
+                let opcode = *code.first().ok_or(#decode_error::TruncatedInstruction(code.len()))?;
+                let mut at = 1;
+                let op = match opcode {
+                    #(#decodes,)*
+                    unknown => return Err(#decode_error::UnknownOpCode(unknown)),
+                };
+                Ok((op, op.size()))
+            }
+        }
+    })
+}
@@ -2,6 +2,8 @@ use proc_macro::TokenStream;
 use syn::{Ident, ExprClosure, LitInt, parse::Parse, LitStr, Token, parse_macro_input, Error, Expr};
 use quote::{quote, quote_spanned};
 
+mod isa;
+
 struct E2ETest {
     name: Ident,
     code_fragment: LitStr,
@@ -75,4 +77,22 @@ pub fn make_answer(item: TokenStream) -> TokenStream {
     };
 
     TokenStream::from(expanded)
-}
\ No newline at end of file
+}
+
+/// Generates the encoding, decoding and metadata functions of an instruction set from its enum definition.
+///
+/// Every variant is annotated with `#[op(code, "description")]` (plus `jump` for jump instructions),
+/// and every operand may be annotated with `#[wire(T)]` for the type it is stored as on disk and
+/// `#[constant]` if it refers to the constant pool. The error types passed to the attribute need
+/// `TruncatedInstruction(usize)`, `UnknownOpCode(u8)` and `InvalidOperand(u8)` variants for decoding,
+/// and an `OperandOutOfRange(String)` variant for encoding.
+#[proc_macro_attribute]
+pub fn isa(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(attr as isa::IsaArgs);
+    let item = parse_macro_input!(item as syn::ItemEnum);
+
+    match isa::expand(args, item) {
+        Ok(expanded) => TokenStream::from(expanded),
+        Err(e) => TokenStream::from(e.to_compile_error()),
+    }
+}
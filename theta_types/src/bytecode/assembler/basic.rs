@@ -1,8 +1,8 @@
 use std::io::Write;
 
 use crate::{bytecode::{
-    Chunk, OpCode, ThetaBitstream, ThetaConstant, BOOL_MARKER, CHUNK_HEADER, CONSTANT_POOL_HEADER,
-    DOUBLE_MARKER, INT_MARKER, STRING_MARKER, ThetaFunction, BITSTREAM_HEADER, FUNCTION_POOL_HEADER, FUNCTION_HEADER,
+    Chunk, ThetaBitstream, ThetaConstant, BOOL_MARKER, CHUNK_HEADER, CONSTANT_POOL_HEADER,
+    DOUBLE_MARKER, INT_MARKER, STRING_MARKER, DEBUG_INFO_MARKER, ThetaFunction, BITSTREAM_HEADER, FUNCTION_POOL_HEADER, FUNCTION_HEADER,
 }, types::TypeInformation};
 
 use super::{AssembleError, Assembler};
@@ -41,69 +41,8 @@ impl<'a> Assembler for BasicAssembler<'a> {
         let chunk_size = chunk.instruction_size();
         self.output_file.write_all(&usize::to_le_bytes(chunk_size))?;
 
-        let instructions_in_chunk = chunk.instructions();
-        for opcode in instructions_in_chunk {
-            match opcode {
-                OpCode::ReturnVoid => self.output_file.write(&[0x0u8])?,
-                OpCode::Return => self.output_file.write(&[0xF0u8])?,
-                OpCode::Constant { offset } => self.output_file.write(&[1u8, *offset as u8])?,
-                OpCode::Push { size } => {
-                    let off_bytes = size.to_le_bytes();
-                    self.output_file.write_all(&[2u8])?;
-                    self.output_file.write(&off_bytes)?
-                }
-                OpCode::Pop => self.output_file.write(&[3u8])?,
-
-                OpCode::Add => self.output_file.write(&[4u8])?,
-                OpCode::Subtract => self.output_file.write(&[5u8])?,
-                OpCode::Multiply => self.output_file.write(&[6u8])?,
-                OpCode::Divide => self.output_file.write(&[7u8])?,
-                OpCode::Negate => self.output_file.write(&[8u8])?,
-                OpCode::Equal => self.output_file.write(&[9u8])?,
-                OpCode::GreaterThan => self.output_file.write(&[0xAu8])?,
-                OpCode::LessThan => self.output_file.write(&[0xBu8])?,
-
-                OpCode::DefineGlobal { offset } => {
-                    self.output_file.write(&[0xC0u8, *offset as u8])?
-                }
-                OpCode::GetGlobal { offset } => self.output_file.write(&[0xC1u8, *offset as u8])?,
-
-                OpCode::DefineLocal { offset } => {
-                    self.output_file.write(&[0xC2u8, *offset as u8])?
-                }
-                OpCode::GetLocal { offset } => self.output_file.write(&[0xC3u8, *offset as u8])?,
-
-                OpCode::JumpLocal { offset } => self.output_file.write(&[0xD0u8, *offset as u8])?,
-
-                OpCode::JumpLocalIfFalse { offset } => {
-                    self.output_file.write(&[0xD1u8, *offset as u8])?
-                }
-
-                OpCode::JumpFar { offset } => {
-                    let off_bytes = offset.to_le_bytes();
-
-                    self.output_file.write_all(&[0xD2u8])?;
-                    self.output_file.write(&off_bytes)?
-                }
-
-                OpCode::JumpFarIfFalse { offset } => {
-                    let off_bytes = offset.to_le_bytes();
-
-                    self.output_file.write_all(&[0xD3u8])?;
-                    self.output_file.write(&off_bytes)?
-                }
-
-                OpCode::CallDirect { name_offset } => {
-                    self.output_file.write(&[0xE0, *name_offset as u8])?
-                },
-
-                OpCode::DebugPrint => self.output_file.write(&[0xFFu8])?,
-                OpCode::Noop => self.output_file.write(&[0xFDu8])?,
-                OpCode::GreaterEqual => self.output_file.write(&[0xA1])?,
-                OpCode::LessEqual => self.output_file.write(&[0xB1])?,
-                OpCode::Breakpoint => self.output_file.write(&[0xFE])?,
-
-            };
+        for opcode in chunk.instructions() {
+            self.output_file.write_all(&opcode.encode()?)?;
         }
         Ok(())
     }
@@ -181,7 +120,18 @@ impl<'a> Assembler for BasicAssembler<'a> {
                 TypeInformation::Function(_, _) => todo!(),
             }
 
+            self.output_file.write_all(&usize::to_le_bytes(func.locals))?;
+
+            let line_table = func.chunk.line_table();
             self.assemble_chunk(func.chunk)?;
+
+            // debug info: the byte offset of every instruction that starts a source line, and the line
+            self.output_file.write_all(DEBUG_INFO_MARKER)?;
+            self.output_file.write_all(&usize::to_le_bytes(line_table.len()))?;
+            for (offset, line) in line_table {
+                self.output_file.write_all(&usize::to_le_bytes(offset))?;
+                self.output_file.write_all(&usize::to_le_bytes(line))?;
+            }
         }
 
         Ok(())
@@ -28,12 +28,14 @@ pub trait Assembler {
 #[derive(Debug)]
 pub enum AssembleError {
     IOError(std::io::Error),
+    OperandOutOfRange(String),
 }
 
 impl fmt::Display for AssembleError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             AssembleError::IOError(e) => write!(f, "AssembleError: {:?}", e),
+            AssembleError::OperandOutOfRange(op) => write!(f, "AssembleError: operand out of range in {}", op),
         }
     }
 }
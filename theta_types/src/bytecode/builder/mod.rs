@@ -0,0 +1,269 @@
+use std::{fmt, error::Error, collections::HashMap};
+
+use super::{Chunk, OpCode, ThetaConstant};
+
+#[cfg(test)]
+mod tests;
+
+/// A position in a chunk that jumps can target before the position is known.
+/// Labels may be placed before (backward jumps) or after (forward jumps) the jumps that use them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Label(usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JumpCondition {
+    Always,
+    IfFalse,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BuilderInstruction {
+    Op(OpCode),
+    /// A jump whose size (local or far) is decided when the chunk is built.
+    Jump { condition: JumpCondition, target: Label },
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ChunkBuildError {
+    UnboundLabel(Label),
+    /// A jump inside an appended chunk does not land on an instruction boundary.
+    InvalidJumpTarget(isize),
+    /// Raw jumps carry a fixed offset and would be broken by relaxation; use `jump` / `jump_if_false`.
+    RawJump(OpCode),
+}
+
+impl fmt::Display for ChunkBuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChunkBuildError::UnboundLabel(label) => write!(f, "label {} was never placed", label.0),
+            ChunkBuildError::InvalidJumpTarget(target) => write!(f, "jump to byte {target:#X} does not land on an instruction"),
+            ChunkBuildError::RawJump(op) => write!(f, "raw jump emitted into chunk builder: {}", op.human_readable()),
+        }
+    }
+}
+
+impl Error for ChunkBuildError {}
+
+/// Builds a `Chunk` out of instructions and symbolic jumps.
+///
+/// Jumps are kept as labels until `build` is called, at which point every jump is
+/// relaxed to the smallest encoding (`JumpLocal` or `JumpFar`) that reaches its target.
+/// Because offsets are only computed at the end, instructions can be inserted or
+/// removed at any point without invalidating jumps.
+#[derive(Debug, Clone, Default)]
+pub struct ChunkBuilder {
+    instructions: Vec<BuilderInstruction>,
+    // label -> index of the instruction it is bound to. An index equal to the
+    // instruction count refers to the end of the chunk.
+    labels: Vec<Option<usize>>,
+    constants: Vec<ThetaConstant>,
+    // index of an instruction -> the source line it starts
+    lines: HashMap<usize, usize>,
+}
+
+impl ChunkBuilder {
+    pub fn new() -> ChunkBuilder {
+        ChunkBuilder { instructions: Vec::new(), labels: Vec::new(), constants: Vec::new(), lines: HashMap::new() }
+    }
+
+    /// Creates a label that is not bound to a position yet.
+    pub fn new_label(&mut self) -> Label {
+        self.labels.push(None);
+        Label(self.labels.len() - 1)
+    }
+
+    /// Binds the label to the next instruction emitted.
+    pub fn place_label(&mut self, label: Label) {
+        self.labels[label.0] = Some(self.instructions.len());
+    }
+
+    /// Creates a label bound to the next instruction emitted. Useful for backward jumps.
+    pub fn label_here(&mut self) -> Label {
+        let label = self.new_label();
+        self.place_label(label);
+        label
+    }
+
+    pub fn emit(&mut self, op: OpCode) -> Result<(), ChunkBuildError> {
+        if op.is_jump() {
+            return Err(ChunkBuildError::RawJump(op));
+        }
+        self.instructions.push(BuilderInstruction::Op(op));
+        Ok(())
+    }
+
+    pub fn jump(&mut self, target: Label) {
+        self.instructions.push(BuilderInstruction::Jump { condition: JumpCondition::Always, target });
+    }
+
+    pub fn jump_if_false(&mut self, target: Label) {
+        self.instructions.push(BuilderInstruction::Jump { condition: JumpCondition::IfFalse, target });
+    }
+
+    pub fn write_constant(&mut self, constant: ThetaConstant) {
+        self.constants.push(constant);
+    }
+
+    /// Appends a finished chunk, relocating its constants behind the ones already in the builder.
+    /// Any jumps inside the chunk are turned back into labels so they get relaxed again.
+    pub fn append_chunk(&mut self, chunk: Chunk) -> Result<(), ChunkBuildError> {
+        let chunk = chunk.relocate(self.constants.len());
+        self.constants.extend_from_slice(chunk.constants());
+
+        let base = self.instructions.len();
+        for (idx, line) in chunk.line_map() {
+            self.lines.entry(base + idx).or_insert(*line);
+        }
+
+        let mut positions = Vec::with_capacity(chunk.instructions().len() + 1);
+        let mut position = 0;
+        for op in chunk.instructions() {
+            positions.push(position);
+            position += op.size();
+        }
+        positions.push(position);
+
+        for (idx, op) in chunk.instructions().iter().enumerate() {
+            let (condition, offset) = match *op {
+                OpCode::JumpLocal { offset } => (JumpCondition::Always, offset as isize),
+                OpCode::JumpLocalIfFalse { offset } => (JumpCondition::IfFalse, offset as isize),
+                OpCode::JumpFar { offset } => (JumpCondition::Always, offset),
+                OpCode::JumpFarIfFalse { offset } => (JumpCondition::IfFalse, offset),
+                other => {
+                    self.instructions.push(BuilderInstruction::Op(other));
+                    continue;
+                }
+            };
+
+            let target_byte = positions[idx] as isize + offset;
+            let target_idx = positions.iter().position(|p| *p as isize == target_byte).ok_or(ChunkBuildError::InvalidJumpTarget(target_byte))?;
+
+            self.labels.push(Some(base + target_idx));
+            let target = Label(self.labels.len() - 1);
+            self.instructions.push(BuilderInstruction::Jump { condition, target });
+        }
+
+        Ok(())
+    }
+
+    /// Inserts an instruction at `index`. Labels bound past `index` move with the instructions they point at;
+    /// a label bound exactly at `index` now points at the inserted instruction.
+    pub fn insert(&mut self, index: usize, instruction: BuilderInstruction) -> Result<(), ChunkBuildError> {
+        if let BuilderInstruction::Op(op) = instruction {
+            if op.is_jump() {
+                return Err(ChunkBuildError::RawJump(op));
+            }
+        }
+
+        self.instructions.insert(index, instruction);
+        for bound in self.labels.iter_mut().flatten() {
+            if *bound > index {
+                *bound += 1;
+            }
+        }
+        self.lines = self.lines.drain().map(|(idx, line)| if idx >= index { (idx + 1, line) } else { (idx, line) }).collect();
+        Ok(())
+    }
+
+    /// Removes the instruction at `index`. Labels bound to it now point at the instruction that followed it.
+    pub fn remove(&mut self, index: usize) -> BuilderInstruction {
+        let removed = self.instructions.remove(index);
+        for bound in self.labels.iter_mut().flatten() {
+            if *bound > index {
+                *bound -= 1;
+            }
+        }
+        // a line starting at the removed instruction now starts at the one that followed it
+        let removed_line = self.lines.remove(&index);
+        self.lines = self.lines.drain().map(|(idx, line)| if idx > index { (idx - 1, line) } else { (idx, line) }).collect();
+        if let Some(line) = removed_line {
+            self.lines.entry(index).or_insert(line);
+        }
+        removed
+    }
+
+    pub fn instructions(&self) -> &[BuilderInstruction] {
+        &self.instructions
+    }
+
+    pub fn len(&self) -> usize {
+        self.instructions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.instructions.is_empty()
+    }
+
+    /// Resolves all labels and produces the final chunk.
+    pub fn build(self) -> Result<Chunk, ChunkBuildError> {
+        let targets = self.instructions.iter().map(|inst| match inst {
+            BuilderInstruction::Op(_) => Ok(None),
+            BuilderInstruction::Jump { condition: _, target } => self.labels[target.0].map(Some).ok_or(ChunkBuildError::UnboundLabel(*target)),
+        }).collect::<Result<Vec<Option<usize>>, ChunkBuildError>>()?;
+
+        // every jump starts out local and is only ever promoted to far.
+        // since promotion can only grow the chunk this is guaranteed to settle.
+        let mut far = vec![false; self.instructions.len()];
+        let positions = loop {
+            let positions = self.positions(&far);
+            let mut changed = false;
+
+            for (idx, target) in targets.iter().enumerate() {
+                if let Some(target) = target {
+                    let offset = positions[*target] as isize - positions[idx] as isize;
+                    if !far[idx] && i8::try_from(offset).is_err() {
+                        far[idx] = true;
+                        changed = true;
+                    }
+                }
+            }
+
+            if !changed {
+                break positions;
+            }
+        };
+
+        let mut chunk = Chunk::new();
+        for constant in self.constants {
+            chunk.write_constant(constant);
+        }
+        for (idx, line) in self.lines {
+            chunk.mark_line(idx, line);
+        }
+
+        for (idx, inst) in self.instructions.into_iter().enumerate() {
+            let op = match (inst, targets[idx]) {
+                (BuilderInstruction::Op(op), _) => op,
+                (BuilderInstruction::Jump { condition, target: _ }, Some(target)) => {
+                    let offset = positions[target] as isize - positions[idx] as isize;
+                    match (condition, far[idx]) {
+                        (JumpCondition::Always, false) => OpCode::JumpLocal { offset: offset as i8 },
+                        (JumpCondition::IfFalse, false) => OpCode::JumpLocalIfFalse { offset: offset as i8 },
+                        (JumpCondition::Always, true) => OpCode::JumpFar { offset },
+                        (JumpCondition::IfFalse, true) => OpCode::JumpFarIfFalse { offset },
+                    }
+                },
+                (BuilderInstruction::Jump { condition: _, target }, None) => return Err(ChunkBuildError::UnboundLabel(target)),
+            };
+            chunk.write_to_chunk(op);
+        }
+
+        Ok(chunk)
+    }
+
+    /// Byte offset of every instruction, plus the end of the chunk, given which jumps are far.
+    fn positions(&self, far: &[bool]) -> Vec<usize> {
+        let mut positions = Vec::with_capacity(self.instructions.len() + 1);
+        let mut position = 0;
+        for (idx, inst) in self.instructions.iter().enumerate() {
+            positions.push(position);
+            position += match inst {
+                BuilderInstruction::Op(op) => op.size(),
+                BuilderInstruction::Jump { condition: _, target: _ } if far[idx] => OpCode::JumpFar { offset: 0 }.size(),
+                BuilderInstruction::Jump { condition: _, target: _ } => OpCode::JumpLocal { offset: 0 }.size(),
+            };
+        }
+        positions.push(position);
+        positions
+    }
+}
@@ -0,0 +1,103 @@
+use crate::{bytecode::{OpCode, ThetaConstant}, build_chunk};
+
+use super::{ChunkBuilder, ChunkBuildError, BuilderInstruction};
+
+#[test]
+fn chunk_builder_forward_jump_is_local() {
+    let mut builder = ChunkBuilder::new();
+    let end = builder.new_label();
+    builder.jump_if_false(end);
+    builder.emit(OpCode::Pop).unwrap();
+    builder.place_label(end);
+    builder.emit(OpCode::ReturnVoid).unwrap();
+
+    let chunk = builder.build().expect("failed to build chunk");
+    assert_eq!(chunk.instructions(), &vec![OpCode::JumpLocalIfFalse { offset: 3 }, OpCode::Pop, OpCode::ReturnVoid]);
+}
+
+#[test]
+fn chunk_builder_backward_jump_is_negative() {
+    let mut builder = ChunkBuilder::new();
+    let head = builder.label_here();
+    builder.emit(OpCode::Noop).unwrap();
+    builder.emit(OpCode::Pop).unwrap();
+    builder.jump(head);
+
+    let chunk = builder.build().expect("failed to build chunk");
+    assert_eq!(chunk.instructions(), &vec![OpCode::Noop, OpCode::Pop, OpCode::JumpLocal { offset: -2 }]);
+}
+
+#[test]
+fn chunk_builder_relaxes_to_far_jump() {
+    let mut builder = ChunkBuilder::new();
+    let end = builder.new_label();
+    builder.jump_if_false(end);
+    for _ in 0..200 {
+        builder.emit(OpCode::Noop).unwrap();
+    }
+    builder.place_label(end);
+
+    let chunk = builder.build().expect("failed to build chunk");
+    assert_eq!(chunk.instructions()[0], OpCode::JumpFarIfFalse { offset: 209 });
+}
+
+#[test]
+fn chunk_builder_insert_fixes_jumps() {
+    let mut builder = ChunkBuilder::new();
+    let end = builder.new_label();
+    builder.jump(end);
+    builder.emit(OpCode::Pop).unwrap();
+    builder.place_label(end);
+    builder.emit(OpCode::ReturnVoid).unwrap();
+
+    builder.insert(1, BuilderInstruction::Op(OpCode::Noop)).unwrap();
+    builder.remove(2);
+    builder.insert(2, BuilderInstruction::Op(OpCode::Add)).unwrap();
+
+    let chunk = builder.build().expect("failed to build chunk");
+    assert_eq!(chunk.instructions(), &vec![OpCode::JumpLocal { offset: 3 }, OpCode::Noop, OpCode::Add, OpCode::ReturnVoid]);
+}
+
+#[test]
+fn chunk_builder_append_chunk_relocates() {
+    let mut builder = ChunkBuilder::new();
+    builder.append_chunk(build_chunk!(OpCode::Constant { offset: 0 }; ThetaConstant::Int(1))).unwrap();
+    builder.append_chunk(build_chunk!(OpCode::JumpLocalIfFalse { offset: 4 }, OpCode::Constant { offset: 0 }, OpCode::Pop; ThetaConstant::Int(2))).unwrap();
+
+    // growing the chunk in front of the appended jump's target should keep it pointing at `Pop`'s successor
+    builder.insert(2, BuilderInstruction::Op(OpCode::Noop)).unwrap();
+
+    let chunk = builder.build().expect("failed to build chunk");
+    assert_eq!(chunk.instructions(), &vec![OpCode::Constant { offset: 0 }, OpCode::JumpLocalIfFalse { offset: 5 }, OpCode::Noop, OpCode::Constant { offset: 1 }, OpCode::Pop]);
+    assert_eq!(chunk.constants(), &vec![ThetaConstant::Int(1), ThetaConstant::Int(2)]);
+}
+
+#[test]
+fn chunk_builder_unbound_label_fails() {
+    let mut builder = ChunkBuilder::new();
+    let nowhere = builder.new_label();
+    builder.jump(nowhere);
+
+    assert_eq!(builder.build().err(), Some(ChunkBuildError::UnboundLabel(nowhere)));
+}
+
+#[test]
+fn chunk_builder_keeps_lines_in_sync() {
+    let mut line_one = crate::bytecode::Chunk::new();
+    line_one.write_to_chunk(OpCode::Noop);
+    line_one.mark_line(0, 1);
+    let mut line_two = crate::bytecode::Chunk::new();
+    line_two.write_to_chunk(OpCode::Pop);
+    line_two.mark_line(0, 2);
+
+    let mut builder = ChunkBuilder::new();
+    builder.append_chunk(line_one).unwrap();
+    builder.append_chunk(line_two).unwrap();
+    builder.insert(1, BuilderInstruction::Op(OpCode::Noop)).unwrap();
+    builder.remove(0);
+
+    let chunk = builder.build().expect("failed to build chunk");
+    assert_eq!(chunk.instructions(), &vec![OpCode::Noop, OpCode::Pop]);
+    // the first line moved onto the instruction that replaced it
+    assert_eq!(chunk.line_table(), vec![(0, 1), (OpCode::Noop.size(), 2)]);
+}
@@ -1,11 +1,12 @@
 use std::collections::HashMap;
 
-use super::{OpCode, ThetaConstant};
+use super::{OpCode, ThetaConstant, DisassembleError};
 
 pub const CHUNK_HEADER: [u8; 8] = [84, 104, 101, 67, 104, 117, 110, 107];
 
 #[derive(Debug, Clone)]
 pub struct Chunk {
+    // index of an instruction -> the source line it starts. instructions without an entry belong to the line before them.
     line_map: HashMap<usize, usize>,
     instructions: Vec<OpCode>,
     constants: Vec<ThetaConstant>,
@@ -43,6 +44,42 @@ impl Chunk {
         &self.constants
     }
 
+    /// Records that the instruction at `index` starts source line `line`.
+    /// Lines that are already recorded are kept, since those come from the more deeply nested code.
+    pub fn mark_line(&mut self, index: usize, line: usize) {
+        self.line_map.entry(index).or_insert(line);
+    }
+
+    pub fn line_map(&self) -> &HashMap<usize, usize> {
+        &self.line_map
+    }
+
+    /// The byte offset of every instruction that starts a source line, along with the line, in order.
+    pub fn line_table(&self) -> Vec<(usize, usize)> {
+        let mut table = Vec::new();
+        let mut position = 0;
+        for (idx, op) in self.instructions.iter().enumerate() {
+            if let Some(line) = self.line_map.get(&idx) {
+                table.push((position, *line));
+            }
+            position += op.size();
+        }
+        table
+    }
+
+    /// Decodes assembled instructions (without the chunk header) back into a chunk.
+    /// Constants are not part of the assembled instructions, so the chunk's constant pool is left empty.
+    pub fn from_bytes(code: &[u8]) -> Result<Chunk, DisassembleError> {
+        let mut chunk = Chunk::new();
+        let mut offset = 0;
+        while offset < code.len() {
+            let (op, size) = OpCode::decode(&code[offset..])?;
+            chunk.write_to_chunk(op);
+            offset += size;
+        }
+        Ok(chunk)
+    }
+
     pub fn relocate(self, offset: usize) -> Chunk {
         let inst = self.instructions.into_iter().map(|x| x.relocate_constants(offset)).collect();
 
@@ -61,10 +98,15 @@ impl Chunk {
         for constant in other.constants {
             new_chunk.write_constant(constant);
         }
+        let instruction_offset = new_chunk.instructions.len();
         for opcode in other.instructions {
             let new_opcode = opcode.relocate_constants(offset_size);
             new_chunk.write_to_chunk(new_opcode);
         }
+        new_chunk.line_map = self.line_map;
+        for (idx, line) in other.line_map {
+            new_chunk.mark_line(idx + instruction_offset, line);
+        }
         new_chunk
     }
 }
@@ -0,0 +1,87 @@
+use std::collections::HashMap;
+
+use log::debug;
+
+use crate::bytecode::{ThetaBitstream, ThetaCompiledFunction, ThetaFileVisitor, ThetaConstant, ThetaFileWalker, ThetaFunction, Chunk};
+
+use super::{Disassembler, DisassembleError};
+
+/// Reads an assembled bitstream back into a `ThetaBitstream`, decoding every function's chunk into `OpCode`s.
+/// This is the inverse of `BasicAssembler::assemble_bitstream`, and is what tools that rewrite bitstreams (such as the linker) consume.
+pub struct BitstreamDisassembler {
+    bitstream: ThetaBitstream,
+    error: Option<DisassembleError>,
+}
+
+impl BitstreamDisassembler {
+    pub fn new() -> BitstreamDisassembler {
+        BitstreamDisassembler { bitstream: ThetaBitstream::new(), error: None }
+    }
+}
+
+impl Default for BitstreamDisassembler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Disassembler for BitstreamDisassembler {
+    type Out = ThetaBitstream;
+
+    fn disassemble(&mut self, input: &dyn AsRef<[u8]>) -> Result<ThetaBitstream, DisassembleError> {
+        let mut tfw = ThetaFileWalker {};
+        tfw.walk_theta_file(self, input)?;
+
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => Ok(self.bitstream.clone()),
+        }
+    }
+}
+
+impl ThetaFileVisitor for BitstreamDisassembler {
+    fn visit_theta_file(&mut self) {
+        debug!("seen theta file")
+    }
+
+    fn visit_theta_bitstream(&mut self) {
+        debug!("seen theta bitstream");
+        self.bitstream = ThetaBitstream::new();
+        self.error = None;
+    }
+
+    fn visit_theta_constant(&mut self, constant: ThetaConstant) {
+        self.bitstream.write_constant(constant);
+    }
+
+    fn visit_theta_function(&mut self, function: ThetaCompiledFunction) {
+        // skip the chunk header and size
+        match Chunk::from_bytes(&function.chunk[16..]) {
+            Ok(mut chunk) => {
+                // line offsets are in bytes, chunk lines are by instruction
+                let mut positions = HashMap::new();
+                let mut position = 0;
+                for (idx, op) in chunk.instructions().iter().enumerate() {
+                    positions.insert(position, idx);
+                    position += op.size();
+                }
+                for (offset, line) in &function.lines {
+                    if let Some(idx) = positions.get(offset) {
+                        chunk.mark_line(*idx, *line);
+                    }
+                }
+
+                self.bitstream.write_function(ThetaFunction {
+                    args: function.args,
+                    chunk,
+                    name: function.name,
+                    return_ty: function.return_ty,
+                    locals: function.locals,
+                })
+            },
+            Err(err) => {
+                self.error.get_or_insert(err);
+            },
+        }
+    }
+}
@@ -3,8 +3,10 @@ use std::error::Error;
 
 mod string;
 mod basic;
+mod bitstream;
 pub use self::string::*;
 pub use self::basic::*;
+pub use self::bitstream::*;
 
 use super::FileVisitError;
 
@@ -21,6 +23,10 @@ pub enum DisassembleError {
     Utf8Error(std::string::FromUtf8Error),
     InvalidMarkerInChunk(Vec<u8>),
     FileWalkError(FileVisitError),
+    UnknownOpCode(u8),
+    TruncatedInstruction(usize),
+    InvalidOperand(u8),
+    InvalidJumpTarget(isize),
 }
 
 impl fmt::Display for DisassembleError {
@@ -31,6 +37,10 @@ impl fmt::Display for DisassembleError {
             DisassembleError::Utf8Error(utf) => write!(f, "UTF-8 error: {}", utf),
             DisassembleError::InvalidMarkerInChunk(marker) => write!(f, "invalid marker: [{}, {}]", marker[0], marker[1]),
             DisassembleError::FileWalkError(fw) => write!(f, "file walk error: {}", fw),
+            DisassembleError::UnknownOpCode(code) => write!(f, "unknown opcode: {:#X}", code),
+            DisassembleError::TruncatedInstruction(len) => write!(f, "instruction truncated with {} bytes remaining", len),
+            DisassembleError::InvalidOperand(code) => write!(f, "invalid operand for opcode {:#X}", code),
+            DisassembleError::InvalidJumpTarget(target) => write!(f, "jump to byte {:#X} does not land on an instruction", target),
         }
     }
 }
@@ -1,7 +1,7 @@
 use log::debug;
 
 use crate::bytecode::{
-    CHUNK_HEADER, ThetaConstant, ThetaFileVisitor,
+    CHUNK_HEADER, ThetaConstant, ThetaFileVisitor, OpCode,
 };
 
 use super::{DisassembleError, Disassembler};
@@ -18,10 +18,11 @@ impl StringDisassembler {
     }
 
     fn disassemble_chunk(&mut self, chunk: &[u8]) -> Result<(usize, String), DisassembleError> {
-        let mut offset = 18;
+        // skip the chunk header and size
+        let mut offset = 16;
         let mut readout = String::new();
 
-        println!("chunk: {:?}", chunk);
+        debug!("chunk: {:?}", chunk);
 
         // assert chunk header
         assert!(chunk[0..8] == CHUNK_HEADER);
@@ -30,137 +31,16 @@ impl StringDisassembler {
 
         while offset < chunk.len() {
             // read into chunk
-            match chunk[offset] {
-                0x0 => {
-                    readout.push_str("Op: Return Void (0x0)\r\n");
-                    offset += 1
-                },
-                0xF0 => {
-                    readout.push_str("Op: Return (0xF0)\r\n");
-                    offset += 1
-                }
-                0x1 => {
-                    readout.push_str(&format!(
-                        "Op: Constant (0x1) with offset: {}\r\n",
-                        &chunk[offset + 1]
-                    ));
-                    offset += 2
-                }
-                0x2 => {
-                    readout.push_str(&format!("Op: Push (0x2) with offset: {:?}\r\n",
-                        &chunk[offset + 1..offset + 1 + std::mem::size_of::<usize>()]
-                    ));
-                    offset += 1 + std::mem::size_of::<usize>()
-                }
-                0x3 => {
-                    readout.push_str("Op: Pop (0x3)\r\n");
-                    offset += 1
-                }
-                0x4 => {
-                    readout.push_str("Op: Add (0x4)\r\n");
-                    offset += 1
-                }
-                0x5 => {
-                    readout.push_str("Op: Sub (0x5)\r\n");
-                    offset += 1
-                }
-                0x6 => {
-                    readout.push_str("Op: Mul (0x6)\r\n");
-                    offset += 1
-                }
-                0x7 => {
-                    readout.push_str("Op: Div (0x7)\r\n");
-                    offset += 1
-                }
-                0x8 => {
-                    readout.push_str("Op: Neg (0x8)\r\n");
-                    offset += 1
-                }
-                0x9 => {
-                    readout.push_str("Op: Eq (0x9)\r\n");
-                    offset += 1
-                }
-                0xA => {
-                    readout.push_str("Op: GT (0xA)\r\n");
-                    offset += 1
+            match OpCode::decode(&chunk[offset..]) {
+                Ok((op, size)) => {
+                    readout.push_str(&format!("Op: {} ({:#X})\r\n", op.human_readable(), op.as_hexcode()));
+                    offset += size
                 },
-                0xA1 => {
-                    readout.push_str("Op: GTE (0xA1)\r\n");
-                    offset += 1
-                }
-                0xB => {
-                    readout.push_str("Op: LT (0xB)\r\n");
-                    offset += 1
-                },
-                0xB1 => {
-                    readout.push_str("Op: LTE (0xB1)\r\n");
-                    offset += 1
-                }
-                0xC0 => {
-                    readout.push_str(&format!(
-                        "Op: Def Global (0x1) with offset: {}\r\n",
-                        &chunk[offset + 1]
-                    ));
-                    offset += 2
-                }
-                0xC1 => {
-                    readout.push_str(&format!(
-                        "Op: Get Global (0x1) with offset: {}\r\n",
-                        &chunk[offset + 1]
-                    ));
-                    offset += 2
-                }
-                0xD0 => {
-                    readout.push_str(&format!(
-                        "Op: Jump Unconditional (0xD0) with offset: {}\r\n",
-                        &chunk[offset + 1]
-                    ));
-                    offset += 2
-                }
-                0xD1 => {
-                    readout.push_str(&format!(
-                        "Op: Jump If False (0xD1) with offset: {}\r\n",
-                        &chunk[offset + 1]
-                    ));
-                    offset += 2
-                }
-                0xD2 => {
-                    readout.push_str(&format!(
-                        "Op: Jump Unconditional Far (0xD2) with offset: {:?}\r\n",
-                        &chunk[offset + 1..offset + 1 + std::mem::size_of::<isize>()]
-                    ));
-                    offset += 1 + std::mem::size_of::<isize>()
-                }
-                0xD3 => {
-                    readout.push_str(&format!(
-                        "Op: Jump Far If False (0xD3) with offset: {:?}\r\n",
-                        &chunk[offset + 1..offset + 1 + std::mem::size_of::<isize>()]
-                    ));
-                    offset += 1 + std::mem::size_of::<isize>()
-                },
-                0xE0 => {
-                    readout.push_str(&format!(
-                        "Op: Call Direct (0xE0) with offset: {}\r\n",
-                        &chunk[offset + 1]
-                    ));
-                    offset += 2
-                },
-                0xFE => {
-                    readout.push_str("Op: Breakpoint (0xFE)\r\n");
-                    offset += 1
-                }
-                0xFD => {
-                    readout.push_str("Op: Noop (0xFD)\r\n");
-                    offset += 1
-                }
-                0xFF => {
-                    readout.push_str("Op: Print (0xFF)\r\n");
-                    offset += 1
-                }
-                code => {
+                Err(DisassembleError::UnknownOpCode(code)) => {
                     readout.push_str(&format!("Op: Unknown ({:#x})\r\n", code));
                     offset += 1
-                }
+                },
+                Err(e) => return Err(e),
             }
         }
 
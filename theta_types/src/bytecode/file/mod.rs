@@ -2,10 +2,13 @@ use std::{fmt, error::Error, rc::Rc};
 
 use log::debug;
 
-use crate::{bytecode::{BITSTREAM_HEADER, CONSTANT_POOL_HEADER, DOUBLE_MARKER, INT_MARKER, BOOL_MARKER, STRING_MARKER, ThetaString, FUNCTION_POOL_HEADER, FUNCTION_HEADER, ThetaCompiledFunction, ThetaFuncArg, CHUNK_HEADER}, types::TypeInformation};
+use crate::{bytecode::{BITSTREAM_HEADER, CONSTANT_POOL_HEADER, DOUBLE_MARKER, INT_MARKER, BOOL_MARKER, STRING_MARKER, ThetaString, FUNCTION_POOL_HEADER, FUNCTION_HEADER, DEBUG_INFO_MARKER, ThetaCompiledFunction, ThetaFuncArg, CHUNK_HEADER}, types::TypeInformation};
 
 use super::ThetaConstant;
 
+#[cfg(test)]
+mod tests;
+
 pub trait ThetaFileVisitor {
     fn visit_theta_file(&mut self);
     fn visit_theta_bitstream(&mut self);
@@ -21,6 +24,10 @@ pub enum FileVisitError {
     TryFromSliceError(std::array::TryFromSliceError),
     Utf8Error(std::string::FromUtf8Error),
     InvalidMarkerInChunk(Vec<u8>),
+    InvalidFunctionHeader(Vec<u8>),
+    MissingDebugInfo(String),
+    UnexpectedEnd(usize),
+    UnknownType(u8),
 }
 
 impl fmt::Display for FileVisitError {
@@ -30,6 +37,10 @@ impl fmt::Display for FileVisitError {
             FileVisitError::TryFromSliceError(tfs) => write!(f, "Could not get item from slice: {}", tfs),
             FileVisitError::Utf8Error(utf) => write!(f, "UTF-8 error: {}", utf),
             FileVisitError::InvalidMarkerInChunk(marker) => write!(f, "invalid marker: [{}, {}]", marker[0], marker[1]),
+            FileVisitError::InvalidFunctionHeader(header) => write!(f, "invalid function header {:X?}, the file may be from an older compiler", header),
+            FileVisitError::MissingDebugInfo(name) => write!(f, "function {} has no line table", name),
+            FileVisitError::UnexpectedEnd(offset) => write!(f, "function pool ended unexpectedly at byte {}", offset),
+            FileVisitError::UnknownType(ty) => write!(f, "unknown type: {:#X}", ty),
         }
     }
 }
@@ -144,15 +155,16 @@ impl ThetaFileWalker {
         for _ in 0..func_pool_size {
             debug!("Fn found");
 
-            // assert header
-            assert!(function_pool[offset..offset+4] == FUNCTION_HEADER);
+            let header = Self::read_bytes(function_pool, offset, 4)?;
+            if header != FUNCTION_HEADER {
+                return Err(FileVisitError::InvalidFunctionHeader(header.to_vec()));
+            }
             offset+=4;
 
             debug!("reading in fn name");
-            let fn_name_size: usize = usize::from_le_bytes(function_pool[offset..offset+8].try_into().expect("could not get fn name size"));
+            let fn_name_size = Self::read_usize(function_pool, offset)?;
             offset += 8;
-            // TODO: this can overflow very easily with a cleverly crafted file.
-            let fn_name = String::from_utf8(function_pool[offset..offset+fn_name_size].to_vec()).expect("could not get fn name");
+            let fn_name = String::from_utf8(Self::read_bytes(function_pool, offset, fn_name_size)?.to_vec())?;
 
             debug!("function named: {fn_name}");
 
@@ -160,60 +172,95 @@ impl ThetaFileWalker {
             debug!("reading fn args");
 
             // arity of fn
-            let fn_arity: usize = usize::from_le_bytes(function_pool[offset..offset+8].try_into().expect("could not get fn arity"));
+            let fn_arity = Self::read_usize(function_pool, offset)?;
             offset += 8;
 
-            let mut fn_args = vec![];
-            for _ in 0..fn_arity {
-                match function_pool[offset] {
-                    0x0 => fn_args.push(ThetaFuncArg::from(TypeInformation::None)),
-                    0x1 => fn_args.push(ThetaFuncArg::from(TypeInformation::Boolean)),
-                    0x2 => fn_args.push(ThetaFuncArg::from(TypeInformation::Int)),
-                    0x3 => fn_args.push(ThetaFuncArg::from(TypeInformation::Float)),
-                    0x4 => fn_args.push(ThetaFuncArg::from(TypeInformation::String)),
-                    _ => panic!("unknown ty info")
-                }
-                offset += 1;
-            }
+            // one byte per argument, so the arity is checked against the input before anything is allocated
+            let fn_args = Self::read_bytes(function_pool, offset, fn_arity)?.iter()
+                .map(|ty| Ok(ThetaFuncArg::from(Self::read_type(*ty)?)))
+                .collect::<Result<Vec<_>, FileVisitError>>()?;
+            offset += fn_arity;
 
             debug!("reading fn return type");
-            let fn_return_ty = match function_pool[offset] {
-                0x0 => TypeInformation::None,
-                0x1 => TypeInformation::Boolean,
-                0x2 => TypeInformation::Int,
-                0x3 => TypeInformation::Float,
-                0x4 => TypeInformation::String,
-                _ => panic!("unknown ty info")
-            };
+            let fn_return_ty = Self::read_type(Self::read_bytes(function_pool, offset, 1)?[0])?;
             offset += 1;
 
+            let fn_locals = Self::read_usize(function_pool, offset)?;
+            offset += 8;
+
             debug!("reading fn bitstream");
-            let (new_off, chunk_code) = self.walk_chunk(&function_pool[offset..])?;
+            let (new_off, chunk_code) = self.walk_chunk(function_pool, offset)?;
+
+            // skip past the chunk header, the chunk size and the instructions
+            offset += 16 + new_off;
+
+            debug!("reading fn line table");
+            if Self::read_bytes(function_pool, offset, DEBUG_INFO_MARKER.len())? != DEBUG_INFO_MARKER {
+                return Err(FileVisitError::MissingDebugInfo(fn_name));
+            }
+            offset += DEBUG_INFO_MARKER.len();
+
+            let line_count = Self::read_usize(function_pool, offset)?;
+            offset += 8;
+
+            // check the whole table is there before trusting the count
+            let table_size = line_count.checked_mul(16).ok_or(FileVisitError::UnexpectedEnd(offset))?;
+            let table = Self::read_bytes(function_pool, offset, table_size)?;
+            let fn_lines = table.as_chunks::<16>().0.iter()
+                .map(|entry| Ok((Self::read_usize(entry, 0)?, Self::read_usize(entry, 8)?)))
+                .collect::<Result<Vec<_>, FileVisitError>>()?;
+            offset += table_size;
 
             visitor.visit_theta_function(ThetaCompiledFunction {
                 args: fn_args,
                 chunk: chunk_code,
                 name: ThetaString::new(fn_name),
                 return_ty: fn_return_ty,
+                locals: fn_locals,
+                lines: fn_lines,
             });
-
-            offset = new_off;
         }
 
         Ok(offset)
 
     }
 
-    fn walk_chunk(&mut self, chunk: &[u8]) -> Result<(usize, Rc<Vec<u8>>), FileVisitError> {
+    fn walk_chunk(&mut self, input: &[u8], offset: usize) -> Result<(usize, Rc<Vec<u8>>), FileVisitError> {
         debug!("-- BEGIN CHUNK --");
 
-        assert!(chunk[0..8] == CHUNK_HEADER);
-        let chunk_size: usize = usize::from_le_bytes(chunk[8..16].try_into().expect("could not get chunk size"));
+        let header = Self::read_bytes(input, offset, 8)?;
+        if header != CHUNK_HEADER {
+            return Err(FileVisitError::InvalidMarkerInChunk(header.to_vec()));
+        }
+        let chunk_size = Self::read_usize(input, offset + 8)?;
 
         debug!("chunk size: {chunk_size}");
         
         // TODO: scan for 'illegal' bytecodes and screen them out
 
-        Ok((chunk_size, Rc::new(chunk[0..=15+chunk_size].to_vec())))
+        let code = Self::read_bytes(input, offset, chunk_size.checked_add(16).ok_or(FileVisitError::UnexpectedEnd(offset + 8))?)?;
+        Ok((chunk_size, Rc::new(code.to_vec())))
+    }
+
+    /// Reads `len` bytes at `offset`, failing instead of panicking when the input is too short.
+    fn read_bytes(input: &[u8], offset: usize, len: usize) -> Result<&[u8], FileVisitError> {
+        offset.checked_add(len)
+            .and_then(|end| input.get(offset..end))
+            .ok_or(FileVisitError::UnexpectedEnd(offset))
+    }
+
+    fn read_usize(input: &[u8], offset: usize) -> Result<usize, FileVisitError> {
+        Ok(usize::from_le_bytes(Self::read_bytes(input, offset, 8)?.try_into()?))
+    }
+
+    fn read_type(ty: u8) -> Result<TypeInformation, FileVisitError> {
+        match ty {
+            0x0 => Ok(TypeInformation::None),
+            0x1 => Ok(TypeInformation::Boolean),
+            0x2 => Ok(TypeInformation::Int),
+            0x3 => Ok(TypeInformation::Float),
+            0x4 => Ok(TypeInformation::String),
+            _ => Err(FileVisitError::UnknownType(ty)),
+        }
     }
 }
\ No newline at end of file
@@ -0,0 +1,77 @@
+use crate::{bytecode::{OpCode, ThetaConstant, ThetaBitstream, ThetaFunction, ThetaString, BasicAssembler, Assembler, BitstreamDisassembler, Disassembler, DisassembleError, FUNCTION_HEADER}, build_chunk, types::TypeInformation};
+
+use super::FileVisitError;
+
+fn assembled() -> Vec<u8> {
+    let function = ThetaFunction {
+        args: vec![],
+        chunk: build_chunk!(OpCode::Constant { offset: 0 }, OpCode::Return),
+        name: ThetaString::new(String::from("main")),
+        return_ty: TypeInformation::Int,
+        locals: 0,
+    };
+
+    let mut bytes = Vec::new();
+    BasicAssembler::new(&mut bytes).assemble_bitstream(ThetaBitstream::new_filled(vec![ThetaConstant::Int(1)], vec![function])).expect("failed to assemble");
+    bytes
+}
+
+fn walk_error(bytes: &[u8]) -> FileVisitError {
+    match BitstreamDisassembler::new().disassemble(&bytes) {
+        Err(DisassembleError::FileWalkError(e)) => e,
+        other => panic!("expected a file walk error, got {:?}", other.map(|bs| bs.functions().len())),
+    }
+}
+
+#[test]
+fn walker_rejects_old_function_headers() {
+    let mut bytes = assembled();
+    let header = bytes.windows(4).position(|w| w == FUNCTION_HEADER).expect("no function header");
+    bytes[header + 3] = 0x44;
+
+    assert!(matches!(walk_error(&bytes), FileVisitError::InvalidFunctionHeader(_)));
+}
+
+#[test]
+fn walker_reports_truncated_line_tables() {
+    let mut bytes = assembled();
+    bytes.truncate(bytes.len() - 4);
+    assert!(matches!(walk_error(&bytes), FileVisitError::UnexpectedEnd(_)));
+
+    // the line count claims more entries than the file holds
+    let mut bytes = assembled();
+    let count = bytes.len() - 8;
+    bytes[count..].copy_from_slice(&usize::to_le_bytes(3));
+    assert!(matches!(walk_error(&bytes), FileVisitError::UnexpectedEnd(_)));
+
+    let mut bytes = assembled();
+    bytes[count..].copy_from_slice(&usize::to_le_bytes(usize::MAX));
+    assert!(matches!(walk_error(&bytes), FileVisitError::UnexpectedEnd(_)));
+}
+
+#[test]
+fn walker_requires_debug_info() {
+    let mut bytes = assembled();
+    let marker = bytes.len() - 10;
+    bytes[marker] = 0;
+
+    assert!(matches!(walk_error(&bytes), FileVisitError::MissingDebugInfo(name) if name == "main"));
+}
+
+#[test]
+fn walker_reports_malformed_function_records() {
+    let header = assembled().windows(4).position(|w| w == FUNCTION_HEADER).expect("no function header");
+    let (name_size, return_ty, chunk) = (header + 4, header + 24, header + 33);
+
+    let mut bytes = assembled();
+    bytes[name_size..name_size + 8].copy_from_slice(&usize::to_le_bytes(usize::MAX));
+    assert!(matches!(walk_error(&bytes), FileVisitError::UnexpectedEnd(_)));
+
+    let mut bytes = assembled();
+    bytes[return_ty] = 0x9;
+    assert!(matches!(walk_error(&bytes), FileVisitError::UnknownType(0x9)));
+
+    let mut bytes = assembled();
+    bytes[chunk] = 0;
+    assert!(matches!(walk_error(&bytes), FileVisitError::InvalidMarkerInChunk(_)));
+}
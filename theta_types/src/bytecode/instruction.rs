@@ -1,152 +1,109 @@
+use theta_macros::isa;
+
+use super::{AssembleError, DisassembleError};
+
+#[cfg(test)]
+mod tests;
+
 // convert from OpCode representation
 // to bytes via an Assembler
 // since in Rust we can represent OpCode sequences using an enumeration
 // rather than just a simple u8 seq in a chunk
+//
+// this enum is the single definition of the instruction set: the byte value, operand layout,
+// description and encoding of every instruction are generated from it by `isa`.
+// operands without a `wire` type are stored on disk as their own type.
+#[isa(decode_error = DisassembleError, encode_error = AssembleError)]
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum OpCode {
+    #[op(0x0, "Return void")]
     ReturnVoid,
+    #[op(0xF0, "Return")]
     Return,
-    Constant { offset: usize },
+    #[op(0x1, "Constant with offset {offset:#X}")]
+    Constant { #[wire(u8)] #[constant] offset: usize },
+    #[op(0x2, "Push with size {size:#X}")]
     Push { size: usize },
+    #[op(0x3, "Pop")]
     Pop,
 
+    #[op(0x4, "Add")]
     Add,
+    #[op(0x5, "Subtract")]
     Subtract,
+    #[op(0x6, "Multiply")]
     Multiply,
+    #[op(0x7, "Divide")]
     Divide,
+    #[op(0x8, "Negate")]
     Negate,
+    #[op(0x9, "Equal")]
     Equal,
+    #[op(0xA, "Greater Than")]
     GreaterThan,
+    #[op(0xA1, "Greater Than Or Equal To")]
     GreaterEqual,
+    #[op(0xB, "Less Than")]
     LessThan,
+    #[op(0xB1, "Less Than Or Equal To")]
     LessEqual,
 
+    // converts the value on top of the stack to the string it prints as
+    #[op(0x20, "Convert to string")]
+    Stringify,
+    #[op(0x21, "Convert int to float")]
+    IntToFloat,
+    // truncates toward zero, saturating at the bounds of an int
+    #[op(0x22, "Convert float to int")]
+    FloatToInt,
+    #[op(0x23, "Convert bool to int")]
+    BoolToInt,
+    // the parsing conversions fail at runtime when the string is not a value of the type
+    #[op(0x24, "Parse int")]
+    ParseInt,
+    #[op(0x25, "Parse float")]
+    ParseFloat,
+    #[op(0x26, "Parse bool")]
+    ParseBool,
+
+    #[op(0xD0, "Jump (local) unconditional with offset {offset:#X}", jump)]
     JumpLocal { offset: i8 },
+    #[op(0xD1, "Jump (local) if false with offset {offset:#X}", jump)]
     JumpLocalIfFalse { offset: i8 },
 
+    #[op(0xD2, "Jump (far) unconditional with offset {offset:#X}", jump)]
     JumpFar { offset: isize },
+    #[op(0xD3, "Jump (far) if false with offset {offset:#X}", jump)]
     JumpFarIfFalse { offset: isize },
 
-    DefineGlobal { offset: usize },
-    GetGlobal { offset: usize },
+    #[op(0xC0, "Define global variable with offset {offset:#X}")]
+    DefineGlobal { #[wire(u8)] #[constant] offset: usize },
+    #[op(0xC1, "Retrieve global variable with offset {offset:#X}")]
+    GetGlobal { #[wire(u8)] #[constant] offset: usize },
 
-    DefineLocal { offset: usize },
-    GetLocal { offset: usize },
+    #[op(0xC2, "Define local variable with offset {offset:#X}")]
+    DefineLocal { #[wire(u8)] offset: usize },
+    #[op(0xC3, "Get local variable with offset {offset:#X}")]
+    GetLocal { #[wire(u8)] offset: usize },
 
-    CallDirect { name_offset: usize },
+    #[op(0xE0, "Call function directly with constant name {name_offset:#X}")]
+    CallDirect { #[wire(u8)] #[constant] name_offset: usize },
+    // a call in tail position. the callee replaces the current frame and returns straight to its caller.
+    #[op(0xE1, "Tail call function directly with constant name {name_offset:#X}")]
+    TailCallDirect { #[wire(u8)] #[constant] name_offset: usize },
 
     // DEBUG BYTECODES
 
+    #[op(0xFE, "Breakpoint")]
     Breakpoint,
+    #[op(0xFD, "Noop")]
     Noop,
+    #[op(0xFF, "Debug print")]
     DebugPrint,
 }
 
 impl OpCode {
-    /// The size of the OpCode in bytes when assembled to disk, in bytes
-    pub fn size(&self) -> usize {
-        match self {
-            OpCode::ReturnVoid => 1,
-            OpCode::Constant { offset: _ } => 2,
-            OpCode::Push { size: _ } => 1 + std::mem::size_of::<usize>(),
-            OpCode::Pop => 1,
-            OpCode::Add => 1,
-            OpCode::Subtract => 1,
-            OpCode::Multiply => 1,
-            OpCode::Divide => 1,
-            OpCode::Negate => 1,
-            OpCode::Equal => 1,
-            OpCode::GreaterThan => 1,
-            OpCode::LessThan => 1,
-            OpCode::JumpLocal { offset: _ } => 2,
-            OpCode::JumpLocalIfFalse { offset: _ } => 2,
-            OpCode::DefineGlobal { offset: _ } => 2,
-            OpCode::GetGlobal { offset: _ } => 2,
-            OpCode::DefineLocal { offset: _ } => 2,
-            OpCode::GetLocal { offset: _ } => 2,
-            OpCode::DebugPrint => 1,
-            OpCode::JumpFar { offset: _ } => 1 + std::mem::size_of::<isize>(),
-            OpCode::JumpFarIfFalse { offset: _ } => 1 + std::mem::size_of::<isize>(),
-            OpCode::Noop => 1,
-            OpCode::CallDirect { name_offset: _ } => 2,
-            OpCode::Return => 1,
-            OpCode::GreaterEqual => 1,
-            OpCode::LessEqual => 1,
-            OpCode::Breakpoint => 1,
-        }
-    }
-
-    pub fn human_readable(&self) -> String {
-        match self {
-            OpCode::ReturnVoid => "Return void".to_string(),
-            OpCode::Constant { offset } => format!("Constant with offset {offset:#X}"),
-            OpCode::Push { size } => format!("Push with size {size:#X}"),
-            OpCode::Pop => "Pop".to_string(),
-            OpCode::Add => "Add".to_string(),
-            OpCode::Subtract => "Subtract".to_string(),
-            OpCode::Multiply => "Multiply".to_string(),
-            OpCode::Divide => "Divide".to_string(),
-            OpCode::Negate => "Negate".to_string(),
-            OpCode::Equal => "Equal".to_string(),
-            OpCode::GreaterThan => "Greater Than".to_string(),
-            OpCode::LessThan => "Less Than".to_string(),
-            OpCode::JumpLocal { offset } => format!("Jump (local) unconditional with offset {offset:#X}"),
-            OpCode::JumpLocalIfFalse { offset } => format!("Jump (local) if false with offset {offset:#X}"),
-            OpCode::JumpFar { offset } => format!("Jump (far) unconditional with offset {offset:#X}"),
-            OpCode::JumpFarIfFalse { offset } => format!("Jump (far) if false with offset {offset:#X}"),
-            OpCode::DefineGlobal { offset } => format!("Define global variable with offset {offset:#X}"),
-            OpCode::GetGlobal { offset } => format!("Retrieve global variable with offset {offset:#X}"),
-            OpCode::DefineLocal { offset } => format!("Define local variable with offset {offset:#X}"),
-            OpCode::GetLocal { offset } => format!("Get local variable with offset {offset:#X}"),
-            OpCode::DebugPrint => "Debug print".to_string(),
-            OpCode::Noop => "Noop".to_string(),
-            OpCode::CallDirect { name_offset } => format!("Call function directly with constant name {name_offset:#X}"),
-            OpCode::Return => "Return".to_string(),
-            OpCode::GreaterEqual => "Greater Than Or Equal To".to_string(),
-            OpCode::LessEqual => "Less Than Or Equal To".to_string(),
-            OpCode::Breakpoint => "Breakpoint".to_string(),
-        }
-    }
-
-    pub fn as_hexcode(&self) -> usize {
-        match self {
-            OpCode::ReturnVoid => 0x0,
-            OpCode::Return => 0xF0,
-            OpCode::Constant { offset: _ } => 0x1,
-            OpCode::Push { size: _ } => 0x2,
-            OpCode::Pop => 0x3,
-            OpCode::Add => 0x4,
-            OpCode::Subtract => 0x5,
-            OpCode::Multiply => 0x6,
-            OpCode::Divide => 0x7,
-            OpCode::Negate => 0x8,
-            OpCode::Equal => 0x9,
-            OpCode::GreaterThan => 0xA,
-            OpCode::LessThan => 0xB,
-            OpCode::JumpLocal { offset: _ } => 0xD0,
-            OpCode::JumpLocalIfFalse { offset: _ } => 0xD1,
-            OpCode::JumpFar { offset: _ } => 0xD2,
-            OpCode::JumpFarIfFalse { offset: _ } => 0xD3,
-            OpCode::DefineGlobal { offset: _ } => 0xC0,
-            OpCode::GetGlobal { offset: _ } => 0xC1,
-            OpCode::DefineLocal { offset: _ } => 0xC2,
-            OpCode::GetLocal { offset: _ } => 0xC3,
-            OpCode::CallDirect { name_offset: _ } => 0xE0,
-            OpCode::DebugPrint => 0xFF,
-            OpCode::Noop => 0xFD,
-            OpCode::GreaterEqual => 0xA1,
-            OpCode::LessEqual => 0xB1,
-            OpCode::Breakpoint => 0xFE,
-        }
-    }
-
     pub fn relocate_constants(self, new_base: usize) -> OpCode {
-        match self {
-            OpCode::Constant { offset } => OpCode::Constant { offset: offset + new_base },
-            OpCode::DefineGlobal { offset } => OpCode::DefineGlobal { offset: offset + new_base },
-            OpCode::GetGlobal { offset } => OpCode::GetGlobal { offset: offset + new_base },
-            OpCode::CallDirect { name_offset } => OpCode::CallDirect { name_offset: name_offset + new_base },
-            _ => self,
-        }
+        self.remap_constants(|offset| offset + new_base)
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,35 @@
+use crate::bytecode::{AssembleError, DisassembleError};
+
+use super::OpCode;
+
+#[test]
+fn opcodes_round_trip_through_encoding() {
+    let ops = vec![
+        OpCode::ReturnVoid, OpCode::Return, OpCode::Constant { offset: 0xFF }, OpCode::Push { size: 0x1234 }, OpCode::Pop,
+        OpCode::Add, OpCode::Subtract, OpCode::Multiply, OpCode::Divide, OpCode::Negate,
+        OpCode::Equal, OpCode::GreaterThan, OpCode::GreaterEqual, OpCode::LessThan, OpCode::LessEqual,
+        OpCode::Stringify, OpCode::IntToFloat, OpCode::FloatToInt, OpCode::BoolToInt, OpCode::ParseInt, OpCode::ParseFloat, OpCode::ParseBool,
+        OpCode::JumpLocal { offset: -3 }, OpCode::JumpLocalIfFalse { offset: 12 }, OpCode::JumpFar { offset: -400 }, OpCode::JumpFarIfFalse { offset: 400 },
+        OpCode::DefineGlobal { offset: 1 }, OpCode::GetGlobal { offset: 2 }, OpCode::DefineLocal { offset: 3 }, OpCode::GetLocal { offset: 4 },
+        OpCode::CallDirect { name_offset: 5 }, OpCode::Breakpoint, OpCode::Noop, OpCode::DebugPrint,
+    ];
+
+    for op in ops {
+        let bytes = op.encode().expect("failed to encode");
+        assert_eq!(bytes.len(), op.size());
+        assert_eq!(bytes[0] as usize, op.as_hexcode());
+        assert_eq!(OpCode::decode(&bytes).expect("failed to decode"), (op, op.size()));
+    }
+}
+
+#[test]
+fn opcode_operand_must_fit_wire_type() {
+    // constant offsets are stored as a single byte
+    assert!(matches!(OpCode::Constant { offset: 0x100 }.encode(), Err(AssembleError::OperandOutOfRange(_))));
+}
+
+#[test]
+fn opcode_decode_rejects_bad_input() {
+    assert!(matches!(OpCode::decode(&[0xEE]), Err(DisassembleError::UnknownOpCode(0xEE))));
+    assert!(matches!(OpCode::decode(&[0xD2, 0x1]), Err(DisassembleError::TruncatedInstruction(2))));
+}
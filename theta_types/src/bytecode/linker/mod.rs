@@ -0,0 +1,214 @@
+use std::{fmt, error::Error, collections::{HashMap, HashSet, VecDeque}};
+
+use super::{ThetaBitstream, ThetaConstant, ThetaFunction, OpCode, Chunk};
+
+#[cfg(test)]
+mod tests;
+
+#[derive(Debug, Clone, Default)]
+pub struct LinkOptions {
+    /// Drop every function that cannot be reached from the entry points.
+    pub strip_unused: bool,
+    /// Functions that are always kept when stripping. Defaults to `main` when empty.
+    pub entry_points: Vec<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum LinkError {
+    DuplicateFunction { name: String, first_unit: usize, second_unit: usize },
+    UnresolvedFunction { name: String, caller: String },
+    UnknownEntryPoint(String),
+    /// A function refers to a constant outside of its unit's constant pool.
+    InvalidConstant { unit: usize, function: String, offset: usize },
+}
+
+impl fmt::Display for LinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LinkError::DuplicateFunction { name, first_unit, second_unit } => write!(f, "function {} is defined in both unit {} and unit {}", name, first_unit, second_unit),
+            LinkError::UnresolvedFunction { name, caller } => write!(f, "unresolved function {} called from {}", name, caller),
+            LinkError::UnknownEntryPoint(name) => write!(f, "entry point {} is not defined", name),
+            LinkError::InvalidConstant { unit, function, offset } => write!(f, "function {} in unit {} refers to missing constant {:#X}", function, unit, offset),
+        }
+    }
+}
+
+impl Error for LinkError {}
+
+// ThetaConstant can't be hashed because of f64; doubles are compared bitwise instead.
+#[derive(Debug, PartialEq, Eq, Hash)]
+enum ConstantKey {
+    Double(u64),
+    Int(i64),
+    Bool(bool),
+    Str(String),
+}
+
+impl From<&ThetaConstant> for ConstantKey {
+    fn from(value: &ThetaConstant) -> Self {
+        match value {
+            ThetaConstant::Double(d) => ConstantKey::Double(d.to_bits()),
+            ThetaConstant::Int(i) => ConstantKey::Int(*i),
+            ThetaConstant::Bool(b) => ConstantKey::Bool(*b),
+            ThetaConstant::Str(s) => ConstantKey::Str(s.clone()),
+        }
+    }
+}
+
+/// Combines several compiled bitstreams into one.
+///
+/// Unlike `ThetaBitstream::merge`, linking checks that every function is defined exactly once
+/// and that every direct call resolves to a function in one of the units. Identical constants
+/// are stored once in the output's constant pool.
+pub struct Linker {
+    options: LinkOptions,
+    units: Vec<ThetaBitstream>,
+}
+
+impl Linker {
+    pub fn new(options: LinkOptions) -> Linker {
+        Linker { options, units: Vec::new() }
+    }
+
+    pub fn add_unit(&mut self, unit: ThetaBitstream) {
+        self.units.push(unit);
+    }
+
+    pub fn link(self) -> Result<ThetaBitstream, Vec<LinkError>> {
+        let mut errors = Vec::new();
+
+        // name -> (unit, function index in unit)
+        let mut definitions: HashMap<String, (usize, usize)> = HashMap::new();
+        for (unit_idx, unit) in self.units.iter().enumerate() {
+            for (fn_idx, func) in unit.functions().iter().enumerate() {
+                match definitions.get(func.name.as_str()) {
+                    Some((first_unit, _)) => errors.push(LinkError::DuplicateFunction { name: func.name.to_string(), first_unit: *first_unit, second_unit: unit_idx }),
+                    None => { definitions.insert(func.name.to_string(), (unit_idx, fn_idx)); },
+                }
+            }
+        }
+
+        for (unit_idx, unit) in self.units.iter().enumerate() {
+            for func in unit.functions() {
+                for offset in func.chunk.instructions().iter().filter_map(constant_offset) {
+                    if offset >= unit.constants().len() {
+                        errors.push(LinkError::InvalidConstant { unit: unit_idx, function: func.name.to_string(), offset });
+                    }
+                }
+
+                for callee in direct_calls(unit, func) {
+                    if !definitions.contains_key(callee) {
+                        errors.push(LinkError::UnresolvedFunction { name: callee.clone(), caller: func.name.to_string() });
+                    }
+                }
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        let kept = if self.options.strip_unused {
+            let entry_points = if self.options.entry_points.is_empty() {
+                vec![String::from("main")]
+            } else {
+                self.options.entry_points.clone()
+            };
+
+            let mut reachable = HashSet::new();
+            let mut queue = VecDeque::new();
+            for entry in entry_points {
+                match definitions.get(&entry) {
+                    Some(def) => queue.push_back(*def),
+                    None => errors.push(LinkError::UnknownEntryPoint(entry)),
+                }
+            }
+
+            while let Some((unit_idx, fn_idx)) = queue.pop_front() {
+                if !reachable.insert((unit_idx, fn_idx)) {
+                    continue;
+                }
+
+                let unit = &self.units[unit_idx];
+                for name in referenced_names(unit, &unit.functions()[fn_idx]) {
+                    if let Some(def) = definitions.get(name) {
+                        queue.push_back(*def);
+                    }
+                }
+            }
+
+            if !errors.is_empty() {
+                return Err(errors);
+            }
+
+            Some(reachable)
+        } else {
+            None
+        };
+
+        // only constants used by the kept functions make it into the output pool
+        let mut output = ThetaBitstream::new();
+        let mut interned: HashMap<ConstantKey, usize> = HashMap::new();
+        for (unit_idx, unit) in self.units.into_iter().enumerate() {
+            let constants = unit.constants;
+            for (fn_idx, func) in unit.functions.into_iter().enumerate() {
+                if kept.as_ref().is_some_and(|k| !k.contains(&(unit_idx, fn_idx))) {
+                    continue;
+                }
+
+                let mut remap = HashMap::new();
+                for offset in func.chunk.instructions().iter().filter_map(constant_offset) {
+                    let constant = &constants[offset];
+                    let new_offset = *interned.entry(ConstantKey::from(constant)).or_insert_with(|| {
+                        output.write_constant(constant.clone());
+                        output.constants().len() - 1
+                    });
+                    remap.insert(offset, new_offset);
+                }
+
+                let mut chunk = Chunk::new();
+                for op in func.chunk.instructions() {
+                    chunk.write_to_chunk(op.remap_constants(|offset| remap[&offset]));
+                }
+                for (idx, line) in func.chunk.line_map() {
+                    chunk.mark_line(*idx, *line);
+                }
+
+                output.write_function(ThetaFunction { chunk, ..func });
+            }
+        }
+
+        Ok(output)
+    }
+}
+
+fn constant_offset(op: &OpCode) -> Option<usize> {
+    match *op {
+        OpCode::Constant { offset } | OpCode::DefineGlobal { offset } | OpCode::GetGlobal { offset } => Some(offset),
+        OpCode::CallDirect { name_offset } | OpCode::TailCallDirect { name_offset } => Some(name_offset),
+        _ => None,
+    }
+}
+
+/// Names of the functions called directly by `func`. A direct call is a string constant pushed immediately before `CallDirect` or `TailCallDirect`.
+fn direct_calls<'a>(unit: &'a ThetaBitstream, func: &ThetaFunction) -> Vec<&'a String> {
+    func.chunk.instructions().windows(2).filter_map(|ops| match ops {
+        [OpCode::Constant { offset }, OpCode::CallDirect { name_offset: _ } | OpCode::TailCallDirect { name_offset: _ }] => match unit.constants().get(*offset) {
+            Some(ThetaConstant::Str(name)) => Some(name),
+            _ => None,
+        },
+        _ => None,
+    }).collect()
+}
+
+/// Every string constant loaded by `func`. Functions can be passed around by name,
+/// so any of these might end up being called; this is used to keep functions alive when stripping.
+fn referenced_names<'a>(unit: &'a ThetaBitstream, func: &ThetaFunction) -> Vec<&'a String> {
+    func.chunk.instructions().iter().filter_map(|op| match op {
+        OpCode::Constant { offset } => match unit.constants().get(*offset) {
+            Some(ThetaConstant::Str(name)) => Some(name),
+            _ => None,
+        },
+        _ => None,
+    }).collect()
+}
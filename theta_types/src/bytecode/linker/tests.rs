@@ -0,0 +1,76 @@
+use crate::{bytecode::{OpCode, ThetaConstant, ThetaBitstream, ThetaFunction, ThetaString, BasicAssembler, Assembler, BitstreamDisassembler, Disassembler}, build_chunk, types::TypeInformation};
+
+use super::{Linker, LinkOptions, LinkError};
+
+fn function(name: &str, chunk: crate::bytecode::Chunk) -> ThetaFunction {
+    ThetaFunction { args: vec![], chunk, name: ThetaString::new(String::from(name)), return_ty: TypeInformation::None, locals: 0 }
+}
+
+fn calling(name: &str, callee: &str) -> ThetaBitstream {
+    ThetaBitstream::new_filled(
+        vec![ThetaConstant::Int(1), ThetaConstant::Str(String::from(callee))],
+        vec![function(name, build_chunk!(OpCode::Constant { offset: 0 }, OpCode::Pop, OpCode::Constant { offset: 1 }, OpCode::CallDirect { name_offset: 0 }, OpCode::ReturnVoid))],
+    )
+}
+
+fn leaf(name: &str) -> ThetaBitstream {
+    ThetaBitstream::new_filled(
+        vec![ThetaConstant::Int(1)],
+        vec![function(name, build_chunk!(OpCode::Constant { offset: 0 }, OpCode::Return))],
+    )
+}
+
+fn link(units: Vec<ThetaBitstream>, options: LinkOptions) -> Result<ThetaBitstream, Vec<LinkError>> {
+    let mut linker = Linker::new(options);
+    for unit in units {
+        linker.add_unit(unit);
+    }
+    linker.link()
+}
+
+#[test]
+fn linker_resolves_calls_and_dedups_constants() {
+    let linked = link(vec![calling("main", "helper"), leaf("helper")], LinkOptions::default()).expect("failed to link");
+
+    assert_eq!(linked.constants(), &vec![ThetaConstant::Int(1), ThetaConstant::Str(String::from("helper"))]);
+    assert_eq!(linked.functions().len(), 2);
+    assert_eq!(linked.functions()[1].chunk.instructions(), &vec![OpCode::Constant { offset: 0 }, OpCode::Return]);
+}
+
+#[test]
+fn linker_reports_duplicate_functions() {
+    let errors = link(vec![leaf("helper"), leaf("helper")], LinkOptions::default()).expect_err("link should fail");
+    assert_eq!(errors, vec![LinkError::DuplicateFunction { name: String::from("helper"), first_unit: 0, second_unit: 1 }]);
+}
+
+#[test]
+fn linker_reports_unresolved_functions() {
+    let errors = link(vec![calling("main", "missing")], LinkOptions::default()).expect_err("link should fail");
+    assert_eq!(errors, vec![LinkError::UnresolvedFunction { name: String::from("missing"), caller: String::from("main") }]);
+}
+
+#[test]
+fn linker_strips_unused_functions() {
+    let options = LinkOptions { strip_unused: true, entry_points: vec![] };
+    let linked = link(vec![calling("main", "helper"), leaf("helper"), leaf("unused")], options).expect("failed to link");
+
+    let names: Vec<&str> = linked.functions().iter().map(|f| f.name.as_str()).collect();
+    assert_eq!(names, vec!["main", "helper"]);
+
+    let errors = link(vec![leaf("helper")], LinkOptions { strip_unused: true, entry_points: vec![String::from("start")] }).expect_err("link should fail");
+    assert_eq!(errors, vec![LinkError::UnknownEntryPoint(String::from("start"))]);
+}
+
+#[test]
+fn linker_round_trips_assembled_bitstreams() {
+    let linked = link(vec![calling("main", "helper"), leaf("helper")], LinkOptions::default()).expect("failed to link");
+    let expected: Vec<_> = linked.functions().iter().map(|f| f.chunk.instructions().clone()).collect();
+
+    let mut bytes = Vec::new();
+    BasicAssembler::new(&mut bytes).assemble_bitstream(linked).expect("failed to assemble");
+    let read = BitstreamDisassembler::new().disassemble(&bytes).expect("failed to disassemble");
+
+    let actual: Vec<_> = read.functions().iter().map(|f| f.chunk.instructions().clone()).collect();
+    assert_eq!(actual, expected);
+    assert_eq!(read.constants().len(), 2);
+}
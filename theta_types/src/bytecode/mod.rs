@@ -3,13 +3,17 @@ mod assembler;
 mod instruction;
 mod value;
 mod chunk;
+mod builder;
 mod bitstream;
 mod file;
+mod linker;
 
 pub use self::assembler::*;
 pub use self::disassembler::*;
 pub use self::instruction::*;
 pub use self::value::*;
 pub use self::chunk::*;
+pub use self::builder::*;
 pub use self::bitstream::*;
-pub use self::file::*;
\ No newline at end of file
+pub use self::file::*;
+pub use self::linker::*;
\ No newline at end of file
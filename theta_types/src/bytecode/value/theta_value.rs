@@ -1,6 +1,6 @@
 use std::rc::Rc;
 use std::ops::{Deref, Add};
-use std::fmt::Debug;
+use std::fmt::{Debug, Display};
 
 
 use crate::bytecode::Chunk;
@@ -10,12 +10,14 @@ use super::Symbol;
 
 pub const CONSTANT_POOL_HEADER: [u8; 8] = [84, 104, 101, 67, 111, 110, 115, 116];
 pub const FUNCTION_POOL_HEADER: [u8; 8] = [0xF4, 0x17, 0xC7, 0x10, 0x17, 0x90, 0x09, 0xF4];
-pub const FUNCTION_HEADER: [u8; 4] = [0x11, 0x22, 0x33, 0x44];
+// bumped whenever the layout of a function record changes, so files from older compilers are rejected
+pub const FUNCTION_HEADER: [u8; 4] = [0x11, 0x22, 0x33, 0x45];
 
 pub const DOUBLE_MARKER: &[u8] = &[0xF, 0xF];
 pub const INT_MARKER: &[u8] = &[0xA, 0xA];
 pub const BOOL_MARKER: &[u8] = &[0xB, 0xB];
 pub const STRING_MARKER: &[u8] = &[0xC, 0xC];
+pub const DEBUG_INFO_MARKER: &[u8] = &[0xD, 0xD];
 
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
 pub enum ThetaValue {
@@ -25,6 +27,20 @@ pub enum ThetaValue {
     Pointer(Rc<ThetaHeapValue>),
 }
 
+/// Values display the way a Theta program prints them, strings without their quotes.
+/// The alternate form, `{:#}`, writes them as they would appear in source instead.
+impl Display for ThetaValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            // the debug form keeps the decimal point, so a float never reads as an int
+            ThetaValue::Double(d) => write!(f, "{:?}", d),
+            ThetaValue::Int(i) => write!(f, "{}", i),
+            ThetaValue::Bool(b) => write!(f, "{}", b),
+            ThetaValue::Pointer(hv) => Display::fmt(hv.as_ref(), f),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum ThetaConstant {
     Double(f64),
@@ -83,6 +99,8 @@ pub struct ThetaFunction {
     pub chunk: Chunk,
     pub name: ThetaString,
     pub return_ty: TypeInformation,
+    /// Number of local slots the function needs after its arguments.
+    pub locals: usize,
 }
 
 #[derive(Debug, Clone)]
@@ -91,6 +109,10 @@ pub struct ThetaCompiledFunction {
     pub chunk: Rc<Vec<u8>>,
     pub name: ThetaString,
     pub return_ty: TypeInformation,
+    /// Number of local slots the function needs after its arguments.
+    pub locals: usize,
+    /// The byte offset of every instruction that starts a source line, along with the line, in order.
+    pub lines: Vec<(usize, usize)>,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -108,4 +130,22 @@ impl From<TypeInformation> for ThetaFuncArg {
 
 pub enum ThetaHeapValue {
     Str(ThetaString),
-}
\ No newline at end of file
+}
+
+impl Display for ThetaHeapValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ThetaHeapValue::Str(s) if f.alternate() => write!(f, "\"{}\"", s.as_str()),
+            ThetaHeapValue::Str(s) => write!(f, "{}", s.as_str()),
+        }
+    }
+}
+
+impl ThetaHeapValue {
+    /// The number of bytes the value occupies on the heap, including its contents.
+    pub fn size(&self) -> usize {
+        std::mem::size_of::<ThetaHeapValue>() + match self {
+            ThetaHeapValue::Str(s) => s.len(),
+        }
+    }
+}
@@ -8,6 +8,7 @@ lazy_static! {
     pub static ref IDENTIFIERS: HashMap<&'static str, TokenType> = {
         let mut hm = HashMap::new();
         hm.insert("and", TokenType::And);
+        hm.insert("as", TokenType::As);
         hm.insert("class", TokenType::Class);
         hm.insert("else", TokenType::Else);
         hm.insert("false", TokenType::False);
@@ -62,6 +63,14 @@ impl Display for Token {
     }
 }
 
+/// A piece of an interpolated string literal.
+#[derive(PartialEq, Debug, Clone)]
+pub enum StringFragment {
+    Text(String),
+    /// The tokens of an expression embedded with `${...}`, ending with `Eof`.
+    Tokens(Vec<Token>),
+}
+
 // TODO:
 // MISSING &, |, ^
 
@@ -78,10 +87,12 @@ pub enum TokenType {
 
     Identifier(String),
     Str(String),
+    /// A string literal with expressions embedded in it, split between its text and the expressions' tokens.
+    Interpolation(Vec<StringFragment>),
     Integer(i32),
     Float(f32),
 
-    And, Class, Else, False, Fun, For, If, Or,
+    And, As, Class, Else, False, Fun, For, If, Or,
     Return, Super, This, True, Let, While,
 
     Eof
@@ -130,9 +141,11 @@ impl Display for TokenType {
             TokenType::LessEqual => write!(f, "<="),
             TokenType::Identifier(id) => write!(f, "ID: {}", id),
             TokenType::Str(s) => write!(f, "String: {}", s),
+            TokenType::Interpolation(fragments) => write!(f, "Interpolated String: {} fragments", fragments.len()),
             TokenType::Integer(i) => write!(f, "Int: {}", i),
             TokenType::Float(fl) => write!(f, "Float: {},", fl),
             TokenType::And => write!(f, "&&"),
+            TokenType::As => write!(f, "as"),
             TokenType::Class => write!(f, "class"),
             TokenType::Else => write!(f, "else"),
             TokenType::False => write!(f, "false"),
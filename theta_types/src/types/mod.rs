@@ -13,13 +13,20 @@ pub enum TypeInformation {
     None,
 }
 
+impl TypeInformation {
+    /// Whether values of the type can be printed, and so converted to strings.
+    pub fn is_printable(&self) -> bool {
+        matches!(self, TypeInformation::Int | TypeInformation::String | TypeInformation::Float | TypeInformation::Boolean)
+    }
+}
+
 impl Display for TypeInformation {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             TypeInformation::Int => write!(f, "Int"),
             TypeInformation::String => write!(f, "String"),
             TypeInformation::Float => write!(f, "Float"),
-            TypeInformation::Boolean => write!(f, "Boolean"),
+            TypeInformation::Boolean => write!(f, "Bool"),
             TypeInformation::NonLiteral(s) => write!(f, "{}", s),
             TypeInformation::None => write!(f, "!"),
             TypeInformation::Function(return_ty, args) => write!(f, "Fn({args:?}) -> {return_ty}"),
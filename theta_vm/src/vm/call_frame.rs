@@ -2,18 +2,27 @@ use std::{collections::HashMap, rc::Rc};
 
 use theta_types::bytecode::{ThetaValue, ThetaCompiledBitstream};
 
+use super::Instruction;
+
 #[derive(Debug)]
 pub struct ThetaStack {
-    globals: HashMap<String, ThetaValue>,
-    // These frames should be colocated near each other to provide spatial locality.
-    // However, that's not as easy as it sounds in Rust. I think a Vec<> should provide spatial locality
-    // _within_ the Vec but I don't know the defined behavior.
+    // globals are resolved to slots when chunks are loaded. a slot is None until the global is defined.
+    globals: Vec<Option<ThetaValue>>,
+    global_slots: HashMap<String, usize>,
+    // every frame's arguments, locals and temporaries live in this one stack.
+    // a frame owns everything from its base pointer upwards, so calls never copy their arguments.
+    values: Vec<ThetaValue>,
     frames: Vec<ThetaCallFrame>,
 }
 
 impl ThetaStack {
     pub fn new() -> ThetaStack {
-        ThetaStack { globals: HashMap::new(), frames: vec![] }
+        ThetaStack { globals: vec![], global_slots: HashMap::new(), values: vec![], frames: vec![] }
+    }
+
+    /// Rebuilds a stack from its parts, e.g. when a VM is restored from a snapshot.
+    pub(super) fn from_parts(globals: Vec<Option<ThetaValue>>, global_slots: HashMap<String, usize>, values: Vec<ThetaValue>, frames: Vec<ThetaCallFrame>) -> ThetaStack {
+        ThetaStack { globals, global_slots, values, frames }
     }
 
     pub fn curr_frame(&self) -> Option<&ThetaCallFrame> {
@@ -28,80 +37,122 @@ impl ThetaStack {
         self.frames.push(sf)
     }
 
-    pub fn push_frame(&mut self, rip: usize, bitstream_ref: Rc<ThetaCompiledBitstream>, chunk_ref: Rc<Vec<u8>>, params: Vec<ThetaValue>) {
-        self.frames.push(ThetaCallFrame::new(rip, bitstream_ref, chunk_ref, params));
+    /// Pushes a frame whose `arity` arguments are already on top of the stack,
+    /// then reserves `locals` slots for the rest of its local variables.
+    pub fn push_frame(&mut self, rip: usize, function: usize, bitstream_ref: Rc<ThetaCompiledBitstream>, chunk_ref: Rc<[Instruction]>, arity: usize, locals: usize) {
+        let base = self.values.len().checked_sub(arity).expect("arguments not on stack");
+        self.frames.push(ThetaCallFrame { rip, base, function: Some(function), bitstream: bitstream_ref, chunk: chunk_ref });
+        self.alloc_framespace(locals);
     }
 
-    pub fn push_opt_frame(&mut self, rip: usize, bitstream_ref: Rc<ThetaCompiledBitstream>, chunk_ref: Rc<Vec<u8>>, params: Vec<Option<ThetaValue>>) {
-        self.frames.push(ThetaCallFrame::new_optional(rip, bitstream_ref, chunk_ref, params));
+    /// Replaces the current frame with one for a tail call whose `arity` arguments are on top of the stack.
+    /// The arguments are moved down to the current frame's base and the return address is kept.
+    pub fn replace_frame(&mut self, function: usize, bitstream_ref: Rc<ThetaCompiledBitstream>, chunk_ref: Rc<[Instruction]>, arity: usize, locals: usize) {
+        let args = self.values.len().checked_sub(arity).expect("arguments not on stack");
+        let frame = self.frames.last_mut().expect("all frames gone");
+        self.values.drain(frame.base..args);
+        frame.function = Some(function);
+        frame.bitstream = bitstream_ref;
+        frame.chunk = chunk_ref;
+        self.alloc_framespace(locals);
     }
 
+    /// Pops the current frame, discarding everything it left on the stack.
     pub fn pop_frame(&mut self) -> Option<ThetaCallFrame> {
-        self.frames.pop()
+        let frame = self.frames.pop()?;
+        self.values.truncate(frame.base);
+        Some(frame)
     }
 
-    pub fn alloc_framespace(&mut self, size: usize) {
-        match self.curr_frame_mut() {
-            // We set new values in frame to be none when framespace is allocated.
-            Some(frame) => frame.locals.resize(frame.locals.len() + size, None),
-            None => panic!()
+    /// Pops every frame and everything they left on the stack, e.g. after a runtime error.
+    pub fn unwind(&mut self) {
+        if let Some(outermost) = self.frames.first() {
+            self.values.truncate(outermost.base);
         }
+        self.frames.clear();
+    }
+
+    /// Every frame on the call stack, outermost first.
+    pub fn frames(&self) -> &[ThetaCallFrame] {
+        &self.frames
+    }
+
+    pub fn depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn alloc_framespace(&mut self, size: usize) {
+        // locals are always defined before they are read, so the value they start with is never observed.
+        self.values.resize(self.values.len() + size, ThetaValue::Int(0));
     }
 
     pub fn push(&mut self, loc: ThetaValue) {
-        // TODO: because this is called when a constant is loaded we need
-        // to track all constants to ensure enough stack space is allocated
-        match self.curr_frame_mut() {
-            Some(frame) => frame.locals.push(Some(loc)),
-            None => panic!("all frames gone"),
-        }
+        self.values.push(loc)
     }
 
     pub fn pop(&mut self) -> Option<ThetaValue> {
-        match self.curr_frame_mut() {
-            Some(frame) => ThetaStack::flatten_stackval(frame.locals.pop()),
-            None => panic!("all frames gone"),
-        }
+        self.values.pop()
     }
 
     pub fn peek(&self) -> Option<&ThetaValue> {
-        match self.curr_frame() {
-            Some(frame) => ThetaStack::flatten_refstackval(frame.locals.last()),
-            None => panic!("all frames gone")
-        }
+        self.values.last()
     }
 
     pub fn set_local(&mut self, li: usize) {
-        // TODO: we should have an instruction as a prefix to allocate stack space
-        // this can set a local into a slot with no local in it. we need to allocate empty space in the vec on the fly
         let tv = self.peek().expect("value not on stack").clone();
-        match self.curr_frame_mut() {
-            Some(frame) => frame.locals[li] = Some(tv),
-            None => panic!("all frames gone"),
-        }
+        let base = self.curr_frame().expect("all frames gone").base;
+        self.values[base + li] = tv;
     }
 
     pub fn get_local(&self, li: usize) -> Option<&ThetaValue> {
-        match self.curr_frame() {
-            Some(frame) => ThetaStack::flatten_refstackval(frame.locals.get(li)),
-            None => panic!("all frames gone")
+        self.values.get(self.curr_frame().expect("all frames gone").base + li)
+    }
+
+    /// Every value on the stack, across all frames.
+    pub fn values(&self) -> &[ThetaValue] {
+        &self.values
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Every defined global by name. Intended for debugging and embedding; the VM itself only uses slots.
+    pub fn globals(&self) -> HashMap<&str, &ThetaValue> {
+        self.global_slots.iter().filter_map(|(name, slot)| Some((name.as_str(), self.globals[*slot].as_ref()?))).collect()
+    }
+
+    /// Returns the slot for a global, allocating a new (undefined) slot if the name has not been seen before.
+    pub fn global_slot(&mut self, name: &str) -> usize {
+        match self.global_slots.get(name) {
+            Some(slot) => *slot,
+            None => {
+                self.globals.push(None);
+                self.global_slots.insert(name.to_string(), self.globals.len() - 1);
+                self.globals.len() - 1
+            },
         }
     }
 
-    pub fn globals(&self) -> &HashMap<String, ThetaValue> {
-        &self.globals
+    pub fn global_slots(&self) -> &HashMap<String, usize> {
+        &self.global_slots
     }
 
-    pub fn globals_mut(&mut self) -> &mut HashMap<String, ThetaValue> {
-        &mut self.globals
+    /// Every global slot, including the ones that are not defined yet.
+    pub(super) fn global_values(&self) -> &[Option<ThetaValue>] {
+        &self.globals
     }
 
-    fn flatten_stackval(val: Option<Option<ThetaValue>>) -> Option<ThetaValue> {
-        val.flatten()
+    pub fn get_global(&self, slot: usize) -> Option<&ThetaValue> {
+        self.globals.get(slot)?.as_ref()
     }
 
-    fn flatten_refstackval(val: Option<&Option<ThetaValue>>) -> Option<&ThetaValue> {
-        val.unwrap_or(&None).as_ref()
+    pub fn set_global(&mut self, slot: usize, value: ThetaValue) {
+        self.globals[slot] = Some(value);
     }
 
     pub fn set_bitstream(&mut self, bitstream_ref: Rc<ThetaCompiledBitstream>) {
@@ -110,22 +161,14 @@ impl ThetaStack {
 }
 
 #[derive(Debug)]
-#[allow(dead_code)]
 pub struct ThetaCallFrame {
+    // index of the instruction to return to in the calling frame
     pub rip: usize,
-    // locals are uninitalized when created. we might need to make this an option and panic on fail
-    pub locals: Vec<Option<ThetaValue>>,
+    // index of the frame's first argument (or local) in the value stack
+    pub base: usize,
+    // index of the function the frame is running, or None for top level chunks
+    pub function: Option<usize>,
     pub bitstream: Rc<ThetaCompiledBitstream>,
     // chunk that we are currently running on
-    pub chunk: Rc<Vec<u8>>,
+    pub chunk: Rc<[Instruction]>,
 }
-
-impl ThetaCallFrame {
-    pub fn new(rip: usize, bitstream_ref: Rc<ThetaCompiledBitstream>, chunk_ref: Rc<Vec<u8>>, params: Vec<ThetaValue>) -> ThetaCallFrame {
-        ThetaCallFrame { rip, locals: params.into_iter().map(Some).collect(), bitstream: bitstream_ref, chunk: chunk_ref }
-    }
-
-    pub fn new_optional(rip: usize, bitstream_ref: Rc<ThetaCompiledBitstream>, chunk_ref: Rc<Vec<u8>>, params: Vec<Option<ThetaValue>>) -> ThetaCallFrame {
-        ThetaCallFrame { rip, locals: params, bitstream: bitstream_ref, chunk: chunk_ref }
-    }
-}
\ No newline at end of file
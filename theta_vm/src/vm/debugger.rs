@@ -0,0 +1,35 @@
+use theta_types::bytecode::{ThetaString, ThetaValue};
+
+/// An instruction in a loaded function: the function's index and the index of the instruction in its code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CodeLocation {
+    pub function: usize,
+    pub offset: usize,
+}
+
+/// A snapshot of a frame on the call stack, as seen by a debugger.
+#[derive(Debug)]
+pub struct FrameInfo<'a> {
+    /// The function the frame is running, or None for top level chunks.
+    pub function: Option<ThetaString>,
+    pub function_index: Option<usize>,
+    /// The instruction the frame is executing. For frames below the top this is the call they are waiting on.
+    pub offset: usize,
+    /// The source line of `offset`, if the function has debug info.
+    pub line: Option<usize>,
+    /// The frame's arguments followed by its locals. For top level chunks, everything the frame has on the stack.
+    pub locals: &'a [ThetaValue],
+}
+
+/// How far `run` should go before yielding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum RunMode {
+    Continue,
+    StepInstruction,
+    /// Runs until a new line starts at any call depth, or the frame at the given call depth returns.
+    StepInto(usize),
+    /// Runs until a new line starts at the given call depth or above.
+    StepOver(usize),
+    /// Runs until the frame at the given call depth returns.
+    StepOut(usize),
+}
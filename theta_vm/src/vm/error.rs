@@ -0,0 +1,98 @@
+use std::{error::Error, fmt};
+
+use theta_types::{bytecode::DisassembleError, types::TypeInformation};
+
+/// The number of frames kept at each end of a backtrace. Frames in between are only counted.
+pub const BACKTRACE_FRAMES: usize = 8;
+
+#[derive(Debug)]
+pub enum RuntimeError {
+    IOError(std::io::Error),
+    DisassembleError(DisassembleError),
+    StackOverflow { limit: StackLimit, backtrace: Backtrace },
+    /// An allocation of `requested` bytes would have taken the heap past `limit` bytes.
+    OutOfMemory { requested: usize, limit: usize },
+    /// A string or float was converted to a type it does not hold a value of.
+    InvalidConversion { input: String, ty: TypeInformation },
+}
+
+/// The limit that was exceeded by a stack overflow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StackLimit {
+    CallDepth(usize),
+    StackSize(usize),
+}
+
+/// Names of the functions on the call stack when an error occured, innermost first.
+/// Top level chunks have no name. Deep stacks only keep their innermost and outermost frames.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Backtrace {
+    pub innermost: Vec<Option<String>>,
+    pub omitted: usize,
+    pub outermost: Vec<Option<String>>,
+}
+
+impl Backtrace {
+    pub fn new(mut frames: Vec<Option<String>>) -> Backtrace {
+        if frames.len() <= BACKTRACE_FRAMES * 2 {
+            return Backtrace { innermost: frames, omitted: 0, outermost: vec![] };
+        }
+
+        let outermost = frames.split_off(frames.len() - BACKTRACE_FRAMES);
+        let omitted = frames.len() - BACKTRACE_FRAMES;
+        frames.truncate(BACKTRACE_FRAMES);
+        Backtrace { innermost: frames, omitted, outermost }
+    }
+
+    /// The total number of frames, including omitted ones.
+    pub fn depth(&self) -> usize {
+        self.innermost.len() + self.omitted + self.outermost.len()
+    }
+}
+
+impl fmt::Display for Backtrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let write_frame = |f: &mut fmt::Formatter<'_>, frame: &Option<String>| match frame {
+            Some(name) => writeln!(f, "  at {}", name),
+            None => writeln!(f, "  at <top level>"),
+        };
+
+        for frame in &self.innermost {
+            write_frame(f, frame)?;
+        }
+        if self.omitted > 0 {
+            writeln!(f, "  ... {} more frames", self.omitted)?;
+        }
+        for frame in &self.outermost {
+            write_frame(f, frame)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuntimeError::IOError(io) => write!(f, "I/O error: {}", io),
+            RuntimeError::DisassembleError(de) => write!(f, "disassembly error: {}", de),
+            RuntimeError::StackOverflow { limit: StackLimit::CallDepth(max), backtrace } => write!(f, "stack overflow: call depth exceeded {}\n{}", max, backtrace),
+            RuntimeError::StackOverflow { limit: StackLimit::StackSize(max), backtrace } => write!(f, "stack overflow: stack size exceeded {} values\n{}", max, backtrace),
+            RuntimeError::OutOfMemory { requested, limit } => write!(f, "out of memory: could not allocate {} bytes with a heap limit of {} bytes", requested, limit),
+            RuntimeError::InvalidConversion { input, ty } => write!(f, "invalid conversion: \"{}\" is not a valid {}", input, ty),
+        }
+    }
+}
+
+impl Error for RuntimeError {}
+
+impl From<std::io::Error> for RuntimeError {
+    fn from(err: std::io::Error) -> Self {
+        RuntimeError::IOError(err)
+    }
+}
+
+impl From<DisassembleError> for RuntimeError {
+    fn from(err: DisassembleError) -> Self {
+        RuntimeError::DisassembleError(err)
+    }
+}
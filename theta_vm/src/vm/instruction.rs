@@ -0,0 +1,68 @@
+use std::rc::Rc;
+
+use theta_types::bytecode::{OpCode, DisassembleError, CHUNK_HEADER};
+
+/// An instruction as executed by the VM.
+/// Chunks are decoded into instructions once when they are loaded, so dispatch never has to parse bytes.
+/// Jumps are resolved from byte offsets to the index of the instruction they land on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Instruction {
+    /// Any OpCode that does not jump. Jump OpCodes are always decoded into `Jump` or `JumpIfFalse`.
+    Op(OpCode),
+    Jump { target: usize },
+    JumpIfFalse { target: usize },
+    /// A call to a known function, resolved from a function name constant followed by `CallDirect`.
+    Call { function: usize },
+    /// A tail call to a known function, resolved from a function name constant followed by `TailCallDirect`.
+    TailCall { function: usize },
+    DefineGlobal { slot: usize },
+    GetGlobal { slot: usize },
+}
+
+/// Decodes an assembled chunk, including its header, into the VM's instruction stream.
+pub fn decode_chunk(chunk: &[u8]) -> Result<Rc<[Instruction]>, DisassembleError> {
+    Ok(decode_chunk_with_offsets(chunk)?.0)
+}
+
+/// Decodes an assembled chunk like `decode_chunk`, also returning the byte offset each instruction was decoded from.
+pub fn decode_chunk_with_offsets(chunk: &[u8]) -> Result<(Rc<[Instruction]>, Vec<usize>), DisassembleError> {
+    if chunk.len() < 16 {
+        return Err(DisassembleError::TruncatedInstruction(chunk.len()));
+    }
+
+    if chunk[0..8] != CHUNK_HEADER {
+        return Err(DisassembleError::InvalidMarkerInChunk(chunk[0..8].to_vec()));
+    }
+
+    let chunk_size = usize::from_le_bytes(chunk[8..16].try_into()?);
+    let code = chunk_size.checked_add(16).and_then(|end| chunk.get(16..end)).ok_or(DisassembleError::TruncatedInstruction(chunk.len() - 16))?;
+
+    // byte offset of each instruction, used to turn jump offsets into instruction indices
+    let mut positions = Vec::new();
+    let mut ops = Vec::new();
+    let mut offset = 0;
+    while offset < code.len() {
+        let (op, size) = OpCode::decode(&code[offset..])?;
+        positions.push(offset);
+        ops.push(op);
+        offset += size;
+    }
+    // jumping to the end of the chunk is allowed
+    positions.push(offset);
+
+    let resolve = |idx: usize, offset: isize| {
+        let target = positions[idx] as isize + offset;
+        positions.binary_search(&(target as usize)).ok().filter(|_| target >= 0).ok_or(DisassembleError::InvalidJumpTarget(target))
+    };
+
+    let instructions = ops.iter().enumerate().map(|(idx, op)| Ok(match *op {
+        OpCode::JumpLocal { offset } => Instruction::Jump { target: resolve(idx, offset as isize)? },
+        OpCode::JumpFar { offset } => Instruction::Jump { target: resolve(idx, offset)? },
+        OpCode::JumpLocalIfFalse { offset } => Instruction::JumpIfFalse { target: resolve(idx, offset as isize)? },
+        OpCode::JumpFarIfFalse { offset } => Instruction::JumpIfFalse { target: resolve(idx, offset)? },
+        op => Instruction::Op(op),
+    })).collect::<Result<Rc<[Instruction]>, DisassembleError>>()?;
+
+    positions.pop();
+    Ok((instructions, positions))
+}
@@ -1,37 +1,199 @@
-use std::{rc::Rc, collections::HashMap, io::Write};
+use std::{rc::Rc, collections::{HashMap, HashSet}, io::Write, time::Instant};
 
 use log::{debug, error};
-use theta_types::bytecode::{ThetaString, ThetaHeapValue, ThetaCompiledBitstream, ThetaCompiledFunction, ThetaValue, DisassembleError, CHUNK_HEADER, Chunk};
+use theta_types::{bytecode::{ThetaString, ThetaHeapValue, ThetaCompiledBitstream, ThetaCompiledFunction, ThetaValue, DisassembleError, OpCode}, types::TypeInformation};
 
-use super::{call_frame::ThetaStack, ThetaCallFrame};
+use super::{call_frame::ThetaStack, ThetaCallFrame, Instruction, decode_chunk_with_offsets, RuntimeError, StackLimit, Backtrace, CodeLocation, FrameInfo, debugger::RunMode, VmObserver, ObserverId, SnapshotError, snapshot::{SnapshotWriter, SnapshotReader}};
+
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 10_000;
+pub const DEFAULT_MAX_STACK_SIZE: usize = 1 << 20;
+/// How many instructions are executed between checks of the deadline.
+pub const DEADLINE_CHECK_INTERVAL: u64 = 1024;
+
+/// Pairs of (byte offset, resolved instruction index) for every instruction decoded from a chunk.
+type OffsetMap = Vec<(usize, usize)>;
+
+/// Why execution stopped, when it stopped without an error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExecutionStatus {
+    /// The code ran to completion.
+    Finished,
+    /// A breakpoint was hit. Execution continues from after it with `resume`.
+    Breakpoint,
+    /// The instruction budget ran out. Execution continues with `resume` once more fuel is added.
+    OutOfFuel,
+    /// The deadline passed. Execution continues with `resume` once the deadline is moved or cleared.
+    DeadlineExceeded,
+    /// A step requested through the debugger API completed.
+    Stepped,
+}
+
+/// A function that has been loaded into the VM, along with its decoded instructions.
+#[derive(Debug, Clone)]
+pub struct LoadedFunction {
+    /// The index resolved calls refer to the function by.
+    pub index: usize,
+    pub function: ThetaCompiledFunction,
+    pub bitstream: Rc<ThetaCompiledBitstream>,
+    pub code: Rc<[Instruction]>,
+    /// The index of every instruction that starts a source line, along with the line, in order.
+    pub lines: Vec<(usize, usize)>,
+}
+
+impl LoadedFunction {
+    /// The source line the instruction at `offset` belongs to, if the function has debug info.
+    pub fn line_at(&self, offset: usize) -> Option<usize> {
+        let idx = self.lines.partition_point(|(start, _)| *start <= offset);
+        idx.checked_sub(1).map(|idx| self.lines[idx].1)
+    }
+}
 
-// TODO: can we snapshot the VM using CoW?
-// probably not, but what happens if an instruction fails due to bad input data?
 pub struct VM {
+    // index of the next instruction in the current chunk
     current_offset: usize,
-    current_chunk: Rc<Vec<u8>>,
+    current_chunk: Rc<[Instruction]>,
     stdout: Box<dyn Write>,
     stack: ThetaStack,
     strings: HashMap<ThetaString, Rc<ThetaHeapValue>>,
     heap: Vec<Rc<ThetaHeapValue>>,
     loaded_bitstreams: Vec<Rc<ThetaCompiledBitstream>>,
-    function_table: HashMap<ThetaString, (ThetaCompiledFunction, Rc<ThetaCompiledBitstream>)>,
+    // functions are resolved to indices when chunks are loaded. an index is None until the function is loaded.
+    functions: Vec<Option<LoadedFunction>>,
+    function_indices: HashMap<ThetaString, usize>,
+    max_call_depth: usize,
+    max_stack_size: usize,
+    // instructions left to execute before yielding, or None for no limit
+    fuel: Option<u64>,
+    deadline: Option<Instant>,
+    // total instructions executed, used to space out deadline checks
+    executed: u64,
+    heap_limit: Option<usize>,
+    heap_used: usize,
+    heap_peak: usize,
+    breakpoints: HashSet<CodeLocation>,
+    // set when execution is paused on a breakpoint, so resuming does not immediately hit it again
+    at_breakpoint: bool,
+    observers: Vec<(ObserverId, Box<dyn VmObserver>)>,
+    next_observer: usize,
 }
 
 impl VM {
     pub fn new(stdout: Box<dyn Write>) -> VM {
         VM {
             current_offset: 0,
-            current_chunk: Rc::default(),
+            current_chunk: Rc::new([]),
             stdout,
             stack: ThetaStack::new(),
             strings: HashMap::new(),
             heap: Vec::new(),
             loaded_bitstreams: vec![],
-            function_table: HashMap::new(),
+            functions: vec![],
+            function_indices: HashMap::new(),
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            max_stack_size: DEFAULT_MAX_STACK_SIZE,
+            fuel: None,
+            deadline: None,
+            executed: 0,
+            heap_limit: None,
+            heap_used: 0,
+            heap_peak: 0,
+            breakpoints: HashSet::new(),
+            at_breakpoint: false,
+            observers: Vec::new(),
+            next_observer: 0,
         }
     }
 
+    pub fn heap_limit(&self) -> Option<usize> {
+        self.heap_limit
+    }
+
+    /// Sets the maximum number of bytes the heap may use. Allocations past it fail with `OutOfMemory`.
+    pub fn set_heap_limit(&mut self, limit: Option<usize>) {
+        self.heap_limit = limit;
+    }
+
+    /// The number of bytes currently allocated on the heap.
+    pub fn heap_used(&self) -> usize {
+        self.heap_used
+    }
+
+    /// The most bytes that have been allocated on the heap at once.
+    pub fn heap_peak(&self) -> usize {
+        self.heap_peak
+    }
+
+    pub fn fuel(&self) -> Option<u64> {
+        self.fuel
+    }
+
+    /// Sets the number of instructions the VM may execute before yielding with `OutOfFuel`. None removes the limit.
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.fuel = fuel;
+    }
+
+    /// Adds to the instruction budget. Does nothing if there is no limit.
+    pub fn add_fuel(&mut self, fuel: u64) {
+        self.fuel = self.fuel.map(|f| f.saturating_add(fuel));
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Sets a point in time after which the VM yields with `DeadlineExceeded`.
+    /// The deadline is only checked every `DEADLINE_CHECK_INTERVAL` instructions, so it may be overrun slightly.
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline;
+    }
+
+    /// The total number of instructions the VM has executed.
+    pub fn executed(&self) -> u64 {
+        self.executed
+    }
+
+    /// Registers an observer to be told about calls, returns, allocations and every executed instruction.
+    /// Observers are told about events in the order they were added. Without an observer, none of the events are built.
+    pub fn add_observer(&mut self, mut observer: Box<dyn VmObserver>) -> ObserverId {
+        // functions loaded before the observer was added are reported first, so it can name them
+        for func in self.functions.iter().flatten() {
+            observer.on_load(func);
+        }
+
+        let id = ObserverId(self.next_observer);
+        self.next_observer += 1;
+        self.observers.push((id, observer));
+        id
+    }
+
+    pub fn remove_observer(&mut self, id: ObserverId) -> Option<Box<dyn VmObserver>> {
+        let idx = self.observers.iter().position(|(observer, _)| *observer == id)?;
+        Some(self.observers.remove(idx).1)
+    }
+
+    pub fn max_call_depth(&self) -> usize {
+        self.max_call_depth
+    }
+
+    /// Sets the maximum number of frames on the call stack. Calls past it fail with a stack overflow.
+    pub fn set_max_call_depth(&mut self, max_call_depth: usize) {
+        self.max_call_depth = max_call_depth;
+    }
+
+    pub fn max_stack_size(&self) -> usize {
+        self.max_stack_size
+    }
+
+    /// Sets the maximum number of values on the value stack, across all frames.
+    pub fn set_max_stack_size(&mut self, max_stack_size: usize) {
+        self.max_stack_size = max_stack_size;
+    }
+
+    /// Where programs print to.
+    pub fn stdout(&mut self) -> &mut dyn Write {
+        self.stdout.as_mut()
+    }
+
     pub fn strings(&self) -> &HashMap<ThetaString, Rc<ThetaHeapValue>> {
         &self.strings
     }
@@ -44,113 +206,412 @@ impl VM {
         &self.heap
     }
 
-    pub fn globals(&self) -> &HashMap<String, ThetaValue> {
+    pub fn globals(&self) -> HashMap<&str, &ThetaValue> {
         self.stack.globals()
     }
 
+    pub fn global(&self, name: &str) -> Option<&ThetaValue> {
+        self.stack.get_global(*self.stack.global_slots().get(name)?)
+    }
+
     pub fn constants(&self) -> &Vec<ThetaValue> {
         &self.stack.curr_frame().expect("stack should always have frame").bitstream.constants
     }
 
-    pub fn functions(&self) -> &HashMap<ThetaString, (ThetaCompiledFunction, Rc<ThetaCompiledBitstream>)> {
-        &self.function_table
+    pub fn functions(&self) -> &[Option<LoadedFunction>] {
+        &self.functions
+    }
+
+    pub fn function_indices(&self) -> &HashMap<ThetaString, usize> {
+        &self.function_indices
+    }
+
+    pub fn function(&self, name: &ThetaString) -> Option<&LoadedFunction> {
+        self.functions.get(*self.function_indices.get(name)?)?.as_ref()
+    }
+
+    /// Returns the index of a function, allocating a new (unloaded) index if the name has not been seen before.
+    pub fn function_index(&mut self, name: &ThetaString) -> usize {
+        match self.function_indices.get(name) {
+            Some(idx) => *idx,
+            None => {
+                self.functions.push(None);
+                self.function_indices.insert(name.clone(), self.functions.len() - 1);
+                self.functions.len() - 1
+            },
+        }
     }
 
     pub fn bitstreams(&self) -> &Vec<Rc<ThetaCompiledBitstream>> {
         &self.loaded_bitstreams
     }
 
+    /// Interns a string without checking the heap limit. This is used for constants while a bitstream is loaded,
+    /// where there is nothing to report an error to. The string still counts towards heap usage.
     pub fn intern_string(&mut self, s_val: ThetaString) -> ThetaValue {
-        let hv = match self.strings.get(&s_val) {
-            Some(rc) => rc.clone(),
-            None => { 
-                let rc = Rc::new(ThetaHeapValue::Str(s_val.clone()));
-                self.strings.insert(s_val, rc.clone());
-                self.heap.push(rc.clone());
-                rc
+        match self.strings.get(&s_val) {
+            Some(rc) => ThetaValue::Pointer(rc.clone()),
+            None => {
+                let hv = ThetaHeapValue::Str(s_val.clone());
+                self.track_allocation(hv.size());
+                self.insert_string(s_val, hv)
             },
-        };
-        ThetaValue::Pointer(hv)
+        }
     }
 
-    pub fn load_bitstream(&mut self, bs: ThetaCompiledBitstream) -> Rc<ThetaCompiledBitstream> {
+    /// Interns a string, failing if allocating it would take the heap past its limit.
+    pub fn try_intern_string(&mut self, s_val: ThetaString) -> Result<ThetaValue, RuntimeError> {
+        match self.strings.get(&s_val) {
+            Some(rc) => Ok(ThetaValue::Pointer(rc.clone())),
+            None => {
+                let hv = ThetaHeapValue::Str(s_val.clone());
+                self.reserve_heap(hv.size())?;
+                Ok(self.insert_string(s_val, hv))
+            },
+        }
+    }
+
+    fn insert_string(&mut self, s_val: ThetaString, hv: ThetaHeapValue) -> ThetaValue {
+        let rc = Rc::new(hv);
+        for (_, observer) in &mut self.observers {
+            observer.on_alloc(&rc, rc.size());
+        }
+        self.strings.insert(s_val, rc.clone());
+        self.heap.push(rc.clone());
+        ThetaValue::Pointer(rc)
+    }
+
+    /// Accounts for an allocation of `size` bytes, collecting garbage first if it would not fit under the heap limit.
+    fn reserve_heap(&mut self, size: usize) -> Result<(), RuntimeError> {
+        if let Some(limit) = self.heap_limit {
+            if self.heap_used + size > limit {
+                self.collect_garbage();
+            }
+            if self.heap_used + size > limit {
+                return Err(RuntimeError::OutOfMemory { requested: size, limit });
+            }
+        }
+
+        self.track_allocation(size);
+        Ok(())
+    }
+
+    fn track_allocation(&mut self, size: usize) {
+        self.heap_used += size;
+        self.heap_peak = self.heap_peak.max(self.heap_used);
+    }
+
+    /// Frees every heap value that is no longer referenced outside of the VM's heap and intern table.
+    /// Returns the number of bytes freed.
+    pub fn collect_garbage(&mut self) -> usize {
+        let strings = &mut self.strings;
+        let mut freed = 0;
+
+        self.heap.retain(|hv| {
+            // interned values are referenced by both the heap and the intern table
+            let owned_refs = match hv.as_ref() {
+                ThetaHeapValue::Str(_) => 2,
+            };
+            if Rc::strong_count(hv) > owned_refs {
+                return true;
+            }
+
+            match hv.as_ref() {
+                ThetaHeapValue::Str(s) => strings.remove(s),
+            };
+            freed += hv.size();
+            false
+        });
+
+        self.heap_used -= freed;
+        freed
+    }
+
+    /// Loads a bitstream into the VM, decoding the chunk of every function in it.
+    pub fn load_bitstream(&mut self, bs: ThetaCompiledBitstream) -> Result<Rc<ThetaCompiledBitstream>, DisassembleError> {
         let loaded_bs = Rc::new(bs);
+
+        // decode everything before touching the function table so a bad bitstream is not partially loaded
+        let decoded = loaded_bs.functions().iter().map(|func| {
+            let (code, offsets) = self.resolve_chunk(&func.chunk, &loaded_bs)?;
+            // line offsets are in bytes. instructions merged while loading keep the line of their first byte.
+            let lines = func.lines.iter().filter_map(|(byte, line)| Some((offsets[offsets.binary_search_by_key(byte, |(byte, _)| *byte).ok()?].1, *line))).collect();
+            Ok((func.clone(), code, lines))
+        }).collect::<Result<Vec<_>, DisassembleError>>()?;
+
         self.loaded_bitstreams.push(loaded_bs.clone());
 
         // TODO: this should not copy the functions.
-        for func in loaded_bs.functions() {
-            self.function_table.insert(func.name.clone(), (func.clone(), loaded_bs.clone()));
+        for (func, code, lines) in decoded {
+            let idx = self.function_index(&func.name);
+            let func = self.functions[idx].insert(LoadedFunction { index: idx, function: func, bitstream: loaded_bs.clone(), code, lines });
+            for (_, observer) in &mut self.observers {
+                observer.on_load(func);
+            }
         }
 
         // self.stack.set_bitstream(loaded_bs.clone());
-        loaded_bs
+        Ok(loaded_bs)
+    }
+
+    /// Decodes a chunk that refers to `bitstream`'s constant pool,
+    /// resolving direct calls and global accesses to function indices and global slots.
+    pub fn load_chunk(&mut self, chunk: &[u8], bitstream: &ThetaCompiledBitstream) -> Result<Rc<[Instruction]>, DisassembleError> {
+        Ok(self.resolve_chunk(chunk, bitstream)?.0)
+    }
+
+    /// Decodes and resolves a chunk like `load_chunk`.
+    /// Also returns, for every decoded instruction, the byte offset it starts at and the index it was resolved to.
+    fn resolve_chunk(&mut self, chunk: &[u8], bitstream: &ThetaCompiledBitstream) -> Result<(Rc<[Instruction]>, OffsetMap), DisassembleError> {
+        let (decoded, byte_offsets) = decode_chunk_with_offsets(chunk)?;
+
+        let constant_name = |offset: usize| match bitstream.constants.get(offset) {
+            Some(ThetaValue::Pointer(hv)) => match hv.as_ref() {
+                ThetaHeapValue::Str(name) => Some(name.clone()),
+            },
+            _ => None,
+        };
+
+        // merging a call into one instruction shifts everything after it, so jump targets are remapped afterwards.
+        let mut new_indices = Vec::with_capacity(decoded.len() + 1);
+        let mut resolved = Vec::with_capacity(decoded.len());
+        let mut idx = 0;
+        while idx < decoded.len() {
+            new_indices.push(resolved.len());
+            match (decoded[idx], decoded.get(idx + 1)) {
+                (Instruction::Op(OpCode::Constant { offset }), Some(Instruction::Op(call @ (OpCode::CallDirect { .. } | OpCode::TailCallDirect { .. })))) if constant_name(offset).is_some() => {
+                    let function = self.function_index(&constant_name(offset).expect("checked above"));
+                    new_indices.push(resolved.len());
+                    resolved.push(match call {
+                        OpCode::TailCallDirect { .. } => Instruction::TailCall { function },
+                        _ => Instruction::Call { function },
+                    });
+                    idx += 2;
+                    continue;
+                },
+                (Instruction::Op(OpCode::DefineGlobal { offset }), _) if constant_name(offset).is_some() => {
+                    resolved.push(Instruction::DefineGlobal { slot: self.stack.global_slot(&constant_name(offset).expect("checked above")) });
+                },
+                (Instruction::Op(OpCode::GetGlobal { offset }), _) if constant_name(offset).is_some() => {
+                    resolved.push(Instruction::GetGlobal { slot: self.stack.global_slot(&constant_name(offset).expect("checked above")) });
+                },
+                (inst, _) => resolved.push(inst),
+            }
+            idx += 1;
+        }
+        new_indices.push(resolved.len());
+
+        let code = resolved.into_iter().map(|inst| match inst {
+            Instruction::Jump { target } => Instruction::Jump { target: new_indices[target] },
+            Instruction::JumpIfFalse { target } => Instruction::JumpIfFalse { target: new_indices[target] },
+            inst => inst,
+        }).collect();
+
+        Ok((code, byte_offsets.into_iter().zip(new_indices).collect()))
     }
 
     pub fn push_frame(&mut self, sf: ThetaCallFrame) {
         self.stack.push_raw_frame(sf);
     }
+
+    /// Pushes a value onto the stack, e.g. an argument for a frame that is about to be pushed.
+    pub fn push_value(&mut self, value: ThetaValue) {
+        self.stack.push(value);
+    }
+
+    /// Pops the value on top of the stack, e.g. the result left behind by a top level chunk that returned one.
+    pub fn pop_value(&mut self) -> Option<ThetaValue> {
+        self.stack.pop()
+    }
 }
 
 impl VM {
-    pub fn execute_code(&mut self) -> Result<(), DisassembleError> {
-        let (chunk, new_offset) = self.page_chunk();
-        let mut cont = true;
-        self.current_offset = new_offset;
-        self.current_chunk = chunk;
+    /// Runs the current frame from its first instruction until it returns or yields.
+    /// If a runtime error occurs, every frame is unwound so the VM can be used again.
+    pub fn execute_code(&mut self) -> Result<ExecutionStatus, RuntimeError> {
+        (self.current_chunk, self.current_offset) = self.page_chunk();
+        self.at_breakpoint = false;
+        self.resume()
+    }
+
+    /// Continues execution from where it last yielded.
+    pub fn resume(&mut self) -> Result<ExecutionStatus, RuntimeError> {
+        self.run(RunMode::Continue)
+    }
+
+    fn run(&mut self, mode: RunMode) -> Result<ExecutionStatus, RuntimeError> {
+        // a breakpoint execution is paused on has already been reported, so its instruction may run
+        let mut skip_breakpoint = std::mem::take(&mut self.at_breakpoint);
+        for (_, observer) in &mut self.observers {
+            observer.on_resume();
+        }
+
+        while self.current_offset < self.current_chunk.len() {
+            if !skip_breakpoint && self.on_breakpoint() {
+                self.at_breakpoint = true;
+                return Ok(ExecutionStatus::Breakpoint);
+            }
+
+            if let Some(status) = self.consume_budget() {
+                self.at_breakpoint = skip_breakpoint;
+                return Ok(status);
+            }
+            skip_breakpoint = false;
+
+            if !self.observers.is_empty() {
+                self.observe_instruction();
+            }
 
-        while self.current_offset < self.current_chunk.len() && cont {
             // read into chunk
-            cont = self.execute_line()?;
+            match self.execute_line() {
+                Ok(None) => {},
+                Ok(Some(status)) => return Ok(status),
+                Err(e) => {
+                    self.unwind();
+                    return Err(e);
+                },
+            };
+
+            let stepped = match mode {
+                RunMode::Continue => false,
+                RunMode::StepInstruction => true,
+                RunMode::StepInto(depth) => self.stack.depth() < depth || self.starts_line(),
+                RunMode::StepOver(depth) => self.stack.depth() < depth || (self.stack.depth() == depth && self.starts_line()),
+                RunMode::StepOut(depth) => self.stack.depth() < depth,
+            };
+            if stepped {
+                return Ok(ExecutionStatus::Stepped);
+            }
         }
 
-        Ok(())
+        Ok(ExecutionStatus::Finished)
+    }
+
+    /// Abandons yielded execution, unwinding every frame.
+    pub fn abort(&mut self) {
+        self.unwind();
+        self.current_chunk = Rc::new([]);
+        self.current_offset = 0;
+        self.at_breakpoint = false;
+    }
+
+    /// Drops every frame without returning from them, telling the observers.
+    fn unwind(&mut self) {
+        self.stack.unwind();
+        for (_, observer) in &mut self.observers {
+            observer.on_unwind();
+        }
+    }
+
+    #[cold]
+    fn observe_instruction(&mut self) {
+        let func = self.stack.curr_frame().and_then(|frame| frame.function).and_then(|function| self.functions[function].as_ref());
+        for (_, observer) in &mut self.observers {
+            observer.on_instruction(func, self.current_offset, self.current_chunk[self.current_offset], self.stack.values());
+        }
+    }
+
+    /// Tells the observers about the frame that was just pushed for a call.
+    #[cold]
+    fn observe_call(&mut self, tail_call: bool) {
+        let frame = self.stack.curr_frame().expect("a frame was just pushed");
+        let func = self.functions[frame.function.expect("calls push frames for functions")].as_ref().expect("called functions are loaded");
+        let args = &self.stack.values()[frame.base..frame.base + func.function.args.len()];
+        for (_, observer) in &mut self.observers {
+            observer.on_call(func, args, tail_call);
+        }
+    }
+
+    /// Tells the observers that the current frame is about to return.
+    #[cold]
+    fn observe_return(&mut self, value: Option<&ThetaValue>) {
+        let func = self.stack.curr_frame().and_then(|frame| frame.function).and_then(|function| self.functions[function].as_ref());
+        for (_, observer) in &mut self.observers {
+            observer.on_return(func, value);
+        }
+    }
+
+    #[cold]
+    fn observe_global_define(&mut self, slot: usize, value: &ThetaValue) {
+        // globals are only known by slot at runtime, the names are kept for loading
+        if let Some((name, _)) = self.stack.global_slots().iter().find(|(_, s)| **s == slot) {
+            for (_, observer) in &mut self.observers {
+                observer.on_global_define(name, value);
+            }
+        }
+    }
+
+    /// Accounts for the next instruction, returning a status if it may not run yet.
+    #[inline(always)]
+    fn consume_budget(&mut self) -> Option<ExecutionStatus> {
+        if let Some(deadline) = self.deadline {
+            if self.executed.is_multiple_of(DEADLINE_CHECK_INTERVAL) && Instant::now() >= deadline {
+                return Some(ExecutionStatus::DeadlineExceeded);
+            }
+        }
+
+        match self.fuel {
+            Some(0) => return Some(ExecutionStatus::OutOfFuel),
+            Some(ref mut fuel) => *fuel -= 1,
+            None => {},
+        }
+
+        self.executed += 1;
+        None
     }
 
     // #[inline(always)]
-    pub fn execute_line(&mut self) -> Result<bool, DisassembleError> {
+    /// Executes the next instruction, returning a status if execution should stop.
+    pub fn execute_line(&mut self) -> Result<Option<ExecutionStatus>, RuntimeError> {
         match self.current_chunk[self.current_offset] {
-            0x0 => { 
+            Instruction::Op(OpCode::ReturnVoid) => { 
                 debug!("Op: Void Return (0x0)");
+                if !self.observers.is_empty() {
+                    self.observe_return(None);
+                }
                 // correct offset and load chunk
                 self.current_offset = self.stack.pop_frame().expect("expected stack frame").rip;
                 // end control
                 match self.stack().curr_frame() {
                     Some(frame) => self.current_chunk = frame.chunk.clone(),
-                    None => return Ok(false),
+                    None => return Ok(Some(ExecutionStatus::Finished)),
                 };
             },
-            0xF0 => {
+            Instruction::Op(OpCode::Return) => {
                 debug!("Op: Return (0xF0)");
                 let sv = self.stack.pop().expect("expected value on top of stack for return");
                 debug!("{:?}", sv);
+                if !self.observers.is_empty() {
+                    self.observe_return(Some(&sv));
+                }
                 // correct offset and load chunk
                 self.current_offset = self.stack.pop_frame().expect("expected stack frame").rip;
+                // load return val onto the stack where the callee's arguments were
+                self.stack.push(sv);
                 match self.stack().curr_frame() {
                     Some(frame) => self.current_chunk = frame.chunk.clone(),
-                    None => return Ok(false),
+                    None => return Ok(Some(ExecutionStatus::Finished)),
                 };
-                // load return val onto the stack
-                self.stack.curr_frame_mut().expect("expected stack frame").locals.push(Some(sv));
             }
-            0x1 => { 
-                debug!("Op: Constant (0x1) with offset: {:#X}", &self.current_chunk[self.current_offset+1]); 
-                let constant = self.stack.curr_frame().expect("expected stack frame").bitstream.constants[self.current_chunk[self.current_offset+1] as usize].clone();
+            Instruction::Op(OpCode::Constant { offset }) => { 
+                debug!("Op: Constant (0x1) with offset: {:#X}", offset); 
+                let constant = self.stack.curr_frame().expect("expected stack frame").bitstream.constants[offset].clone();
                 self.stack.push(constant); 
-                self.current_offset += 2 
+                self.current_offset += 1 
             },
-            0x2 => { 
-                debug!("Op: Push (0x2) with inc size {:#X}", self.current_chunk[self.current_offset+1]);
-                let stack_inc_size = usize::from_le_bytes(self.current_chunk[self.current_offset+1..self.current_offset+9].try_into().expect("8 ele slice not converted"));
+            Instruction::Op(OpCode::Push { size: stack_inc_size }) => { 
+                debug!("Op: Push (0x2) with inc size {:#X}", stack_inc_size);
+                self.check_stack_size(stack_inc_size, None)?;
                 self.stack.alloc_framespace(stack_inc_size);
-                self.current_offset += 1 + std::mem::size_of::<usize>()
+                self.current_offset += 1
             },
-            0x3 => { 
+            Instruction::Op(OpCode::Pop) => { 
                 debug!("Op: Pop (0x3)"); 
                 let pot = self.stack.pop();
                 debug!("Popped from top of stack: {pot:?}");
                 self.current_offset += 1 
             },
-            0x4 => {
+            Instruction::Op(OpCode::Add) => {
                 debug!("Op: Add (0x4)");
                 let right = self.stack.pop().expect("failed to grab value off stack");
                 let left = self.stack.pop().expect("failed to grab value off stack");
@@ -162,7 +623,7 @@ impl VM {
                         match (&*l, &*r) {
                             (ThetaHeapValue::Str(ls), ThetaHeapValue::Str(ref rs)) => {
                                 let s_val = ls.clone() + rs;
-                                let tv = self.intern_string(s_val);                              
+                                let tv = self.try_intern_string(s_val)?;                              
                                 self.stack.push(tv);
                             },
                         }
@@ -171,7 +632,58 @@ impl VM {
                 };
                 self.current_offset += 1
             },
-            0x5 => {
+            Instruction::Op(OpCode::Stringify) => {
+                debug!("Op: Stringify (0x20)");
+                let value = self.stack.pop().expect("failed to grab value off stack");
+                let tv = self.try_intern_string(ThetaString::new(value.to_string()))?;
+                self.stack.push(tv);
+                self.current_offset += 1
+            },
+            Instruction::Op(OpCode::IntToFloat) => {
+                debug!("Op: IntToFloat (0x21)");
+                match self.stack.pop().expect("failed to grab value off stack") {
+                    ThetaValue::Int(i) => self.stack.push(ThetaValue::Double(i as f64)),
+                    _ => panic!("invalid operands"),
+                };
+                self.current_offset += 1
+            },
+            Instruction::Op(OpCode::FloatToInt) => {
+                debug!("Op: FloatToInt (0x22)");
+                match self.stack.pop().expect("failed to grab value off stack") {
+                    // `as` would saturate, so values an Int cannot hold fail like an unparsable string does
+                    ThetaValue::Double(d) if (i64::MIN as f64..i64::MAX as f64).contains(&d) => self.stack.push(ThetaValue::Int(d as i64)),
+                    ThetaValue::Double(d) => return Err(RuntimeError::InvalidConversion { input: d.to_string(), ty: TypeInformation::Int }),
+                    _ => panic!("invalid operands"),
+                };
+                self.current_offset += 1
+            },
+            Instruction::Op(OpCode::BoolToInt) => {
+                debug!("Op: BoolToInt (0x23)");
+                match self.stack.pop().expect("failed to grab value off stack") {
+                    ThetaValue::Bool(b) => self.stack.push(ThetaValue::Int(b as i64)),
+                    _ => panic!("invalid operands"),
+                };
+                self.current_offset += 1
+            },
+            Instruction::Op(OpCode::ParseInt) => {
+                debug!("Op: ParseInt (0x24)");
+                let value = self.parse_string(TypeInformation::Int, |s| s.parse().ok().map(ThetaValue::Int))?;
+                self.stack.push(value);
+                self.current_offset += 1
+            },
+            Instruction::Op(OpCode::ParseFloat) => {
+                debug!("Op: ParseFloat (0x25)");
+                let value = self.parse_string(TypeInformation::Float, |s| s.parse().ok().map(ThetaValue::Double))?;
+                self.stack.push(value);
+                self.current_offset += 1
+            },
+            Instruction::Op(OpCode::ParseBool) => {
+                debug!("Op: ParseBool (0x26)");
+                let value = self.parse_string(TypeInformation::Boolean, |s| s.parse().ok().map(ThetaValue::Bool))?;
+                self.stack.push(value);
+                self.current_offset += 1
+            },
+            Instruction::Op(OpCode::Subtract) => {
                 debug!("Op: Sub (0x5)");
                 let right = self.stack.pop().expect("failed to grab value off stack");
                 let left = self.stack.pop().expect("failed to grab value off stack");
@@ -183,7 +695,7 @@ impl VM {
                 };
                 self.current_offset += 1
             },
-            0x6 => {
+            Instruction::Op(OpCode::Multiply) => {
                 debug!("Op: Mul (0x6)");
                 let right = self.stack.pop().expect("failed to grab value off stack");
                 let left = self.stack.pop().expect("failed to grab value off stack");
@@ -195,7 +707,7 @@ impl VM {
                 };
                 self.current_offset += 1
             },
-            0x7 => {
+            Instruction::Op(OpCode::Divide) => {
                 debug!("Op: Div (0x7)");
                 let right = self.stack.pop().expect("failed to grab value off stack");
                 let left = self.stack.pop().expect("failed to grab value off stack");
@@ -207,7 +719,7 @@ impl VM {
                 };
                 self.current_offset += 1
             },
-            0x8 => {
+            Instruction::Op(OpCode::Negate) => {
                 debug!("Op: Neg (0x8)");
                 let left = self.stack.pop().expect("failed to grab value off stack");
 
@@ -218,7 +730,7 @@ impl VM {
                 };
                 self.current_offset += 1
             },
-            0x9 => {
+            Instruction::Op(OpCode::Equal) => {
                 debug!("Op: Equal (0x9)");
                 let right = self.stack.pop().expect("failed to grab value off stack");
                 let left = self.stack.pop().expect("failed to grab value off stack");
@@ -236,7 +748,7 @@ impl VM {
                 };
                 self.current_offset += 1
             },
-            0xA => {
+            Instruction::Op(OpCode::GreaterThan) => {
                 debug!("Op: GT (0xA)");
                 let right = self.stack.pop().expect("failed to grab value off stack");
                 let left = self.stack.pop().expect("failed to grab value off stack");
@@ -253,7 +765,7 @@ impl VM {
                 };
                 self.current_offset += 1
             },
-            0xA1 => {
+            Instruction::Op(OpCode::GreaterEqual) => {
                 debug!("Op: GTE (0xA1)");
                 let right = self.stack.pop().expect("failed to grab value off stack");
                 let left = self.stack.pop().expect("failed to grab value off stack");
@@ -270,7 +782,7 @@ impl VM {
                 };
                 self.current_offset += 1
             },
-            0xB => {
+            Instruction::Op(OpCode::LessThan) => {
                 debug!("Op: LT (0xB)");
                 let right = self.stack.pop().expect("failed to grab value off stack");
                 let left = self.stack.pop().expect("failed to grab value off stack");
@@ -287,7 +799,7 @@ impl VM {
                 };
                 self.current_offset += 1
             },
-            0xB1 => {
+            Instruction::Op(OpCode::LessEqual) => {
                 debug!("Op: LTE (0xB1)");
                 let right = self.stack.pop().expect("failed to grab value off stack");
                 let left = self.stack.pop().expect("failed to grab value off stack");
@@ -304,111 +816,48 @@ impl VM {
                 };
                 self.current_offset += 1
             },
-            0xC0 => { 
-                debug!("Op: Define Global (0xC0) with offset: {:#X}", self.current_chunk[self.current_offset+1] as usize);
-                let glob = self.stack.curr_frame().expect("expected stack frame").bitstream.constants[self.current_chunk[self.current_offset+1] as usize].clone();
-                match glob {
-                    ThetaValue::Pointer(hv) => {
-                        match &*hv {
-                            ThetaHeapValue::Str(s) => {
-                                let sv = self.stack.peek().expect("no value on stack").clone();
-                                self.stack.globals_mut().insert(s.to_string(), sv);
-                                self.stack.pop();
-                            },
-                        }
-                    },
-                    _ => panic!("Define Global with no HV")
-                }
-                self.current_offset += 2
-            },
-            0xC1 => { 
-                debug!("Op: Read Global (0xC1)");
-                let glob = self.stack.curr_frame().expect("expected stack frame").bitstream.constants[self.current_chunk[self.current_offset+1] as usize].clone();
-                match glob {
-                    ThetaValue::Pointer(hv) => {
-                        match &*hv {
-                            ThetaHeapValue::Str(s) => {
-                                let v = self.stack.globals_mut().get(s.internal().as_str());
-                                let v2 = v.expect("no such constant").clone();
-                                self.stack.push(v2);
-                            },
-                        }
-                    },
-                    _ => panic!("Read Global with no HV")
+            Instruction::DefineGlobal { slot } => { 
+                debug!("Op: Define Global (0xC0) with slot: {:#X}", slot);
+                let sv = self.stack.peek().expect("no value on stack").clone();
+                if !self.observers.is_empty() {
+                    self.observe_global_define(slot, &sv);
                 }
-                self.current_offset += 2
-            },
-            0xC2 => { 
-                debug!("Op: Define Local (0xC2) with offset: {:#X}", self.current_chunk[self.current_offset+1] as usize);
-                let li = self.current_chunk[self.current_offset+1] as usize;
-                self.stack.set_local(li);
-                self.current_offset += 2
-            },
-            0xC3 => { 
-                debug!("Op: Read Local (0xC3) with offset: {:#X}", self.current_chunk[self.current_offset+1] as usize);
-                let li = self.current_chunk[self.current_offset+1] as usize;
-                self.stack.push(self.stack.get_local(li).expect("local does not exist when it should").clone());
-                self.current_offset += 2
-            },
-            0xD0 => {
-                debug!("Op: Jump Unconditional (0xD0) with offset: {:#X}", self.current_chunk[self.current_offset+1] as usize);
-                let local_jump_point = self.current_chunk[self.current_offset+1] as i8;
-                let (new_off, overflow) = self.current_offset.overflowing_add_signed(local_jump_point as isize);
-                if overflow {
-                    panic!()
-                }
-                self.current_offset = new_off;
+                self.stack.set_global(slot, sv);
+                self.stack.pop();
+                self.current_offset += 1
             },
-            0xD1 => {
-                debug!("Op: Jump If False Local (0xD1) with offset: {:#X}", self.current_chunk[self.current_offset+1] as usize);
-                let local_jump_point = self.current_chunk[self.current_offset+1] as i8;
-
-                // this op should not pop off the stack, we should instead emit an instruction to do that.
-                match self.stack.peek() {
-                    Some(ThetaValue::Bool(false)) => {
-                        debug!("jumping because top of stack is false");
-                        let (new_off, overflow) = self.current_offset.overflowing_add_signed(local_jump_point as isize);
-                        if overflow {
-                            panic!()
-                        }
-                        self.current_offset = new_off;
-                    },
-                    Some(ThetaValue::Bool(_)) => {
-                        debug!("not jumping, top of stack is not false");
-                        self.current_offset += 2;
-                    },
-                    _ => {
-                        error!("top of stack non-existent on JMPIFF instruction");
-                        panic!()
-                    }
-                }
+            Instruction::GetGlobal { slot } => { 
+                debug!("Op: Read Global (0xC1) with slot: {:#X}", slot);
+                let v = self.stack.get_global(slot).expect("global is not defined").clone();
+                self.stack.push(v);
+                self.current_offset += 1
             },
-            0xD2 => {
-                debug!("Op: Jump Unconditional Far (0xD2) with offset: {:#X}", self.current_chunk[self.current_offset+1] as usize);
-                let local_jump_point = isize::from_le_bytes(self.current_chunk[self.current_offset+1..self.current_offset+9].try_into().expect("8 ele slice not converted"));
-                let (new_off, overflow) = self.current_offset.overflowing_add_signed(local_jump_point);
-                if overflow {
-                    panic!()
-                }
-                self.current_offset = new_off;                
+            Instruction::Op(OpCode::DefineLocal { offset }) => { 
+                debug!("Op: Define Local (0xC2) with offset: {:#X}", offset);
+                self.stack.set_local(offset);
+                self.current_offset += 1
             },
-            0xD3 => {
-                debug!("Op: Jump If False Far (0xD3) with offset: {:#X}", self.current_chunk[self.current_offset+1] as usize);
-                let local_jump_point = isize::from_le_bytes(self.current_chunk[self.current_offset+1..self.current_offset+9].try_into().expect("8 ele slice not converted"));
+            Instruction::Op(OpCode::GetLocal { offset }) => { 
+                debug!("Op: Read Local (0xC3) with offset: {:#X}", offset);
+                self.stack.push(self.stack.get_local(offset).expect("local does not exist when it should").clone());
+                self.current_offset += 1
+            },
+            Instruction::Jump { target } => {
+                debug!("Op: Jump Unconditional to instruction {:#X}", target);
+                self.current_offset = target;
+            },
+            Instruction::JumpIfFalse { target } => {
+                debug!("Op: Jump If False to instruction {:#X}", target);
 
                 // this op should not pop off the stack, we should instead emit an instruction to do that.
                 match self.stack.peek() {
                     Some(ThetaValue::Bool(false)) => {
                         debug!("jumping because top of stack is false");
-                        let (new_off, overflow) = self.current_offset.overflowing_add_signed(local_jump_point);
-                        if overflow {
-                            panic!()
-                        }
-                        self.current_offset = new_off;                        
+                        self.current_offset = target;
                     },
                     Some(ThetaValue::Bool(_)) => {
                         debug!("not jumping, top of stack is not false");
-                        self.current_offset += 2;
+                        self.current_offset += 1;
                     },
                     _ => {
                         error!("top of stack non-existent on JMPIFF instruction");
@@ -416,82 +865,490 @@ impl VM {
                     }
                 }
             },
-            0xE0 => {
-                debug!("Op: Call Direct (0xE0) with offset: {:#X}", &self.current_chunk[self.current_offset+1]);
+            Instruction::Call { function } => {
+                debug!("Op: Call (0xE0) with function index: {:#X}", function);
+                self.call_function(function)?;
+            },
+            Instruction::Op(OpCode::CallDirect { name_offset: _ }) => {
+                debug!("Op: Call Direct (0xE0)");
+                // calls that could not be resolved when the chunk was loaded look the function up by name.
                 // on top of the stack should be either a function object or a symbol reference
-                let stack_top = self.stack.pop().expect("expected stack item");
-                // let constant = self.stack.curr_frame().expect("expected stack frame").bitstream.constants[chunk[offset+1] as usize].clone();
-
-                // TODO: function object
-                let func_name = match stack_top {
-                    ThetaValue::Pointer(hv) => match hv.as_ref() {
-                        ThetaHeapValue::Str(func_name) => func_name.clone(),
-                    },
-                    _ => panic!("non-string found at constant for func call")
-                };
-
-                // TODO: this should throw a runtime error
-                let func = self.function_table.get(&func_name).expect("function is not loaded");
-
-                let stack_size: usize = func.0.args.len();
-                let locals = &mut self.stack.curr_frame_mut().expect("need call frame").locals;
-                // cut out the params from the current stack
-                let params = locals.split_off(locals.len()-stack_size);
-
-                self.current_offset += 2;
-                self.stack.push_opt_frame(self.current_offset, func.1.clone(), func.0.chunk.clone(), params);
-                // let ck = func.0.chunk.clone();
-                // self.execute_code(&ck)?;
-                // self.stack.pop_frame();
-                (self.current_chunk, self.current_offset) = self.page_chunk();
-            }
-            0xFD => {
+                let function = self.pop_function();
+                self.call_function(function)?;
+            },
+            Instruction::TailCall { function } => {
+                debug!("Op: Tail Call (0xE1) with function index: {:#X}", function);
+                self.tail_call_function(function)?;
+            },
+            Instruction::Op(OpCode::TailCallDirect { name_offset: _ }) => {
+                debug!("Op: Tail Call Direct (0xE1)");
+                let function = self.pop_function();
+                self.tail_call_function(function)?;
+            },
+            Instruction::Op(OpCode::Noop) => {
                 debug!("Op: Noop (0xFD)");
                 self.current_offset += 1
             },
-            0xFE => {
+            Instruction::Op(OpCode::Breakpoint) => {
                 debug!("Op: Breakpoint (0xFE)");
                 self.current_offset += 1;
                 // yield control from the machine to the client
-                return Ok(false);
+                return Ok(Some(ExecutionStatus::Breakpoint));
             }
-            0xFF => { 
+            Instruction::Op(OpCode::DebugPrint) => { 
                 debug!("Op: Print (0xFF)"); 
-                writeln!(self.stdout, "{:?}", self.stack.pop())?; 
+                let value = self.stack.pop().expect("failed to grab value off stack");
+                writeln!(self.stdout, "{}", value)?;
                 self.current_offset += 1
             },
-            code => { 
-                debug!("Op: Unknown ({:#x})", code); 
-                panic!("unknown");
-                self.current_offset += 1
-            }
+            Instruction::Op(op) => panic!("instruction was not resolved when loaded: {}", op.human_readable()),
+        };
+
+        Ok(None)
+    }
+
+    /// Pops a string and parses it as a value of `ty`.
+    fn parse_string(&mut self, ty: TypeInformation, parse: impl Fn(&str) -> Option<ThetaValue>) -> Result<ThetaValue, RuntimeError> {
+        match self.stack.pop().expect("failed to grab value off stack") {
+            ThetaValue::Pointer(hv) => match hv.as_ref() {
+                ThetaHeapValue::Str(s) => parse(s.as_str()).ok_or_else(|| RuntimeError::InvalidConversion { input: s.to_string(), ty }),
+            },
+            _ => panic!("invalid operands"),
+        }
+    }
+
+    fn call_function(&mut self, function: usize) -> Result<(), RuntimeError> {
+        // TODO: this should throw a runtime error
+        let func = self.functions[function].as_ref().expect("function is not loaded");
+
+        if self.stack.depth() >= self.max_call_depth {
+            return Err(self.stack_overflow(StackLimit::CallDepth(self.max_call_depth), Some(function)));
+        }
+        self.check_stack_size(func.function.locals, Some(function))?;
+
+        // the arguments are already on top of the stack and become the first locals of the new frame
+        self.current_offset += 1;
+        self.stack.push_frame(self.current_offset, function, func.bitstream.clone(), func.code.clone(), func.function.args.len(), func.function.locals);
+        (self.current_chunk, self.current_offset) = self.page_chunk();
+        if !self.observers.is_empty() {
+            self.observe_call(false);
+        }
+        Ok(())
+    }
+
+    /// Reuses the current frame for the callee, so the callee returns directly to the current frame's caller.
+    fn tail_call_function(&mut self, function: usize) -> Result<(), RuntimeError> {
+        // TODO: this should throw a runtime error
+        let func = self.functions[function].as_ref().expect("function is not loaded");
+
+        // the current frame's locals are released before the callee's are allocated, so this only overestimates
+        self.check_stack_size(func.function.locals, Some(function))?;
+
+        self.stack.replace_frame(function, func.bitstream.clone(), func.code.clone(), func.function.args.len(), func.function.locals);
+        (self.current_chunk, self.current_offset) = self.page_chunk();
+        if !self.observers.is_empty() {
+            self.observe_call(true);
+        }
+        Ok(())
+    }
+
+    /// Fails if allocating `additional` values would take the value stack past its limit.
+    /// `callee` is the function the values are being allocated for, if they are for a new frame.
+    fn check_stack_size(&self, additional: usize, callee: Option<usize>) -> Result<(), RuntimeError> {
+        if self.stack.len() + additional > self.max_stack_size {
+            return Err(self.stack_overflow(StackLimit::StackSize(self.max_stack_size), callee));
+        }
+        Ok(())
+    }
+
+    fn stack_overflow(&self, limit: StackLimit, callee: Option<usize>) -> RuntimeError {
+        let name = |function: usize| self.functions[function].as_ref().map(|func| func.function.name.to_string());
+
+        let frames = callee.into_iter().map(name)
+            .chain(self.stack.frames().iter().rev().map(|frame| frame.function.and_then(name)))
+            .collect();
+
+        RuntimeError::StackOverflow { limit, backtrace: Backtrace::new(frames) }
+    }
+
+    /// Pops a function reference off the stack for calls that could not be resolved when the chunk was loaded.
+    fn pop_function(&mut self) -> usize {
+        // on top of the stack should be either a function object or a symbol reference
+        let stack_top = self.stack.pop().expect("expected stack item");
+
+        // TODO: function object
+        let func_name = match stack_top {
+            ThetaValue::Pointer(hv) => match hv.as_ref() {
+                ThetaHeapValue::Str(func_name) => func_name.clone(),
+            },
+            _ => panic!("non-string found at constant for func call")
         };
 
-        Ok(true)
+        self.function_index(&func_name)
     }
 
     #[inline(always)]
-    fn page_chunk(&mut self) -> (Rc<Vec<u8>>, usize) {
+    fn page_chunk(&mut self) -> (Rc<[Instruction]>, usize) {
+        // chunks are validated and decoded when they are loaded, so execution always starts at the first instruction
         let chunk = self.stack.curr_frame().expect("no frame").chunk.clone();
-        let mut offset = 0;
+        (chunk, 0)
+    }
+}
+
+// debugger API
+impl VM {
+    /// Sets a breakpoint on the instruction at `offset` in a loaded function.
+    /// Returns None if the function is not loaded or has no such instruction.
+    pub fn set_breakpoint(&mut self, function: &ThetaString, offset: usize) -> Option<CodeLocation> {
+        let index = *self.function_indices.get(function)?;
+        if offset >= self.functions[index].as_ref()?.code.len() {
+            return None;
+        }
+
+        let location = CodeLocation { function: index, offset };
+        self.breakpoints.insert(location);
+        Some(location)
+    }
 
-        debug!("chunk: {:X?}", chunk);
+    /// Sets a breakpoint on the first instruction of `line` in every loaded function with debug info for it.
+    pub fn set_line_breakpoint(&mut self, line: usize) -> Vec<CodeLocation> {
+        let locations: Vec<CodeLocation> = self.functions.iter().enumerate().filter_map(|(index, func)| {
+            let (offset, _) = func.as_ref()?.lines.iter().find(|(_, l)| *l == line)?;
+            Some(CodeLocation { function: index, offset: *offset })
+        }).collect();
 
-        // assert chunk header
-        assert!(chunk[0..8] == CHUNK_HEADER);
+        self.breakpoints.extend(locations.iter().copied());
+        locations
+    }
+
+    pub fn clear_breakpoint(&mut self, location: CodeLocation) -> bool {
+        self.breakpoints.remove(&location)
+    }
 
-        offset += 8;
-        
-        debug!("=== BEGIN CHUNK ===");
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
 
-        let chunk_size: usize = usize::from_le_bytes(chunk[offset..offset+8].try_into().expect("could not get chunk size"));
-        debug!("chunk size: {chunk_size}");
+    pub fn breakpoints(&self) -> &HashSet<CodeLocation> {
+        &self.breakpoints
+    }
 
-        offset += 8;
+    /// Executes a single instruction.
+    pub fn step_instruction(&mut self) -> Result<ExecutionStatus, RuntimeError> {
+        self.run(RunMode::StepInstruction)
+    }
 
-        debug!("-- BEGIN INSTRUCTIONS --");
+    /// Runs until the next source line of the current frame starts, without stopping inside calls.
+    /// In code without debug info this steps a single instruction, running calls to completion.
+    pub fn step_over(&mut self) -> Result<ExecutionStatus, RuntimeError> {
+        self.run(RunMode::StepOver(self.stack.depth()))
+    }
+
+    /// Runs until the next source line starts, stopping inside calls.
+    pub fn step_into(&mut self) -> Result<ExecutionStatus, RuntimeError> {
+        self.run(RunMode::StepInto(self.stack.depth()))
+    }
+
+    /// Runs until the current frame returns.
+    pub fn step_out(&mut self) -> Result<ExecutionStatus, RuntimeError> {
+        self.run(RunMode::StepOut(self.stack.depth()))
+    }
+
+    /// The location execution will continue from, if it is inside a loaded function.
+    pub fn current_location(&self) -> Option<CodeLocation> {
+        Some(CodeLocation { function: self.stack.curr_frame()?.function?, offset: self.current_offset })
+    }
+
+    /// Every frame on the call stack, innermost first.
+    pub fn frames(&self) -> Vec<FrameInfo<'_>> {
+        let frames = self.stack.frames();
+        let values = self.stack.values();
+
+        frames.iter().enumerate().rev().map(|(idx, frame)| {
+            // frames below the top are waiting on the call just before their callee's return address
+            let offset = match frames.get(idx + 1) {
+                Some(callee) => callee.rip - 1,
+                None => self.current_offset,
+            };
+            let end = frames.get(idx + 1).map_or(values.len(), |callee| callee.base);
+            let func = frame.function.and_then(|function| self.functions[function].as_ref());
+
+            let locals_end = match func {
+                Some(func) => (frame.base + func.function.args.len() + func.function.locals).min(end),
+                None => end,
+            };
+
+            FrameInfo {
+                function: func.map(|func| func.function.name.clone()),
+                function_index: frame.function,
+                offset,
+                line: func.and_then(|func| func.line_at(offset)),
+                locals: &values[frame.base..locals_end],
+            }
+        }).collect()
+    }
+
+    fn on_breakpoint(&self) -> bool {
+        !self.breakpoints.is_empty() && self.current_location().is_some_and(|location| self.breakpoints.contains(&location))
+    }
 
-        (chunk, offset)
+    /// Whether the next instruction starts a source line. Code without debug info treats every instruction as its own line.
+    fn starts_line(&self) -> bool {
+        match self.stack.curr_frame().and_then(|frame| frame.function).and_then(|function| self.functions[function].as_ref()) {
+            Some(func) if !func.lines.is_empty() => func.lines.iter().any(|(offset, _)| *offset == self.current_offset),
+            _ => true,
+        }
+    }
+}
+
+// snapshots
+impl VM {
+    /// Serializes the VM's state so it can be restored later, or by another process, with `restore`.
+    /// This covers globals, the heap, every loaded bitstream and function, and the call stack,
+    /// so a VM paused by a breakpoint or a budget resumes exactly where it left off.
+    /// The output and observers are not part of the state, nor is the deadline, which is only meaningful to this process.
+    pub fn snapshot(&self) -> Result<Vec<u8>, SnapshotError> {
+        let mut out = SnapshotWriter::new(&self.heap);
+
+        out.write_usize(self.heap.len());
+        for hv in &self.heap {
+            out.write_heap_value(hv);
+        }
+        out.write_usize(self.heap_used);
+        out.write_usize(self.heap_peak);
+        out.write_option_usize(self.heap_limit);
+
+        let bitstreams: HashMap<*const ThetaCompiledBitstream, usize> = self.loaded_bitstreams.iter().enumerate().map(|(idx, bs)| (Rc::as_ptr(bs), idx)).collect();
+        let bitstream_index = |bs: &Rc<ThetaCompiledBitstream>| bitstreams.get(&Rc::as_ptr(bs)).copied().ok_or(SnapshotError::UnknownBitstream);
+        out.write_usize(self.loaded_bitstreams.len());
+        for bs in &self.loaded_bitstreams {
+            out.write_usize(bs.constants.len());
+            for constant in &bs.constants {
+                out.write_value(constant);
+            }
+            out.write_usize(bs.functions.len());
+            for func in &bs.functions {
+                out.write_function(func);
+            }
+        }
+
+        // frames share their chunk with the function they run, so every chunk is written once and referred to by index
+        let mut chunks: Vec<&Rc<[Instruction]>> = vec![];
+        let mut chunk_indices: HashMap<*const Instruction, usize> = HashMap::new();
+        let mut chunk_index = |chunk| *chunk_indices.entry(Rc::as_ptr(chunk) as *const Instruction).or_insert_with(|| {
+            chunks.push(chunk);
+            chunks.len() - 1
+        });
+        let function_chunks: Vec<Option<usize>> = self.functions.iter().map(|func| func.as_ref().map(|func| chunk_index(&func.code))).collect();
+        let frame_chunks: Vec<usize> = self.stack.frames().iter().map(|frame| chunk_index(&frame.chunk)).collect();
+        let current_chunk = chunk_index(&self.current_chunk);
+
+        out.write_usize(chunks.len());
+        for chunk in chunks {
+            out.write_chunk(chunk)?;
+        }
+
+        out.write_usize(self.functions.len());
+        for (func, chunk) in self.functions.iter().zip(function_chunks) {
+            out.write_bool(func.is_some());
+            if let (Some(func), Some(chunk)) = (func, chunk) {
+                out.write_function(&func.function);
+                out.write_usize(bitstream_index(&func.bitstream)?);
+                out.write_usize(chunk);
+                out.write_lines(&func.lines);
+            }
+        }
+        // sorted, so the same state always gives the same snapshot
+        let mut function_indices: Vec<(&ThetaString, &usize)> = self.function_indices.iter().collect();
+        function_indices.sort_by_key(|(_, idx)| **idx);
+        out.write_usize(function_indices.len());
+        for (name, idx) in function_indices {
+            out.write_str(name);
+            out.write_usize(*idx);
+        }
+
+        out.write_usize(self.stack.global_values().len());
+        for global in self.stack.global_values() {
+            out.write_bool(global.is_some());
+            if let Some(value) = global {
+                out.write_value(value);
+            }
+        }
+        let mut global_slots: Vec<(&String, &usize)> = self.stack.global_slots().iter().collect();
+        global_slots.sort_by_key(|(_, slot)| **slot);
+        out.write_usize(global_slots.len());
+        for (name, slot) in global_slots {
+            out.write_str(name);
+            out.write_usize(*slot);
+        }
+
+        out.write_usize(self.stack.len());
+        for value in self.stack.values() {
+            out.write_value(value);
+        }
+        out.write_usize(self.stack.depth());
+        for (frame, chunk) in self.stack.frames().iter().zip(frame_chunks) {
+            out.write_usize(frame.rip);
+            out.write_usize(frame.base);
+            out.write_option_usize(frame.function);
+            out.write_usize(bitstream_index(&frame.bitstream)?);
+            out.write_usize(chunk);
+        }
+        out.write_usize(current_chunk);
+        out.write_usize(self.current_offset);
+
+        out.write_usize(self.max_call_depth);
+        out.write_usize(self.max_stack_size);
+        out.write_option_u64(self.fuel);
+        out.write_u64(self.executed);
+        let mut breakpoints: Vec<&CodeLocation> = self.breakpoints.iter().collect();
+        breakpoints.sort_by_key(|location| (location.function, location.offset));
+        out.write_usize(breakpoints.len());
+        for location in breakpoints {
+            out.write_usize(location.function);
+            out.write_usize(location.offset);
+        }
+        out.write_bool(self.at_breakpoint);
+
+        Ok(out.finish())
+    }
+
+    /// Rebuilds a VM from a snapshot taken with `snapshot`, writing its output to `stdout`.
+    /// Execution continues from where the snapshot was taken with `resume`.
+    pub fn restore(snapshot: &[u8], stdout: Box<dyn Write>) -> Result<VM, SnapshotError> {
+        let mut input = SnapshotReader::new(snapshot)?;
+        let mut machine = VM::new(stdout);
+
+        let len = input.read_usize()?;
+        let heap = (0..len).map(|_| Ok(Rc::new(input.read_heap_value()?))).collect::<Result<Vec<_>, SnapshotError>>()?;
+        machine.strings = heap.iter().map(|hv| match hv.as_ref() {
+            ThetaHeapValue::Str(s) => (s.clone(), hv.clone()),
+        }).collect();
+        input.set_heap(heap);
+        machine.heap = input.heap().to_vec();
+        machine.heap_used = input.read_usize()?;
+        machine.heap_peak = input.read_usize()?;
+        machine.heap_limit = input.read_option_usize()?;
+
+        let len = input.read_usize()?;
+        for _ in 0..len {
+            let len = input.read_usize()?;
+            let constants = (0..len).map(|_| input.read_value()).collect::<Result<_, _>>()?;
+            let len = input.read_usize()?;
+            let functions = (0..len).map(|_| input.read_function()).collect::<Result<_, _>>()?;
+            machine.loaded_bitstreams.push(Rc::new(ThetaCompiledBitstream::new_filled(constants, functions)));
+        }
+        let bitstream = |input: &mut SnapshotReader, bitstreams: &[Rc<ThetaCompiledBitstream>]| Ok::<_, SnapshotError>(bitstreams[input.read_index("bitstream", bitstreams.len())?].clone());
+
+        let len = input.read_usize()?;
+        let chunks = (0..len).map(|_| input.read_chunk()).collect::<Result<Vec<_>, _>>()?;
+        let chunk = |input: &mut SnapshotReader| Ok::<_, SnapshotError>(chunks[input.read_index("chunk", chunks.len())?].clone());
+
+        let len = input.read_usize()?;
+        for _ in 0..len {
+            let func = match input.read_bool()? {
+                true => Some(LoadedFunction {
+                    index: machine.functions.len(),
+                    function: input.read_function()?,
+                    bitstream: bitstream(&mut input, &machine.loaded_bitstreams)?,
+                    code: chunk(&mut input)?,
+                    lines: input.read_lines()?,
+                }),
+                false => None,
+            };
+            machine.functions.push(func);
+        }
+        let len = input.read_usize()?;
+        for _ in 0..len {
+            let name = ThetaString::new(input.read_string()?);
+            let idx = input.read_index("function", machine.functions.len())?;
+            machine.function_indices.insert(name, idx);
+        }
+
+        let len = input.read_usize()?;
+        let globals = (0..len).map(|_| Ok(if input.read_bool()? { Some(input.read_value()?) } else { None })).collect::<Result<Vec<_>, SnapshotError>>()?;
+        let len = input.read_usize()?;
+        let global_slots = (0..len).map(|_| Ok((input.read_string()?, input.read_index("global", globals.len())?))).collect::<Result<_, SnapshotError>>()?;
+
+        let len = input.read_usize()?;
+        let values = (0..len).map(|_| input.read_value()).collect::<Result<Vec<_>, _>>()?;
+        let len = input.read_usize()?;
+        let mut frames = vec![];
+        for _ in 0..len {
+            let rip = input.read_usize()?;
+            let base = input.read_index("stack value", values.len() + 1)?;
+            let function = input.read_option_usize()?;
+            if let Some(index) = function.filter(|function| *function >= machine.functions.len()) {
+                return Err(SnapshotError::InvalidReference { kind: "function", index });
+            }
+            // a function's frame holds its arguments and locals, which are read without checking
+            if let Some(func) = function.and_then(|function| machine.functions[function].as_ref()) {
+                let size = base.saturating_add(func.function.args.len()).saturating_add(func.function.locals);
+                if size > values.len() {
+                    return Err(SnapshotError::InvalidReference { kind: "stack value", index: size - 1 });
+                }
+            }
+            frames.push(ThetaCallFrame { rip, base, function, bitstream: bitstream(&mut input, &machine.loaded_bitstreams)?, chunk: chunk(&mut input)? });
+        }
+
+        // instructions are executed without checking what they refer to, so that is checked here instead
+        for code in &chunks {
+            for instruction in code.iter() {
+                let (kind, index, len) = match *instruction {
+                    Instruction::Jump { target } | Instruction::JumpIfFalse { target } => ("jump target", target, code.len() + 1),
+                    Instruction::Call { function } | Instruction::TailCall { function } => ("function", function, machine.functions.len()),
+                    Instruction::DefineGlobal { slot } | Instruction::GetGlobal { slot } => ("global", slot, globals.len()),
+                    Instruction::Op(_) => continue,
+                };
+                if index >= len {
+                    return Err(SnapshotError::InvalidReference { kind, index });
+                }
+            }
+        }
+        // constants are read from the bitstream of the frame running the code
+        let code_with_constants = machine.functions.iter().flatten().map(|func| (&func.code, &func.bitstream))
+            .chain(frames.iter().map(|frame| (&frame.chunk, &frame.bitstream)));
+        for (code, bitstream) in code_with_constants {
+            for instruction in code.iter() {
+                if let Instruction::Op(OpCode::Constant { offset }) = *instruction {
+                    if offset >= bitstream.constants.len() {
+                        return Err(SnapshotError::InvalidReference { kind: "constant", index: offset });
+                    }
+                }
+            }
+        }
+
+        machine.stack = ThetaStack::from_parts(globals, global_slots, values, frames);
+        machine.current_chunk = chunk(&mut input)?;
+        machine.current_offset = input.read_usize()?;
+
+        machine.max_call_depth = input.read_usize()?;
+        machine.max_stack_size = input.read_usize()?;
+        machine.fuel = input.read_option_u64()?;
+        machine.executed = input.read_u64()?;
+        let len = input.read_usize()?;
+        for _ in 0..len {
+            let function = input.read_index("function", machine.functions.len())?;
+            machine.breakpoints.insert(CodeLocation { function, offset: input.read_usize()? });
+        }
+        machine.at_breakpoint = input.read_bool()?;
+
+        if !input.is_finished() {
+            return Err(SnapshotError::TrailingData(input.position()));
+        }
+        Ok(machine)
+    }
+
+    /// Puts the VM back into the state of a snapshot, e.g. to undo an instruction that failed.
+    /// Unlike `restore`, the VM keeps its output, observers and deadline.
+    pub fn rollback(&mut self, snapshot: &[u8]) -> Result<(), SnapshotError> {
+        let mut restored = VM::restore(snapshot, Box::new(std::io::sink()))?;
+        std::mem::swap(&mut restored.stdout, &mut self.stdout);
+        restored.observers = std::mem::take(&mut self.observers);
+        restored.next_observer = self.next_observer;
+        restored.deadline = self.deadline;
+        *self = restored;
+        Ok(())
     }
 }
 
@@ -1,4 +1,18 @@
 mod machine;
 mod call_frame;
+mod instruction;
+mod error;
+mod debugger;
+mod trace;
+mod profile;
+mod observer;
+mod snapshot;
 pub use self::machine::*;
-pub use self::call_frame::*;
\ No newline at end of file
+pub use self::call_frame::*;
+pub use self::instruction::*;
+pub use self::error::*;
+pub use self::debugger::{CodeLocation, FrameInfo};
+pub use self::trace::{Tracer, DEFAULT_TRACE_STACK_VALUES};
+pub use self::profile::{Profiler, Profile, FunctionProfile, DEFAULT_PROFILE_SAMPLE_INTERVAL, TOP_LEVEL_NAME};
+pub use self::observer::{VmObserver, ObserverId};
+pub use self::snapshot::{SnapshotError, SNAPSHOT_HEADER, SNAPSHOT_VERSION};
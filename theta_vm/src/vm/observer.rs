@@ -0,0 +1,75 @@
+use std::{cell::RefCell, rc::Rc};
+
+use theta_types::bytecode::{ThetaValue, ThetaHeapValue};
+
+use super::{Instruction, LoadedFunction};
+
+/// Identifies an observer registered with `VM::add_observer`, so it can be removed again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ObserverId(pub(super) usize);
+
+/// Callbacks for instrumenting a VM, registered with `VM::add_observer`.
+///
+/// Every callback does nothing by default, so an observer only implements the events it needs.
+/// `function` is None for top level chunks, which do not belong to a function.
+pub trait VmObserver {
+    /// Called for every function loaded into the VM, including the ones loaded before the observer was added.
+    fn on_load(&mut self, _function: &LoadedFunction) {}
+
+    /// Called each time the VM starts or resumes running code.
+    fn on_resume(&mut self) {}
+
+    /// Called before each instruction executes. `stack` is the whole value stack, topmost value last.
+    fn on_instruction(&mut self, _function: Option<&LoadedFunction>, _offset: usize, _instruction: Instruction, _stack: &[ThetaValue]) {}
+
+    /// Called once the callee's frame has been pushed, with the arguments it was called with.
+    /// A tail call replaces the caller's frame, so the caller gets no `on_return`.
+    fn on_call(&mut self, _function: &LoadedFunction, _args: &[ThetaValue], _tail_call: bool) {}
+
+    /// Called before a frame returns. `value` is None for frames that return nothing.
+    fn on_return(&mut self, _function: Option<&LoadedFunction>, _value: Option<&ThetaValue>) {}
+
+    /// Called when every frame is dropped at once, after a runtime error or `VM::abort`.
+    /// The dropped frames get no `on_return`, so observers that follow the call stack should clear it here.
+    fn on_unwind(&mut self) {}
+
+    fn on_global_define(&mut self, _name: &str, _value: &ThetaValue) {}
+
+    /// Called when a value is allocated on the heap, with the number of bytes it takes.
+    fn on_alloc(&mut self, _value: &ThetaHeapValue, _size: usize) {}
+}
+
+/// Lets the caller keep a handle to an observer while the VM holds it, e.g. to finish a `Tracer` or `Profiler` afterwards.
+impl<T: VmObserver + ?Sized> VmObserver for Rc<RefCell<T>> {
+    fn on_load(&mut self, function: &LoadedFunction) {
+        self.borrow_mut().on_load(function)
+    }
+
+    fn on_resume(&mut self) {
+        self.borrow_mut().on_resume()
+    }
+
+    fn on_instruction(&mut self, function: Option<&LoadedFunction>, offset: usize, instruction: Instruction, stack: &[ThetaValue]) {
+        self.borrow_mut().on_instruction(function, offset, instruction, stack)
+    }
+
+    fn on_call(&mut self, function: &LoadedFunction, args: &[ThetaValue], tail_call: bool) {
+        self.borrow_mut().on_call(function, args, tail_call)
+    }
+
+    fn on_return(&mut self, function: Option<&LoadedFunction>, value: Option<&ThetaValue>) {
+        self.borrow_mut().on_return(function, value)
+    }
+
+    fn on_unwind(&mut self) {
+        self.borrow_mut().on_unwind()
+    }
+
+    fn on_global_define(&mut self, name: &str, value: &ThetaValue) {
+        self.borrow_mut().on_global_define(name, value)
+    }
+
+    fn on_alloc(&mut self, value: &ThetaHeapValue, size: usize) {
+        self.borrow_mut().on_alloc(value, size)
+    }
+}
@@ -0,0 +1,240 @@
+use std::{collections::HashMap, fmt, io::Write, time::{Duration, Instant}};
+
+use theta_types::bytecode::ThetaValue;
+
+use super::{Instruction, LoadedFunction, VmObserver};
+
+/// How many instructions are executed between stack samples by default.
+pub const DEFAULT_PROFILE_SAMPLE_INTERVAL: u64 = 100;
+/// The name used for frames running a top level chunk, which have no function.
+pub const TOP_LEVEL_NAME: &str = "<top level>";
+
+#[derive(Debug, Clone, Default)]
+struct Counters {
+    calls: u64,
+    instructions: u64,
+    inclusive: Duration,
+    exclusive: Duration,
+}
+
+/// Profiles the code a VM executes.
+///
+/// Instructions and calls are counted exactly for every function. Every `sample_interval` instructions the frames
+/// on the stack are sampled: the time since the last sample is charged to the function on top of the stack
+/// as exclusive time, and to every function on the stack as inclusive time.
+/// The sampled stacks are also kept for flamegraphs.
+///
+/// A profiler is a `VmObserver`, so it starts profiling once it is added to a VM.
+#[derive(Debug, Clone)]
+pub struct Profiler {
+    sample_interval: u64,
+    until_sample: u64,
+    last_sample: Instant,
+    // indexed by function index plus one. top level chunks are counted at 0.
+    counters: Vec<Counters>,
+    stacks: HashMap<Vec<Option<usize>>, u64>,
+    // indexed by function index
+    names: Vec<Option<String>>,
+    // the function of every frame on the VM's stack, outermost first, followed through calls and returns
+    frames: Vec<Option<usize>>,
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler {
+            sample_interval: DEFAULT_PROFILE_SAMPLE_INTERVAL,
+            until_sample: DEFAULT_PROFILE_SAMPLE_INTERVAL,
+            last_sample: Instant::now(),
+            counters: Vec::new(),
+            stacks: HashMap::new(),
+            names: Vec::new(),
+            frames: Vec::new(),
+        }
+    }
+
+    /// Sets how many instructions are executed between stack samples. Longer intervals are cheaper but less precise.
+    pub fn with_sample_interval(mut self, sample_interval: u64) -> Profiler {
+        self.sample_interval = sample_interval.max(1);
+        self.until_sample = self.sample_interval;
+        self
+    }
+
+    pub fn sample_interval(&self) -> u64 {
+        self.sample_interval
+    }
+
+    /// Counts an instruction about to be executed by `function`. Returns whether the stack should be sampled.
+    fn count(&mut self, function: Option<usize>) -> bool {
+        self.counters(function).instructions += 1;
+
+        self.until_sample -= 1;
+        if self.until_sample == 0 {
+            self.until_sample = self.sample_interval;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Records the functions of the frames on the stack, outermost first.
+    fn sample(&mut self, stack: Vec<Option<usize>>) {
+        let now = Instant::now();
+        let elapsed = now - self.last_sample;
+        self.last_sample = now;
+
+        if let Some(top) = stack.last() {
+            self.counters(*top).exclusive += elapsed;
+        }
+        // recursive functions appear several times, but the time is only spent once
+        let mut seen = Vec::with_capacity(stack.len());
+        for function in &stack {
+            if !seen.contains(function) {
+                seen.push(*function);
+                self.counters(*function).inclusive += elapsed;
+            }
+        }
+
+        *self.stacks.entry(stack).or_default() += 1;
+    }
+
+    fn counters(&mut self, function: Option<usize>) -> &mut Counters {
+        let idx = function.map_or(0, |function| function + 1);
+        if idx >= self.counters.len() {
+            self.counters.resize(idx + 1, Counters::default());
+        }
+        &mut self.counters[idx]
+    }
+
+    /// Turns the counters into a report.
+    pub fn finish(self) -> Profile {
+        let names = self.names;
+        let name = |function: Option<usize>| match function {
+            Some(function) => names.get(function).cloned().flatten().unwrap_or_else(|| format!("<function {}>", function)),
+            None => String::from(TOP_LEVEL_NAME),
+        };
+
+        // functions that were never loaded or called have nothing to report
+        let counters = self.counters.into_iter().enumerate().filter(|(_, counters)| counters.calls > 0 || counters.instructions > 0);
+        let mut functions: Vec<FunctionProfile> = counters.map(|(idx, counters)| FunctionProfile {
+            name: name(idx.checked_sub(1)),
+            calls: counters.calls,
+            instructions: counters.instructions,
+            inclusive: counters.inclusive,
+            exclusive: counters.exclusive,
+        }).collect();
+        functions.sort_by(|a, b| b.exclusive.cmp(&a.exclusive).then(b.instructions.cmp(&a.instructions)).then(a.name.cmp(&b.name)));
+
+        let mut stacks: Vec<(Vec<String>, u64)> = self.stacks.into_iter().map(|(stack, samples)| (stack.into_iter().map(name).collect(), samples)).collect();
+        stacks.sort();
+
+        Profile { functions, stacks }
+    }
+}
+
+impl VmObserver for Profiler {
+    fn on_load(&mut self, function: &LoadedFunction) {
+        if function.index >= self.names.len() {
+            self.names.resize(function.index + 1, None);
+        }
+        self.names[function.index] = Some(function.function.name.internal().to_string());
+    }
+
+    /// Restarts the clock, so time spent while the VM was not running is not charged to any function.
+    fn on_resume(&mut self) {
+        self.last_sample = Instant::now();
+    }
+
+    fn on_instruction(&mut self, function: Option<&LoadedFunction>, _offset: usize, _instruction: Instruction, _stack: &[ThetaValue]) {
+        let function = function.map(|func| func.index);
+        // the frame that was running when the profiler was added
+        if self.frames.is_empty() {
+            self.frames.push(function);
+        }
+
+        if self.count(function) {
+            self.sample(self.frames.clone());
+        }
+    }
+
+    fn on_call(&mut self, function: &LoadedFunction, _args: &[ThetaValue], tail_call: bool) {
+        self.counters(Some(function.index)).calls += 1;
+        if tail_call {
+            self.frames.pop();
+        }
+        self.frames.push(Some(function.index));
+    }
+
+    fn on_return(&mut self, _function: Option<&LoadedFunction>, _value: Option<&ThetaValue>) {
+        self.frames.pop();
+    }
+
+    fn on_unwind(&mut self) {
+        self.frames.clear();
+    }
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// What a profiled function did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionProfile {
+    pub name: String,
+    pub calls: u64,
+    /// Instructions executed in the function itself, excluding its callees.
+    pub instructions: u64,
+    /// Time with the function anywhere on the stack.
+    pub inclusive: Duration,
+    /// Time with the function on top of the stack.
+    pub exclusive: Duration,
+}
+
+/// The results of profiling, with every function named.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Profile {
+    /// Every function that executed, the most expensive first.
+    pub functions: Vec<FunctionProfile>,
+    /// Every sampled stack, outermost function first, with the number of times it was sampled.
+    pub stacks: Vec<(Vec<String>, u64)>,
+}
+
+impl Profile {
+    pub fn function(&self, name: &str) -> Option<&FunctionProfile> {
+        self.functions.iter().find(|function| function.name == name)
+    }
+
+    pub fn instructions(&self) -> u64 {
+        self.functions.iter().map(|function| function.instructions).sum()
+    }
+
+    /// Writes the sampled stacks in the folded format read by flamegraph tools, one `outer;inner samples` per line.
+    pub fn write_folded(&self, out: &mut impl Write) -> std::io::Result<()> {
+        for (stack, samples) in &self.stacks {
+            writeln!(out, "{} {}", stack.join(";"), samples)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let instructions = self.instructions().max(1);
+        let millis = |duration: Duration| duration.as_secs_f64() * 1000.0;
+
+        writeln!(f, "{:>10} {:>14} {:>8} {:>12} {:>12}  function", "calls", "instructions", "%", "self ms", "total ms")?;
+        for function in &self.functions {
+            writeln!(f, "{:>10} {:>14} {:>7.2}% {:>12.3} {:>12.3}  {}",
+                function.calls,
+                function.instructions,
+                function.instructions as f64 * 100.0 / instructions as f64,
+                millis(function.exclusive),
+                millis(function.inclusive),
+                function.name,
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,387 @@
+use std::{collections::HashMap, error::Error, fmt, rc::Rc};
+
+use theta_types::{bytecode::{ThetaValue, ThetaHeapValue, ThetaString, ThetaCompiledFunction, ThetaFuncArg, Symbol, OpCode, AssembleError, DisassembleError}, types::TypeInformation};
+
+use super::Instruction;
+
+pub const SNAPSHOT_HEADER: [u8; 8] = [84, 104, 101, 83, 110, 97, 112, 33];
+/// Bumped whenever the layout of a snapshot changes. Snapshots from other versions are refused.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+const DOUBLE_TAG: u8 = 0;
+const INT_TAG: u8 = 1;
+const BOOL_TAG: u8 = 2;
+// a pointer to a value on the heap, by its index in the heap
+const HEAP_TAG: u8 = 3;
+// a pointer to a string that is not on the heap, written out in full
+const STRING_TAG: u8 = 4;
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    InvalidHeader(Vec<u8>),
+    UnsupportedVersion(u32),
+    /// The snapshot ended early, at the given byte.
+    Truncated(usize),
+    /// The snapshot continues past its end, from the given byte.
+    TrailingData(usize),
+    InvalidTag { kind: &'static str, tag: u8 },
+    InvalidReference { kind: &'static str, index: usize },
+    Utf8Error(std::string::FromUtf8Error),
+    AssembleError(AssembleError),
+    DisassembleError(DisassembleError),
+    /// A frame or function refers to a bitstream that was never loaded into the VM, so it cannot be written.
+    UnknownBitstream,
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::InvalidHeader(header) => write!(f, "not a snapshot, header is {:?}", header),
+            SnapshotError::UnsupportedVersion(version) => write!(f, "snapshot version {} is not supported, expected {}", version, SNAPSHOT_VERSION),
+            SnapshotError::Truncated(at) => write!(f, "snapshot truncated at byte {}", at),
+            SnapshotError::TrailingData(at) => write!(f, "unexpected data after the end of the snapshot at byte {}", at),
+            SnapshotError::InvalidTag { kind, tag } => write!(f, "invalid {} tag: {:#X}", kind, tag),
+            SnapshotError::InvalidReference { kind, index } => write!(f, "reference to {} {} which does not exist", kind, index),
+            SnapshotError::Utf8Error(utf) => write!(f, "UTF-8 error: {}", utf),
+            SnapshotError::AssembleError(ae) => write!(f, "could not encode instruction: {}", ae),
+            SnapshotError::DisassembleError(de) => write!(f, "could not decode instruction: {}", de),
+            SnapshotError::UnknownBitstream => write!(f, "code refers to a bitstream that is not loaded into the VM"),
+        }
+    }
+}
+
+impl Error for SnapshotError {}
+
+impl From<std::string::FromUtf8Error> for SnapshotError {
+    fn from(e: std::string::FromUtf8Error) -> Self {
+        SnapshotError::Utf8Error(e)
+    }
+}
+
+impl From<AssembleError> for SnapshotError {
+    fn from(e: AssembleError) -> Self {
+        SnapshotError::AssembleError(e)
+    }
+}
+
+impl From<DisassembleError> for SnapshotError {
+    fn from(e: DisassembleError) -> Self {
+        SnapshotError::DisassembleError(e)
+    }
+}
+
+/// Writes the pieces of a snapshot. Every integer is written as 8 little endian bytes, whatever its type.
+pub(super) struct SnapshotWriter {
+    bytes: Vec<u8>,
+    // heap values are shared, so pointers to them are written as their index in the heap
+    heap: HashMap<*const ThetaHeapValue, usize>,
+}
+
+impl SnapshotWriter {
+    pub(super) fn new(heap: &[Rc<ThetaHeapValue>]) -> SnapshotWriter {
+        let mut bytes = SNAPSHOT_HEADER.to_vec();
+        bytes.extend_from_slice(&SNAPSHOT_VERSION.to_le_bytes());
+        let heap = heap.iter().enumerate().map(|(idx, hv)| (Rc::as_ptr(hv), idx)).collect();
+        SnapshotWriter { bytes, heap }
+    }
+
+    pub(super) fn finish(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    pub(super) fn write_u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    pub(super) fn write_bool(&mut self, value: bool) {
+        self.bytes.push(value as u8);
+    }
+
+    pub(super) fn write_u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(super) fn write_usize(&mut self, value: usize) {
+        self.write_u64(value as u64);
+    }
+
+    pub(super) fn write_option_u64(&mut self, value: Option<u64>) {
+        self.write_bool(value.is_some());
+        if let Some(value) = value {
+            self.write_u64(value);
+        }
+    }
+
+    pub(super) fn write_option_usize(&mut self, value: Option<usize>) {
+        self.write_option_u64(value.map(|value| value as u64));
+    }
+
+    pub(super) fn write_str(&mut self, value: &str) {
+        self.write_usize(value.len());
+        self.bytes.extend_from_slice(value.as_bytes());
+    }
+
+    pub(super) fn write_heap_value(&mut self, value: &ThetaHeapValue) {
+        match value {
+            ThetaHeapValue::Str(s) => self.write_str(s),
+        }
+    }
+
+    pub(super) fn write_value(&mut self, value: &ThetaValue) {
+        match value {
+            ThetaValue::Double(d) => {
+                self.write_u8(DOUBLE_TAG);
+                self.write_u64(d.to_bits());
+            },
+            ThetaValue::Int(i) => {
+                self.write_u8(INT_TAG);
+                self.write_u64(*i as u64);
+            },
+            ThetaValue::Bool(b) => {
+                self.write_u8(BOOL_TAG);
+                self.write_bool(*b);
+            },
+            ThetaValue::Pointer(hv) => match self.heap.get(&Rc::as_ptr(hv)).copied() {
+                Some(idx) => {
+                    self.write_u8(HEAP_TAG);
+                    self.write_usize(idx);
+                },
+                None => {
+                    self.write_u8(STRING_TAG);
+                    self.write_heap_value(hv);
+                },
+            },
+        }
+    }
+
+    pub(super) fn write_type(&mut self, ty: &TypeInformation) {
+        // literal types use the same codes as the assembler
+        match ty {
+            TypeInformation::None => self.write_u8(0x0),
+            TypeInformation::Boolean => self.write_u8(0x1),
+            TypeInformation::Int => self.write_u8(0x2),
+            TypeInformation::Float => self.write_u8(0x3),
+            TypeInformation::String => self.write_u8(0x4),
+            TypeInformation::NonLiteral(symbol) => {
+                self.write_u8(0x5);
+                self.write_str(symbol.id());
+            },
+            TypeInformation::Function(return_ty, args) => {
+                self.write_u8(0x6);
+                self.write_type(return_ty);
+                self.write_usize(args.len());
+                for arg in args {
+                    self.write_type(arg);
+                }
+            },
+        }
+    }
+
+    pub(super) fn write_function(&mut self, function: &ThetaCompiledFunction) {
+        self.write_str(&function.name);
+        self.write_usize(function.args.len());
+        for arg in &function.args {
+            self.write_type(&arg.ty);
+        }
+        self.write_type(&function.return_ty);
+        self.write_usize(function.locals);
+        self.write_usize(function.chunk.len());
+        self.bytes.extend_from_slice(&function.chunk);
+        self.write_lines(&function.lines);
+    }
+
+    pub(super) fn write_lines(&mut self, lines: &[(usize, usize)]) {
+        self.write_usize(lines.len());
+        for (offset, line) in lines {
+            self.write_usize(*offset);
+            self.write_usize(*line);
+        }
+    }
+
+    pub(super) fn write_chunk(&mut self, chunk: &[Instruction]) -> Result<(), SnapshotError> {
+        self.write_usize(chunk.len());
+        for instruction in chunk {
+            match *instruction {
+                Instruction::Op(op) => {
+                    self.write_u8(0);
+                    self.bytes.extend_from_slice(&op.encode()?);
+                },
+                Instruction::Jump { target } => self.write_operand(1, target),
+                Instruction::JumpIfFalse { target } => self.write_operand(2, target),
+                Instruction::Call { function } => self.write_operand(3, function),
+                Instruction::TailCall { function } => self.write_operand(4, function),
+                Instruction::DefineGlobal { slot } => self.write_operand(5, slot),
+                Instruction::GetGlobal { slot } => self.write_operand(6, slot),
+            }
+        }
+        Ok(())
+    }
+
+    fn write_operand(&mut self, tag: u8, operand: usize) {
+        self.write_u8(tag);
+        self.write_usize(operand);
+    }
+}
+
+/// Reads back the pieces written by `SnapshotWriter`, in the same order.
+pub(super) struct SnapshotReader<'a> {
+    bytes: &'a [u8],
+    at: usize,
+    heap: Vec<Rc<ThetaHeapValue>>,
+}
+
+impl<'a> SnapshotReader<'a> {
+    pub(super) fn new(bytes: &'a [u8]) -> Result<SnapshotReader<'a>, SnapshotError> {
+        let mut reader = SnapshotReader { bytes, at: 0, heap: vec![] };
+
+        let header = reader.take(SNAPSHOT_HEADER.len())?;
+        if header != SNAPSHOT_HEADER {
+            return Err(SnapshotError::InvalidHeader(header.to_vec()));
+        }
+        let version = u32::from_le_bytes(reader.take(4)?.try_into().expect("took 4 bytes"));
+        if version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+
+        Ok(reader)
+    }
+
+    /// Sets the heap that pointers are read from. Values can only be read once it is set.
+    pub(super) fn set_heap(&mut self, heap: Vec<Rc<ThetaHeapValue>>) {
+        self.heap = heap;
+    }
+
+    pub(super) fn heap(&self) -> &[Rc<ThetaHeapValue>] {
+        &self.heap
+    }
+
+    /// The byte the next read starts at.
+    pub(super) fn position(&self) -> usize {
+        self.at
+    }
+
+    /// Whether every byte of the snapshot has been read.
+    pub(super) fn is_finished(&self) -> bool {
+        self.at == self.bytes.len()
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], SnapshotError> {
+        let bytes = self.bytes.get(self.at..self.at.saturating_add(len)).ok_or(SnapshotError::Truncated(self.bytes.len()))?;
+        self.at += len;
+        Ok(bytes)
+    }
+
+    pub(super) fn read_u8(&mut self) -> Result<u8, SnapshotError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(super) fn read_bool(&mut self) -> Result<bool, SnapshotError> {
+        match self.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            tag => Err(SnapshotError::InvalidTag { kind: "bool", tag }),
+        }
+    }
+
+    pub(super) fn read_u64(&mut self) -> Result<u64, SnapshotError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().expect("took 8 bytes")))
+    }
+
+    pub(super) fn read_usize(&mut self) -> Result<usize, SnapshotError> {
+        Ok(self.read_u64()? as usize)
+    }
+
+    /// Reads an index into something with `len` entries, failing if it is out of bounds.
+    pub(super) fn read_index(&mut self, kind: &'static str, len: usize) -> Result<usize, SnapshotError> {
+        let index = self.read_usize()?;
+        if index >= len {
+            return Err(SnapshotError::InvalidReference { kind, index });
+        }
+        Ok(index)
+    }
+
+    pub(super) fn read_option_u64(&mut self) -> Result<Option<u64>, SnapshotError> {
+        Ok(if self.read_bool()? { Some(self.read_u64()?) } else { None })
+    }
+
+    pub(super) fn read_option_usize(&mut self) -> Result<Option<usize>, SnapshotError> {
+        Ok(self.read_option_u64()?.map(|value| value as usize))
+    }
+
+    pub(super) fn read_string(&mut self) -> Result<String, SnapshotError> {
+        let len = self.read_usize()?;
+        Ok(String::from_utf8(self.take(len)?.to_vec())?)
+    }
+
+    pub(super) fn read_heap_value(&mut self) -> Result<ThetaHeapValue, SnapshotError> {
+        Ok(ThetaHeapValue::Str(ThetaString::new(self.read_string()?)))
+    }
+
+    pub(super) fn read_value(&mut self) -> Result<ThetaValue, SnapshotError> {
+        Ok(match self.read_u8()? {
+            DOUBLE_TAG => ThetaValue::Double(f64::from_bits(self.read_u64()?)),
+            INT_TAG => ThetaValue::Int(self.read_u64()? as i64),
+            BOOL_TAG => ThetaValue::Bool(self.read_bool()?),
+            HEAP_TAG => {
+                let idx = self.read_index("heap value", self.heap.len())?;
+                ThetaValue::Pointer(self.heap[idx].clone())
+            },
+            STRING_TAG => ThetaValue::Pointer(Rc::new(self.read_heap_value()?)),
+            tag => return Err(SnapshotError::InvalidTag { kind: "value", tag }),
+        })
+    }
+
+    pub(super) fn read_type(&mut self) -> Result<TypeInformation, SnapshotError> {
+        Ok(match self.read_u8()? {
+            0x0 => TypeInformation::None,
+            0x1 => TypeInformation::Boolean,
+            0x2 => TypeInformation::Int,
+            0x3 => TypeInformation::Float,
+            0x4 => TypeInformation::String,
+            0x5 => TypeInformation::NonLiteral(Symbol::from(self.read_string()?)),
+            0x6 => {
+                let return_ty = self.read_type()?;
+                let len = self.read_usize()?;
+                let args = (0..len).map(|_| self.read_type()).collect::<Result<_, _>>()?;
+                TypeInformation::Function(Box::new(return_ty), args)
+            },
+            tag => return Err(SnapshotError::InvalidTag { kind: "type", tag }),
+        })
+    }
+
+    pub(super) fn read_function(&mut self) -> Result<ThetaCompiledFunction, SnapshotError> {
+        let name = ThetaString::new(self.read_string()?);
+        let len = self.read_usize()?;
+        let args = (0..len).map(|_| Ok(ThetaFuncArg::from(self.read_type()?))).collect::<Result<_, SnapshotError>>()?;
+        let return_ty = self.read_type()?;
+        let locals = self.read_usize()?;
+        let len = self.read_usize()?;
+        let chunk = Rc::new(self.take(len)?.to_vec());
+        let lines = self.read_lines()?;
+
+        Ok(ThetaCompiledFunction { args, chunk, name, return_ty, locals, lines })
+    }
+
+    pub(super) fn read_lines(&mut self) -> Result<Vec<(usize, usize)>, SnapshotError> {
+        let len = self.read_usize()?;
+        (0..len).map(|_| Ok((self.read_usize()?, self.read_usize()?))).collect()
+    }
+
+    pub(super) fn read_chunk(&mut self) -> Result<Rc<[Instruction]>, SnapshotError> {
+        let len = self.read_usize()?;
+        (0..len).map(|_| Ok(match self.read_u8()? {
+            0 => {
+                let (op, size) = OpCode::decode(&self.bytes[self.at..])?;
+                self.at += size;
+                Instruction::Op(op)
+            },
+            1 => Instruction::Jump { target: self.read_usize()? },
+            2 => Instruction::JumpIfFalse { target: self.read_usize()? },
+            3 => Instruction::Call { function: self.read_usize()? },
+            4 => Instruction::TailCall { function: self.read_usize()? },
+            5 => Instruction::DefineGlobal { slot: self.read_usize()? },
+            6 => Instruction::GetGlobal { slot: self.read_usize()? },
+            tag => return Err(SnapshotError::InvalidTag { kind: "instruction", tag }),
+        })).collect()
+    }
+}
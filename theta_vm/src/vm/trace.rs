@@ -0,0 +1,176 @@
+use std::{io::{BufWriter, Write}, rc::Rc};
+
+use theta_types::bytecode::{ThetaValue, ThetaHeapValue};
+
+use super::{Instruction, LoadedFunction, VmObserver};
+
+/// How many values from the top of the stack each trace record includes by default.
+pub const DEFAULT_TRACE_STACK_VALUES: usize = 3;
+
+/// Records every instruction the VM executes as one JSON object per line:
+///
+/// ```text
+/// {"function":"double","offset":2,"line":2,"op":"Multiply","code":6,"text":"Multiply","stack":[4,2]}
+/// ```
+///
+/// `function` and `line` are null for top level chunks and code without debug info. `code` is the OpCode's byte,
+/// or null for instructions the VM resolved while loading, such as calls to known functions.
+/// `stack` holds the values on top of the stack before the instruction runs, topmost first.
+///
+/// A tracer is a `VmObserver`, so it starts recording once it is added to a VM.
+pub struct Tracer {
+    output: BufWriter<Box<dyn Write>>,
+    stack_values: usize,
+    // indexed by function index, so resolved calls can be named
+    names: Vec<Option<Rc<str>>>,
+    // the first write error. tracing stops once writing fails, the error is reported by `finish`.
+    error: Option<std::io::Error>,
+}
+
+/// One executed instruction, as seen by a `Tracer`.
+struct TraceRecord<'a> {
+    pub function: Option<&'a str>,
+    pub offset: usize,
+    pub line: Option<usize>,
+    pub instruction: Instruction,
+    /// The name of the function a resolved call goes to.
+    pub callee: Option<&'a str>,
+    pub stack: &'a [ThetaValue],
+}
+
+impl Tracer {
+    pub fn new(output: Box<dyn Write>) -> Tracer {
+        Tracer { output: BufWriter::new(output), stack_values: DEFAULT_TRACE_STACK_VALUES, names: Vec::new(), error: None }
+    }
+
+    /// Sets how many values from the top of the stack each record includes.
+    pub fn with_stack_values(mut self, stack_values: usize) -> Tracer {
+        self.stack_values = stack_values;
+        self
+    }
+
+    pub fn stack_values(&self) -> usize {
+        self.stack_values
+    }
+
+    /// Flushes the trace, returning the first error that occured while writing it.
+    pub fn finish(&mut self) -> std::io::Result<()> {
+        match self.error.take() {
+            Some(e) => Err(e),
+            None => self.output.flush(),
+        }
+    }
+
+    fn record(&mut self, record: &TraceRecord) {
+        if self.error.is_some() {
+            return;
+        }
+        if let Err(e) = self.write_record(record) {
+            self.error = Some(e);
+        }
+    }
+
+    fn write_record(&mut self, record: &TraceRecord) -> std::io::Result<()> {
+        let out = &mut self.output;
+
+        write!(out, "{{\"function\":")?;
+        match record.function {
+            Some(name) => write_string(out, name)?,
+            None => write!(out, "null")?,
+        }
+        write!(out, ",\"offset\":{},\"line\":", record.offset)?;
+        match record.line {
+            Some(line) => write!(out, "{}", line)?,
+            None => write!(out, "null")?,
+        }
+
+        let callee = record.callee.unwrap_or("?");
+        let (op, code, text) = match record.instruction {
+            Instruction::Op(op) => {
+                // the Debug name without operands, e.g. `Constant` for `Constant { offset: 1 }`
+                let name = format!("{:?}", op);
+                let name = name.split([' ', '{']).next().unwrap_or_default().to_string();
+                (name, Some(op.as_hexcode()), op.human_readable())
+            },
+            Instruction::Jump { target } => (String::from("Jump"), None, format!("Jump to {}", target)),
+            Instruction::JumpIfFalse { target } => (String::from("JumpIfFalse"), None, format!("Jump if false to {}", target)),
+            Instruction::Call { function: _ } => (String::from("Call"), None, format!("Call {}", callee)),
+            Instruction::TailCall { function: _ } => (String::from("TailCall"), None, format!("Tail call {}", callee)),
+            Instruction::DefineGlobal { slot } => (String::from("DefineGlobal"), None, format!("Define global variable in slot {}", slot)),
+            Instruction::GetGlobal { slot } => (String::from("GetGlobal"), None, format!("Retrieve global variable in slot {}", slot)),
+        };
+
+        write!(out, ",\"op\":")?;
+        write_string(out, &op)?;
+        write!(out, ",\"code\":")?;
+        match code {
+            Some(code) => write!(out, "{}", code)?,
+            None => write!(out, "null")?,
+        }
+        write!(out, ",\"text\":")?;
+        write_string(out, &text)?;
+
+        write!(out, ",\"stack\":[")?;
+        for (idx, value) in record.stack.iter().rev().take(self.stack_values).enumerate() {
+            if idx > 0 {
+                write!(out, ",")?;
+            }
+            write_value(out, value)?;
+        }
+        writeln!(out, "]}}")
+    }
+}
+
+impl VmObserver for Tracer {
+    fn on_load(&mut self, function: &LoadedFunction) {
+        if function.index >= self.names.len() {
+            self.names.resize(function.index + 1, None);
+        }
+        self.names[function.index] = Some(Rc::from(function.function.name.internal().as_str()));
+    }
+
+    fn on_instruction(&mut self, function: Option<&LoadedFunction>, offset: usize, instruction: Instruction, stack: &[ThetaValue]) {
+        let callee = match instruction {
+            Instruction::Call { function } | Instruction::TailCall { function } => self.names.get(function).cloned().flatten(),
+            _ => None,
+        };
+
+        self.record(&TraceRecord {
+            function: function.map(|func| func.function.name.internal().as_str()),
+            offset,
+            line: function.and_then(|func| func.line_at(offset)),
+            instruction,
+            callee: callee.as_deref(),
+            stack,
+        });
+    }
+}
+
+fn write_value(out: &mut impl Write, value: &ThetaValue) -> std::io::Result<()> {
+    match value {
+        ThetaValue::Int(i) => write!(out, "{}", i),
+        // JSON has no representation for NaN or the infinities
+        ThetaValue::Double(d) if d.is_finite() => write!(out, "{:?}", d),
+        ThetaValue::Double(d) => write_string(out, &d.to_string()),
+        ThetaValue::Bool(b) => write!(out, "{}", b),
+        ThetaValue::Pointer(hv) => match hv.as_ref() {
+            ThetaHeapValue::Str(s) => write_string(out, s.internal()),
+        },
+    }
+}
+
+fn write_string(out: &mut impl Write, s: &str) -> std::io::Result<()> {
+    write!(out, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(out, "\\\"")?,
+            '\\' => write!(out, "\\\\")?,
+            '\n' => write!(out, "\\n")?,
+            '\r' => write!(out, "\\r")?,
+            '\t' => write!(out, "\\t")?,
+            c if c.is_control() => write!(out, "\\u{:04x}", c as u32)?,
+            c => write!(out, "{}", c)?,
+        }
+    }
+    write!(out, "\"")
+}